@@ -0,0 +1,172 @@
+//!
+//! Compare the insert throughput of the clickhouse writers
+//! Requires a clickhouse server listening on localhost:8123
+//!
+//! cargo bench --bench clickhouse_writer -- [rows]
+//!
+use std::time::Instant;
+
+use clickhouse::Client;
+use parser::{
+    configuration::{DataTopic, DataType},
+    output::{Fields, OUTPUT_DATE_FORMAT_UTC, OutputWriter, Tuple},
+    writer::{
+        clickhouse_config::{ClickhouseMode, create_database},
+        clickhouse_typed_writer::ClickhouseTypedWriter,
+        clickhouse_writer::{ClickhouseOptions, ClickhouseWriter},
+    },
+};
+use serde_json::json;
+use tokio::runtime::Runtime;
+
+const SERVER: &str = "localhost:8123";
+const TABLE: &str = "evtx";
+const DEFAULT_ROWS: usize = 1_000_000;
+
+fn main() {
+    let rows = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_ROWS);
+
+    let partial_field_def = vec![
+        ("System.EventID".to_owned(), DataType::Uint16),
+        ("System.EventRecordID".to_owned(), DataType::Uint64),
+        ("System.Channel".to_owned(), DataType::String),
+        ("System.TimeCreated".to_owned(), DataType::Date),
+        ("System.Execution.ProcessID".to_owned(), DataType::Int64),
+    ];
+    let topic = DataTopic::new(
+        TABLE.to_owned(),
+        TABLE.to_owned(),
+        partial_field_def.clone(),
+        "System.TimeCreated".to_owned(),
+    );
+
+    for mode in [ClickhouseMode::json, ClickhouseMode::typed_columns] {
+        let database = format!("bench_clickhouse_writer_{mode:?}");
+        create_tables(&database, &topic, mode);
+
+        let options = ClickhouseOptions::default();
+        let mut writer: Box<dyn OutputWriter> = match mode {
+            ClickhouseMode::json => Box::new(
+                ClickhouseWriter::new(
+                    SERVER,
+                    &None,
+                    &None,
+                    &database,
+                    TABLE,
+                    options.batch_policy(),
+                    options.spool(&database, TABLE, "bench", "bench", "jsonl"),
+                )
+                .unwrap(),
+            ),
+            ClickhouseMode::typed_columns => Box::new(
+                ClickhouseTypedWriter::new(
+                    SERVER,
+                    &None,
+                    &None,
+                    &database,
+                    TABLE,
+                    &partial_field_def,
+                    options.batch_policy(),
+                    options.spool(&database, TABLE, "bench", "bench", "rowbinary"),
+                )
+                .unwrap(),
+            ),
+        };
+
+        let fields = Fields::new("bench", "Security.evtx", "bench.7z", "Security.evtx");
+        let start = Instant::now();
+        for i in 0..rows {
+            writer.write(tuple(&fields, i)).unwrap();
+        }
+        writer.flush().unwrap();
+        // the rows are counted once the asynchronous inserts are written to the table
+        wait_for_rows(&database, rows);
+        let elapsed = start.elapsed();
+
+        println!(
+            "{mode:?}: {rows} rows in {:.2}s, {:.0} rows/s",
+            elapsed.as_secs_f64(),
+            rows as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
+
+fn client() -> Client {
+    Client::default()
+        .with_url(format!("http://{SERVER}"))
+        .with_option("enable_json_type", "1")
+}
+
+fn create_tables(database: &str, topic: &DataTopic, mode: ClickhouseMode) {
+    let client = client();
+    let runtime = Runtime::new().unwrap();
+    runtime.block_on(async {
+        client
+            .query(&format!("DROP DATABASE IF EXISTS {database}"))
+            .execute()
+            .await
+            .unwrap();
+        create_database(
+            &client,
+            database,
+            &vec![topic.clone()],
+            &None,
+            false,
+            "",
+            0,
+            mode,
+        )
+        .await
+        .unwrap();
+    });
+}
+
+///
+/// Flush the asynchronous insert queue and check that every row is in the table
+///
+fn wait_for_rows(database: &str, rows: usize) {
+    let client = client();
+    let runtime = Runtime::new().unwrap();
+    let count = runtime.block_on(async {
+        client
+            .query("SYSTEM FLUSH ASYNC INSERT QUEUE")
+            .execute()
+            .await
+            .unwrap();
+        client
+            .query(&format!("SELECT count() FROM {database}.{TABLE}"))
+            .fetch_one::<u64>()
+            .await
+            .unwrap()
+    });
+    assert_eq!(rows as u64, count, "rows missing in {database}.{TABLE}");
+}
+
+///
+/// An event shaped like the evtx events
+///
+fn tuple(fields: &Fields, i: usize) -> Tuple {
+    let date = chrono::DateTime::from_timestamp(1_700_000_000 + i as i64, 0).unwrap();
+    let data = json!({
+        "System": {
+            "EventID": 4624,
+            "EventRecordID": i,
+            "Channel": "Security",
+            "Computer": "WORKSTATION",
+            "TimeCreated": date.format(OUTPUT_DATE_FORMAT_UTC).to_string(),
+            "Execution": { "ProcessID": 668, "ThreadID": 4520 },
+        },
+        "EventData": {
+            "SubjectUserSid": "S-1-5-18",
+            "TargetUserName": format!("user{}", i % 100),
+            "LogonType": 3,
+            "IpAddress": "10.0.0.1",
+        },
+    });
+    let mut tuple = Tuple::new(fields);
+    tuple.set_data(data, Some(date.timestamp())).unwrap();
+    tuple
+}
@@ -7,14 +7,31 @@ use std::{
     time::{Duration, Instant},
 };
 
+use chrono::Utc;
 use flume::Sender;
 use log::{error, info, warn};
 
 use crate::{
     Error,
     configuration::{Configuration, ParserConfig, ParserType},
-    input::{csv::parse_csv, evtx::parse_evtx, hive::parse_hive, srum::SrumParser},
-    output::{Fields, OutputConfig},
+    input::{
+        csv::parse_csv,
+        evtx::parse_evtx,
+        hive::parse_hive,
+        hive_profiles::{UserProfiles, has_placeholder},
+        journald::parse_journald,
+        jsonl::parse_jsonl,
+        regex_log::parse_regex_log,
+        setupapi::parse_setupapi,
+        srum::SrumParser,
+        syslog::parse_syslog,
+        w3c::parse_w3c,
+        xml_records::parse_xml_records,
+    },
+    machine_identity::MachineIdentity,
+    output::{Fields, IdHashing, OutputConfig, Tuple},
+    provenance::{EvidenceManifest, FileEvidence, Provenance, manifest_tuple},
+    source_path::{PathLayout, SourcePaths, collect_files},
 };
 
 ///
@@ -26,6 +43,8 @@ pub struct ArchiveMsg {
     in_error: bool,
     is_temp_folder: bool,
     decompress_duration: Duration,
+    // the hash of the source archive, None for decompressed folders
+    source: Option<FileEvidence>,
 }
 
 ///
@@ -35,7 +54,8 @@ pub struct ParseMsg {
     file: PathBuf,
     config: ParserType,
     fields: Fields,
-    reply: Sender<Result<FileResultMsg, Error>>,
+    archive: Option<FileEvidence>,
+    reply: Sender<ParseResultMsg>,
 }
 
 ///
@@ -47,6 +67,15 @@ pub struct FileResultMsg {
     pub duration: Duration,
 }
 
+///
+/// Message sent by the parsing threads once a file is processed
+///
+pub struct ParseResultMsg {
+    pub result: Result<FileResultMsg, Error>,
+    // the evidence_manifest entry of the file, None when it could not be built
+    pub manifest: Option<Tuple>,
+}
+
 ///
 /// Message sent after a successful archive parsing
 ///
@@ -66,20 +95,35 @@ pub struct ArchiveResultMsg {
 /// Worflow for folders:
 ///     folder   --> archive management service --> file parsing service
 ///
-pub fn parse(configuration: Configuration) -> Result<(), Error> {
+pub fn parse(mut configuration: Configuration) -> Result<(), Error> {
+    //some outputs are prepared from the field definition of the topics
+    if configuration.output.iter().any(OutputConfig::needs_topics) {
+        let topics = configuration.list_topics()?;
+        for output in &mut configuration.output {
+            output.prepare(&configuration.client_context, &topics)?;
+        }
+    }
+
     //remove temp folder and recreate it
     let _ = fs::remove_dir_all(&configuration.temp_folder);
     fs::create_dir_all(&configuration.temp_folder)?;
     let (reply, receiver) = flume::unbounded::<ArchiveResultMsg>();
 
+    let provenance = Provenance::new(&configuration)?;
     let file_parsing_service = create_parsing_threads(
         thread_number_or_default(configuration.parsing_threads),
         &configuration.output,
         &configuration.client_context,
+        &provenance,
     );
     let archive_service = create_archive_threads(
         thread_number_or_default(configuration.archive_threads),
         &configuration.parsers,
+        configuration.id_hashing,
+        MachineIdentity::new(&configuration.machine_identity),
+        &configuration.path_layouts,
+        &configuration.output,
+        &configuration.client_context,
         file_parsing_service,
         reply,
     );
@@ -173,6 +217,7 @@ fn create_decompression_threads(
                         is_temp_folder: false,
                         in_error: false,
                         decompress_duration: Duration::from_secs(0),
+                        source: None,
                     };
                     if let Err(e) = archive_sender.send(archive_msg) {
                         error!("an error occured when sending decompressed archive: {e}");
@@ -185,6 +230,14 @@ fn create_decompression_threads(
                 output_path.push(file);
 
                 let instant = Instant::now();
+                let source =
+                    match FileEvidence::from_file(&input_path, &input_path.to_string_lossy()) {
+                        Ok(source) => Some(source),
+                        Err(e) => {
+                            error!("could not hash '{}': {e}", input_path.display());
+                            None
+                        }
+                    };
                 let command_output = Command::new("7z")
                     .arg("x")
                     .arg(input_path.to_str().unwrap())
@@ -198,6 +251,7 @@ fn create_decompression_threads(
                     is_temp_folder: true,
                     in_error: false,
                     decompress_duration: instant.elapsed(),
+                    source,
                 };
 
                 if !command_output.status.success() {
@@ -217,12 +271,21 @@ fn create_decompression_threads(
 
 ///
 /// Archive management threads:
-/// - read the content of the provideed folder and send the files to the parsing thread
-/// - upon completion delete the temporary folder if needed
+/// - resolve the computer name of the archive
+/// - read the content of the provideed folder and its sub folders and send the files to the parsing thread
+/// - translate the path of the files to their original path
+/// - name the root of the user hives after the user owning them
+/// - upon completion notify the outputs and delete the temporary folder if needed
 ///
+#[allow(clippy::too_many_arguments)]
 fn create_archive_threads(
     num_thread: usize,
     parsers: &[ParserConfig],
+    id_hashing: IdHashing,
+    machine_identity: MachineIdentity,
+    path_layouts: &[PathLayout],
+    output_configs: &[OutputConfig],
+    client_context: &str,
     file_parsing_sender: Sender<ParseMsg>,
     archive_reply: Sender<ArchiveResultMsg>,
 ) -> Sender<ArchiveMsg> {
@@ -234,6 +297,10 @@ fn create_archive_threads(
         let file_parsing_sender = file_parsing_sender.clone();
         let archive_reply = archive_reply.clone();
         let parsers = parsers.to_owned();
+        let machine_identity = machine_identity.clone();
+        let path_layouts = path_layouts.to_vec();
+        let output_configs = output_configs.to_vec();
+        let client_context = client_context.to_string();
         thread::spawn(move || {
             while let Ok(archive_msg) = archive_receiver.recv() {
                 let instant = Instant::now();
@@ -259,18 +326,46 @@ fn create_archive_threads(
                     continue;
                 }
 
+                let computer = machine_identity.resolve(&archive_name, &archive_msg.folder);
                 let mut num_errors = 0;
-                let (reply, receiver) = flume::unbounded::<Result<FileResultMsg, Error>>();
+                let (reply, receiver) = flume::unbounded::<ParseResultMsg>();
+
+                let source_paths = match SourcePaths::new(&path_layouts, &archive_msg.folder) {
+                    Ok(source_paths) => source_paths,
+                    Err(e) => {
+                        num_errors += 1;
+                        error!("Archive:'{archive_name}' collector layout error: {e}");
+                        SourcePaths::default()
+                    }
+                };
+
+                //iterate the folder and its sub folders to find file that match the file filters
+                //the files of an archive that cannot be listed are not parsed
+                let files = match collect_files(&archive_msg.folder) {
+                    Ok(files) => files,
+                    Err(e) => {
+                        num_errors += 1;
+                        error!("Archive:'{archive_name}' files cannot be listed: {e}");
+                        Vec::new()
+                    }
+                };
 
-                //iterate the folder to find file that match the file filters
-                let read_dir = fs::read_dir(&archive_msg.folder).unwrap();
-                for path in read_dir {
-                    let path = path.unwrap();
-                    let file_name = path.file_name().to_string_lossy().to_string();
+                //the user profiles are only needed to name the root of the user hives
+                let has_user_hive = parsers.iter().any(|parser| match &parser.parser {
+                    ParserType::hive { root_name } => has_placeholder(root_name),
+                    _ => false,
+                });
+                let profiles = if has_user_hive {
+                    UserProfiles::load(&files, &source_paths)
+                } else {
+                    UserProfiles::default()
+                };
+                for file in files {
+                    let file_name = &file.relative_path;
 
                     let mut found = false;
                     for parser in &parsers {
-                        if parser.file_filter.is_match(&file_name) {
+                        if parser.file_filter.is_match(&file.file_name) {
                             if found {
                                 num_errors += 1;
                                 error!(
@@ -278,17 +373,26 @@ fn create_archive_threads(
                                 )
                             } else {
                                 found = true;
-                                let fields = Fields::new(
-                                    &archive_name,
-                                    &file_name,
+                                let original_path = source_paths.original_path(&file.relative_path);
+                                let config = match &parser.parser {
+                                    ParserType::hive { root_name } => ParserType::hive {
+                                        root_name: profiles.root_name(root_name, &original_path),
+                                    },
+                                    config => config.clone(),
+                                };
+                                let mut fields = Fields::new(
+                                    &computer,
+                                    &original_path,
                                     &archive_name,
-                                    &file_name,
+                                    &file.flat_name(),
                                 );
+                                fields.id_hashing = id_hashing;
 
                                 let msg = ParseMsg {
-                                    file: path.path(),
-                                    config: parser.parser.clone(),
+                                    file: file.path.clone(),
+                                    config,
                                     fields,
+                                    archive: archive_msg.source.clone(),
                                     reply: reply.clone(),
                                 };
 
@@ -310,9 +414,19 @@ fn create_archive_threads(
                 //if not dropped, the receiver will never stop
                 drop(reply);
 
-                //wait for every files to be processed
-                while let Ok(result) = receiver.recv() {
-                    match result {
+                //wait for every files to be processed, their manifest entries are written in a single output
+                let mut manifest =
+                    EvidenceManifest::new(&output_configs, &client_context, &archive_name);
+                while let Ok(msg) = receiver.recv() {
+                    if let Some(entry) = msg.manifest
+                        && let Err(e) = manifest.write(entry)
+                    {
+                        num_errors += 1;
+                        error!(
+                            "Error while writing the evidence manifest of archive {archive_name}: {e}"
+                        )
+                    }
+                    match msg.result {
                         Ok(msg) => info!(
                             "Archive:'{archive_name}' file:'{}'. {} rows processed in {:.2?}",
                             msg.file, msg.num_rows, msg.duration
@@ -323,6 +437,20 @@ fn create_archive_threads(
                         }
                     }
                 }
+                if let Err(e) = manifest.complete() {
+                    num_errors += 1;
+                    error!(
+                        "Error while completing the evidence manifest of archive {archive_name}: {e}"
+                    )
+                }
+                for output_config in &output_configs {
+                    if let Err(e) =
+                        output_config.complete_archive(&client_context, &archive_name, num_errors)
+                    {
+                        num_errors += 1;
+                        error!("Error while completing archive {archive_name}: {e}")
+                    }
+                }
                 let result = ArchiveResultMsg {
                     folder: archive_msg
                         .folder
@@ -357,6 +485,7 @@ pub fn create_parsing_threads(
     num_thread: usize,
     output_configs: &[OutputConfig],
     client_context: &str,
+    provenance: &Provenance,
 ) -> Sender<ParseMsg> {
     let num_thread = thread_number_or_default(num_thread);
     let (sender, receiver) = flume::bounded::<ParseMsg>(num_thread);
@@ -365,10 +494,16 @@ pub fn create_parsing_threads(
         let receiver = receiver.clone();
         let output_configs = output_configs.to_vec();
         let client_context = client_context.to_string();
+        let provenance = provenance.clone();
 
         thread::spawn(move || {
-            while let Ok(parse_msg) = receiver.recv() {
-                let result = parse_file(&parse_msg, &output_configs, &client_context);
+            while let Ok(mut parse_msg) = receiver.recv() {
+                let result = parse_evidence(
+                    &mut parse_msg,
+                    &output_configs,
+                    &client_context,
+                    &provenance,
+                );
 
                 if let Err(e) = parse_msg.reply.send(result) {
                     error!("an error occured when sending parsing result: {e}");
@@ -379,6 +514,53 @@ pub fn create_parsing_threads(
     sender
 }
 
+///
+/// Hash the file, parse it and build its entry of the evidence manifest
+/// A manifest error is logged, it does not replace the result of the parsing
+///
+fn parse_evidence(
+    parse_msg: &mut ParseMsg,
+    output_config: &[OutputConfig],
+    client_context: &str,
+    provenance: &Provenance,
+) -> ParseResultMsg {
+    let processed_at = Utc::now();
+    let artifact = match FileEvidence::from_file(&parse_msg.file, &parse_msg.fields.original_file) {
+        Ok(artifact) => artifact,
+        Err(e) => {
+            return ParseResultMsg {
+                result: Err(e),
+                manifest: None,
+            };
+        }
+    };
+    parse_msg.fields.artifact_hash = artifact.sha256.clone();
+
+    let result = parse_file(parse_msg, output_config, client_context);
+    let (num_rows, error) = match &result {
+        Ok(msg) => (msg.num_rows, None),
+        Err(e) => (0, Some(e)),
+    };
+    let manifest = manifest_tuple(
+        &parse_msg.fields,
+        provenance,
+        &parse_msg.config,
+        processed_at,
+        parse_msg.archive.as_ref(),
+        &artifact,
+        num_rows,
+        error,
+    )
+    .inspect_err(|e| {
+        error!(
+            "Error while building the evidence manifest of file '{}': {e}",
+            parse_msg.fields.archive_file
+        )
+    })
+    .ok();
+    ParseResultMsg { result, manifest }
+}
+
 ///
 /// Parse a file and write result to the output
 /// upon success, returns the execution time
@@ -424,6 +606,70 @@ fn parse_file(
             let parser = SrumParser::new(&parse_msg.file)?;
             parser.parse_all_tables(client_context, &parse_msg.fields, output_config)?
         }
+        ParserType::syslog { year } => parse_syslog(
+            &parse_msg.file,
+            client_context,
+            &parse_msg.fields,
+            *year,
+            output_config,
+        )?,
+        ParserType::journald => parse_journald(
+            &parse_msg.file,
+            client_context,
+            &parse_msg.fields,
+            output_config,
+        )?,
+        ParserType::w3c => parse_w3c(
+            &parse_msg.file,
+            client_context,
+            &parse_msg.fields,
+            output_config,
+        )?,
+        ParserType::setupapi => parse_setupapi(
+            &parse_msg.file,
+            client_context,
+            &parse_msg.fields,
+            output_config,
+        )?,
+        ParserType::regex_log {
+            mapping_file,
+            best_effort,
+            skip_lines,
+        } => {
+            let best_effort = best_effort.unwrap_or(false);
+            let skip_lines = skip_lines.unwrap_or(0);
+            parse_regex_log(
+                &parse_msg.file,
+                client_context,
+                &parse_msg.fields,
+                output_config,
+                mapping_file,
+                best_effort,
+                skip_lines,
+            )?
+        }
+        ParserType::jsonl {
+            mapping_file,
+            best_effort,
+        } => parse_jsonl(
+            &parse_msg.file,
+            client_context,
+            &parse_msg.fields,
+            output_config,
+            mapping_file,
+            best_effort.unwrap_or(false),
+        )?,
+        ParserType::xml_records {
+            mapping_file,
+            best_effort,
+        } => parse_xml_records(
+            &parse_msg.file,
+            client_context,
+            &parse_msg.fields,
+            output_config,
+            mapping_file,
+            best_effort.unwrap_or(false),
+        )?,
     };
     Ok(FileResultMsg {
         file: parse_msg.fields.archive_file.to_owned(),
@@ -452,12 +698,75 @@ fn thread_number_or_default(thread_number: usize) -> usize {
 mod tests {
     use regex::Regex;
 
-    use crate::init_log;
+    use serde_json::{Map, Value};
+
+    use crate::{
+        init_log,
+        output::{FIELD_ARTIFACT_HASH, FIELD_PARSER_VERSION},
+        provenance::{EVIDENCE_MANIFEST_TABLE_NAME, PARSER_VERSION},
+        writer::{dead_letter::ErrorPolicy, file_writer::FileOptions},
+    };
 
     use super::*;
 
     const TEMP_FOLDER: &str = "data/temp";
 
+    ///
+    /// Count the data files and their size without the provenance fields, after checking them
+    /// Returns the manifest entries separately
+    ///
+    fn read_output(folder: &str, artifact_hash: &str) -> (usize, u64, Vec<Value>) {
+        let manifest_suffix = format!(".{EVIDENCE_MANIFEST_TABLE_NAME}.jsonl");
+        let mut len = 0;
+        let mut count = 0;
+        let mut manifest = Vec::new();
+        for path in fs::read_dir(folder).unwrap() {
+            let path = path.unwrap();
+            let content = fs::read_to_string(path.path()).unwrap();
+            if path
+                .file_name()
+                .to_string_lossy()
+                .ends_with(&manifest_suffix)
+            {
+                for line in content.lines() {
+                    manifest.push(serde_json::from_str(line).unwrap());
+                }
+                continue;
+            }
+            for line in content.lines() {
+                let mut row: Map<String, Value> = serde_json::from_str(line).unwrap();
+                assert_eq!(artifact_hash, row.remove(FIELD_ARTIFACT_HASH).unwrap());
+                assert_eq!(PARSER_VERSION, row.remove(FIELD_PARSER_VERSION).unwrap());
+                len += serde_json::to_string(&row).unwrap().len() as u64 + 1;
+            }
+            count += 1;
+        }
+        (count, len, manifest)
+    }
+
+    ///
+    /// The archive tests parse a single SRUM database, described by one manifest entry
+    ///
+    fn check_manifest(manifest: &[Value], artifact: &FileEvidence, archive: Option<&str>) {
+        assert_eq!(1, manifest.len());
+        let data = &manifest[0]["data"];
+        assert_eq!("srum", data["Parser"]);
+        assert_eq!(PARSER_VERSION, data["ParserVersion"]);
+        assert_eq!(artifact.sha256, data["ArtifactSha256"]);
+        assert_eq!(artifact.blake3, data["ArtifactBlake3"]);
+        assert_eq!(artifact.size, data["ArtifactSize"]);
+        assert!(data["Error"].is_null());
+        assert!(data["NumRows"].as_u64().unwrap() > 0);
+        match archive {
+            Some(archive) => {
+                let archive = FileEvidence::from_file(archive, "").unwrap();
+                assert_eq!(archive.sha256, data["ArchiveSha256"]);
+                assert_eq!(archive.size, data["ArchiveSize"]);
+            }
+            None => assert!(data["ArchiveSha256"].is_null()),
+        }
+    }
+
     #[test]
     fn decompress_service_error() {
         fs::create_dir_all(TEMP_FOLDER).unwrap();
@@ -518,13 +827,14 @@ mod tests {
     #[test]
     fn parsefile() {
         init_log();
-        let (reply, _) = flume::unbounded::<Result<FileResultMsg, Error>>();
+        let (reply, _) = flume::unbounded::<ParseResultMsg>();
 
         let fields = Fields::new("machine", "SRUMDB.dat", "SRUMDB", "SRUDB.dat");
         let parse_msg = ParseMsg {
             file: "data/parser/SRUDB.dat".into(),
             config: ParserType::srum,
             fields,
+            archive: None,
             reply: reply,
         };
 
@@ -533,17 +843,14 @@ mod tests {
         fs::create_dir_all(temp).unwrap();
         let conf = OutputConfig::file {
             folder: temp.to_string(),
+            options: FileOptions::default(),
+            errors: ErrorPolicy::default(),
         };
         parse_file(&parse_msg, &vec![conf], "client_name").unwrap();
 
-        let paths = fs::read_dir(format!("{temp}/SRUMDB")).unwrap();
-        let mut len = 0;
-        let mut count = 0;
-        for path in paths {
-            let path = path.unwrap();
-            len += fs::metadata(path.path()).unwrap().len();
-            count += 1;
-        }
+        // the artifact is only hashed when parsed from an archive
+        let (count, len, manifest) = read_output(&format!("{temp}/SRUMDB"), "");
+        assert!(manifest.is_empty());
         assert_eq!(10, count);
         assert_eq!(3038209, len);
         let _ = fs::remove_dir_all(temp);
@@ -564,6 +871,8 @@ mod tests {
         fs::create_dir_all(output).unwrap();
         let output_config = OutputConfig::file {
             folder: output.to_string(),
+            options: FileOptions::default(),
+            errors: ErrorPolicy::default(),
         };
 
         let configuration = Configuration {
@@ -576,18 +885,21 @@ mod tests {
             decompression_threads: 0,
             parsers: vec![parser_config],
             output: vec![output_config],
+            ..Default::default()
         };
 
         parse(configuration).unwrap();
 
-        let paths = fs::read_dir(format!("{output}/machine1_2025")).unwrap();
-        let mut len = 0;
-        let mut count = 0;
-        for path in paths {
-            let path = path.unwrap();
-            len += fs::metadata(path.path()).unwrap().len();
-            count += 1;
-        }
+        let artifact =
+            FileEvidence::from_file("data/archive/decompressed/machine1_2025/SRUDB.dat", "")
+                .unwrap();
+        let (count, len, manifest) =
+            read_output(&format!("{output}/machine1_2025"), &artifact.sha256);
+        check_manifest(
+            &manifest,
+            &artifact,
+            Some("data/archive/compressed/machine1_2025.7z"),
+        );
         let _ = fs::remove_dir_all(output);
         assert_eq!(10, count);
         assert_eq!(3066469, len);
@@ -608,6 +920,8 @@ mod tests {
         fs::create_dir_all(output).unwrap();
         let output_config = OutputConfig::file {
             folder: output.to_string(),
+            options: FileOptions::default(),
+            errors: ErrorPolicy::default(),
         };
 
         let configuration = Configuration {
@@ -620,18 +934,17 @@ mod tests {
             decompression_threads: 0,
             parsers: vec![parser_config],
             output: vec![output_config],
+            ..Default::default()
         };
 
         parse(configuration).unwrap();
 
-        let paths = fs::read_dir(format!("{output}/machine1_2025")).unwrap();
-        let mut len = 0;
-        let mut count = 0;
-        for path in paths {
-            let path = path.unwrap();
-            len += fs::metadata(path.path()).unwrap().len();
-            count += 1;
-        }
+        let artifact =
+            FileEvidence::from_file("data/archive/decompressed/machine1_2025/SRUDB.dat", "")
+                .unwrap();
+        let (count, len, manifest) =
+            read_output(&format!("{output}/machine1_2025"), &artifact.sha256);
+        check_manifest(&manifest, &artifact, None);
         // let _ = fs::remove_dir_all(output);
         assert_eq!(10, count);
         assert_eq!(3066469, len);
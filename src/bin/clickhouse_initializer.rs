@@ -4,7 +4,9 @@ use clickhouse::Client;
 use log::error;
 
 use parser::{
-    configuration::Configuration, errors::Error, writer::clickhouse_config::create_database,
+    configuration::Configuration,
+    errors::Error,
+    writer::clickhouse_config::{ClickhouseMode, create_database},
 };
 use std::{fs, io, path::Path, process::exit};
 
@@ -49,6 +51,11 @@ async fn run(args: CliArgs, password: &str) -> Result<(), Error> {
         args.with_kafka,
         &args.kafka_server.unwrap_or("set_me".to_owned()),
         args.kafka_consumers.unwrap_or(1),
+        if args.typed_columns {
+            ClickhouseMode::typed_columns
+        } else {
+            ClickhouseMode::json
+        },
     )
     .await?;
 
@@ -131,4 +138,9 @@ struct CliArgs {
     /// requires --with-kafka
     #[arg(short, long, verbatim_doc_comment)]
     kafka_consumers: Option<usize>,
+
+    /// Store the fields of the topic definitions as typed columns
+    /// Must match the mode of the clickhouse output, cannot be used with --with-kafka
+    #[arg(long, verbatim_doc_comment)]
+    typed_columns: bool,
 }
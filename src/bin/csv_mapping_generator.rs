@@ -0,0 +1,63 @@
+use std::{fs, path::Path, process::exit};
+
+use clap::Parser;
+use parser::{errors::Error, input::csv_mapping_generator::generate_mapping};
+
+const EXIT_FAILURE: i32 = 1;
+const DEFAULT_SAMPLE_ROWS: usize = 100;
+
+///
+/// Generate a csv mapping file from a sample csv file
+/// The generated file is a starting point that should be reviewed before use
+///
+fn main() {
+    let args: CliArgs = CliArgs::parse();
+    if let Err(e) = run(args) {
+        eprintln!("{e}");
+        exit(EXIT_FAILURE);
+    }
+}
+
+fn run(args: CliArgs) -> Result<(), Error> {
+    let topic = match args.topic {
+        Some(topic) => topic,
+        None => Path::new(&args.input)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_lowercase())
+            .unwrap_or_default(),
+    };
+    let generated = generate_mapping(
+        &args.input,
+        &topic,
+        args.rows.unwrap_or(DEFAULT_SAMPLE_ROWS),
+    )?;
+    let yaml = generated.to_yaml()?;
+    match args.output {
+        Some(output) => fs::write(output, yaml)?,
+        None => print!("{yaml}"),
+    }
+    Ok(())
+}
+
+///
+/// Generate a csv mapping file from a sample csv file
+///
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct CliArgs {
+    /// The sample csv file
+    #[arg(short, long, verbatim_doc_comment)]
+    input: String,
+
+    /// The mapping file to write, printed on the standard output if not set
+    #[arg(short, long, verbatim_doc_comment)]
+    output: Option<String>,
+
+    /// The topic name, defaults to the lowercase file name
+    #[arg(short, long, verbatim_doc_comment)]
+    topic: Option<String>,
+
+    /// Number of rows used to infer the field types
+    #[arg(short, long, verbatim_doc_comment)]
+    rows: Option<usize>,
+}
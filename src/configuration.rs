@@ -9,9 +9,22 @@ use crate::{
         csv_mapping::CsvMapping,
         evtx::{EVTX_SORT_FIELD, EVTX_TABLE_NAME, evtx_fields},
         hive::{HIVE_SORT_FIELD, HIVE_TABLE_NAME, hive_fields},
+        journald::{JOURNALD_SORT_FIELD, JOURNALD_TABLE_NAME, journald_fields},
+        parse_errors::{PARSE_ERRORS_TABLE_NAME, parse_errors_fields},
+        record_mapping::RecordMapping,
+        regex_log_mapping::RegexLogMapping,
+        setupapi::{SETUPAPI_SORT_FIELD, SETUPAPI_TABLE_NAME, setupapi_fields},
         srum_model::{SRUM_SORT_FIELD, srum_tables},
+        syslog::{SYSLOG_SORT_FIELD, SYSLOG_TABLE_NAME, syslog_fields},
+        w3c::{W3C_SORT_FIELD, W3C_TABLE_NAME, w3c_fields},
     },
-    output::{OutputConfig, full_topic_name},
+    machine_identity::IdentitySource,
+    output::{IdHashing, OutputConfig, full_topic_name},
+    provenance::{
+        EVIDENCE_MANIFEST_SORT_FIELD, EVIDENCE_MANIFEST_TABLE_NAME, evidence_manifest_fields,
+    },
+    source_path::PathLayout,
+    writer::dead_letter::{DEAD_LETTERS_TABLE_NAME, OnError, dead_letters_fields},
 };
 
 ///
@@ -31,6 +44,25 @@ pub enum ParserType {
         root_name: String,
     },
     srum,
+    syslog {
+        year: Option<i32>,
+    },
+    journald,
+    w3c,
+    setupapi,
+    regex_log {
+        mapping_file: String,
+        best_effort: Option<bool>,
+        skip_lines: Option<usize>,
+    },
+    jsonl {
+        mapping_file: String,
+        best_effort: Option<bool>,
+    },
+    xml_records {
+        mapping_file: String,
+        best_effort: Option<bool>,
+    },
 }
 
 ///
@@ -59,6 +91,12 @@ pub struct Configuration {
     pub parsers: Vec<ParserConfig>,
     #[serde(default)]
     pub output: Vec<OutputConfig>,
+    #[serde(default)]
+    pub id_hashing: IdHashing,
+    #[serde(default)]
+    pub machine_identity: Vec<IdentitySource>,
+    #[serde(default)]
+    pub path_layouts: Vec<PathLayout>,
 }
 impl Configuration {
     ///
@@ -88,6 +126,7 @@ impl Configuration {
 
     ///
     /// list topics
+    /// the parse_errors and evidence_manifest topics are added as soon as a parser is configured
     ///
     pub fn list_topics(&self) -> Result<Vec<DataTopic>, Error> {
         let mut list = Vec::new();
@@ -106,7 +145,7 @@ impl Configuration {
                     }
                     is_parsed.insert(name.to_owned());
                     let topic_name = full_topic_name(&self.client_context, name);
-                    let partial_field_def = mapping.partial_fields();
+                    let partial_field_def = mapping.partial_fields()?;
                     list.push(DataTopic::new(
                         topic_name,
                         name.to_owned(),
@@ -162,8 +201,132 @@ impl Configuration {
                         HIVE_SORT_FIELD.to_owned(),
                     ));
                 }
+                ParserType::syslog { year: _ } => {
+                    if is_parsed.contains(SYSLOG_TABLE_NAME) {
+                        continue;
+                    }
+                    is_parsed.insert(SYSLOG_TABLE_NAME.to_owned());
+                    let topic_name = full_topic_name(&self.client_context, SYSLOG_TABLE_NAME);
+                    list.push(DataTopic::new(
+                        topic_name,
+                        SYSLOG_TABLE_NAME.to_owned(),
+                        syslog_fields(),
+                        SYSLOG_SORT_FIELD.to_owned(),
+                    ));
+                }
+                ParserType::journald => {
+                    if is_parsed.contains(JOURNALD_TABLE_NAME) {
+                        continue;
+                    }
+                    is_parsed.insert(JOURNALD_TABLE_NAME.to_owned());
+                    let topic_name = full_topic_name(&self.client_context, JOURNALD_TABLE_NAME);
+                    list.push(DataTopic::new(
+                        topic_name,
+                        JOURNALD_TABLE_NAME.to_owned(),
+                        journald_fields(),
+                        JOURNALD_SORT_FIELD.to_owned(),
+                    ));
+                }
+                ParserType::w3c => {
+                    if is_parsed.contains(W3C_TABLE_NAME) {
+                        continue;
+                    }
+                    is_parsed.insert(W3C_TABLE_NAME.to_owned());
+                    let topic_name = full_topic_name(&self.client_context, W3C_TABLE_NAME);
+                    list.push(DataTopic::new(
+                        topic_name,
+                        W3C_TABLE_NAME.to_owned(),
+                        w3c_fields(),
+                        W3C_SORT_FIELD.to_owned(),
+                    ));
+                }
+                ParserType::setupapi => {
+                    if is_parsed.contains(SETUPAPI_TABLE_NAME) {
+                        continue;
+                    }
+                    is_parsed.insert(SETUPAPI_TABLE_NAME.to_owned());
+                    let topic_name = full_topic_name(&self.client_context, SETUPAPI_TABLE_NAME);
+                    list.push(DataTopic::new(
+                        topic_name,
+                        SETUPAPI_TABLE_NAME.to_owned(),
+                        setupapi_fields(),
+                        SETUPAPI_SORT_FIELD.to_owned(),
+                    ));
+                }
+                ParserType::regex_log {
+                    mapping_file: config_file,
+                    best_effort: _,
+                    skip_lines: _,
+                } => {
+                    let mapping = RegexLogMapping::load(config_file)?;
+                    let name = &mapping.topic;
+                    if is_parsed.contains(name) {
+                        continue;
+                    }
+                    is_parsed.insert(name.to_owned());
+                    let topic_name = full_topic_name(&self.client_context, name);
+                    let partial_field_def = mapping.partial_fields();
+                    list.push(DataTopic::new(
+                        topic_name,
+                        name.to_owned(),
+                        partial_field_def,
+                        mapping.sort_field.unwrap_or("".to_owned()),
+                    ));
+                }
+                ParserType::jsonl {
+                    mapping_file: config_file,
+                    best_effort: _,
+                }
+                | ParserType::xml_records {
+                    mapping_file: config_file,
+                    best_effort: _,
+                } => {
+                    let mapping = RecordMapping::load(config_file)?;
+                    let name = &mapping.topic;
+                    if is_parsed.contains(name) {
+                        continue;
+                    }
+                    is_parsed.insert(name.to_owned());
+                    let topic_name = full_topic_name(&self.client_context, name);
+                    let partial_field_def = mapping.partial_fields();
+                    list.push(DataTopic::new(
+                        topic_name,
+                        name.to_owned(),
+                        partial_field_def,
+                        mapping.sort_field.unwrap_or("".to_owned()),
+                    ));
+                }
             }
         }
+        if !self.parsers.is_empty() {
+            let topic_name = full_topic_name(&self.client_context, PARSE_ERRORS_TABLE_NAME);
+            list.push(DataTopic::new(
+                topic_name,
+                PARSE_ERRORS_TABLE_NAME.to_owned(),
+                parse_errors_fields(),
+                "".to_owned(),
+            ));
+            let topic_name = full_topic_name(&self.client_context, EVIDENCE_MANIFEST_TABLE_NAME);
+            list.push(DataTopic::new(
+                topic_name,
+                EVIDENCE_MANIFEST_TABLE_NAME.to_owned(),
+                evidence_manifest_fields(),
+                EVIDENCE_MANIFEST_SORT_FIELD.to_owned(),
+            ));
+        }
+        if self
+            .output
+            .iter()
+            .any(|output| output.error_policy().on_error == OnError::dlq)
+        {
+            let topic_name = full_topic_name(&self.client_context, DEAD_LETTERS_TABLE_NAME);
+            list.push(DataTopic::new(
+                topic_name,
+                DEAD_LETTERS_TABLE_NAME.to_owned(),
+                dead_letters_fields(),
+                "".to_owned(),
+            ));
+        }
         Ok(list)
     }
 }
@@ -206,8 +369,13 @@ pub enum DataType {
     Int64,
     Uint8,
     Uint16,
+    Uint64,
     Float,
     Boolean,
+    Uuid,
+    Ipv4,
+    Ipv6,
+    Array(Box<DataType>),
 }
 
 ///
@@ -224,7 +392,7 @@ pub struct ParserConfig {
 ///
 /// a deserialiser for the Regex struct
 ///
-fn regex_deserializer<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+pub(crate) fn regex_deserializer<'de, D>(deserializer: D) -> Result<Regex, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -235,7 +403,7 @@ where
 ///
 /// a serialiser for the Regex struct
 ///
-fn regex_serializer<S>(regex: &Regex, s: S) -> Result<S::Ok, S::Error>
+pub(crate) fn regex_serializer<S>(regex: &Regex, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
@@ -274,29 +442,178 @@ parsing_threads: 0
 # available parser:
 # - srum
 # - csv
+# - evtx
+# - hive: the root_name can be templated with {username} and {SID} for the user hives
+# - syslog: RFC3164/RFC5424 files such as auth.log, the optional year is the one of the last entry of the file
+# - journald: systemd journal export files (journalctl -o export)
+# - w3c: IIS/W3C extended log files
+# - setupapi: Windows setupapi.dev.log
+# - regex_log: any text log, the fields are extracted with a regex or a grok expression (see data/app_log.map.yaml)
+# - jsonl: one json object per line, such as Velociraptor or Hayabusa outputs (see data/hayabusa.map.yaml)
+# - xml_records: xml files, one record per element (see data/events_xml.map.yaml)
 parsers:
 - file_filter: SRUDB.*\.dat$
   parser: srum
 - file_filter: test.*\.csv$
   parser: !csv
     # csv column mapping requires an additional configuration file (see data/ntfs_info.map.yaml)
+    # skip_lines skips data rows after the header, use header_line in the mapping file to skip a preamble
     mapping_file: conf/test.yaml
     best_effort: true
     skip_lines: 0
+- file_filter: (?i)^NTUSER\.DAT$
+  parser: !hive
+    # {username} and {SID} are replaced by the user owning the hive
+    # the user is found in the original path of the file, its SID in the SOFTWARE hive of the archive
+    root_name: \HKU\{SID}
+- file_filter: (?i)^UsrClass\.dat$
+  parser: !hive
+    root_name: \HKU\{SID}_Classes
+- file_filter: auth\.log.*$
+  parser: !syslog
+    year: 2024
+- file_filter: u_ex.*\.log$
+  parser: w3c
+- file_filter: app.*\.log$
+  parser: !regex_log
+    mapping_file: conf/app_log.yaml
+    best_effort: true
+    skip_lines: 0
+- file_filter: .*\.jsonl$
+  parser: !jsonl
+    mapping_file: conf/hayabusa.yaml
+    best_effort: true
+# Algorithm used to compute the row ids, identical rows get the same id and are deduplicated
+# the id depends on the data, the computer name and the original path of the file,
+# they change with machine_identity and path_layouts: a re-imported archive may get new ids
+# - legacy: the default, concatenates the keys and the values
+# - canonical_v1: typed values and sorted keys, prevents false merges between different rows
+id_hashing: legacy
+
+# How the computer name is resolved, the sources are tried in order
+# the archive name is used when no source gives a name
+# - archive_regex: the 'computer' capture group, or the first group, of a regex applied to the archive name
+# - orc_config: the computer name found in the Config.xml or Statistics.json file of a DFIR ORC archive
+# - system_hive: the ComputerName key of the SYSTEM hive found in the archive
+machine_identity:
+- !archive_regex
+  regex: ^ORC_[^_]+_(?P<computer>[^_]+)_
+- orc_config
+- !system_hive
+  file_filter: ^SYSTEM$
+
+# How the original path of the files is rebuilt from the layout of the collector
+# the path relative to the archive root is used when no layout applies
+# - orc: the FullName of the files listed in the GetThis.csv files
+# - kape: C/Users/bob/NTUSER.DAT becomes C:\Users\bob\NTUSER.DAT
+# - velociraptor: uploads/auto/C%3A/Users/bob/NTUSER.DAT becomes C:\Users\bob\NTUSER.DAT
+# - custom: the 'drive' and 'path' capture groups of a regex applied to the relative path
+path_layouts:
+- orc
+- kape
+- velociraptor
+
 # configure the output
 output:
 - type: file
   folder: output
+# optional, compression: none (default), gzip, zstd
+# compression: zstd
+# optional, start a new numbered file (file.0001.jsonl.zst) when the uncompressed size or the number of rows is reached
+# max_file_size: 1073741824
+# max_file_rows: 1000000
+# optional, when the file exists: overwrite (default), append, fail
+# if_exists: overwrite
 - type: kafka
   params:
     bootstrap.servers: localhost:9092
     queue.buffering.max.ms: '100'
     queue.buffering.max.messages: '100000'
     compression.codec: lz4
+# optional, encoding of the messages: json (default), avro or protobuf
+# avro and protobuf use the Confluent wire format, the schemas are generated from the topic definitions
+# and registered in the schema registry under the '{client_context}_{topic}-value' subjects
+# format: avro
+# schema_registry: http://localhost:8081
+# registry_login:
+# registry_password:
+# optional, produce every parsed file in a transaction, committed when the file is completely parsed
+# and aborted on error: read_committed consumers see all the rows of a file or none
+# transactional: true
+# optional, maximum duration of a transaction in milliseconds, 900000 (15 minutes) by default
+# it must cover the parsing of the largest file and not exceed the transaction.max.timeout.ms of the brokers
+# transaction_timeout_ms: 900000
 - type: clickhouse
   server: localhost:8123
   login: default
 # password:
+# optional, layout of the tables created by clickhouse_initializer, must match its --typed-columns flag
+# json (default): every field is in the data column
+# typed_columns: the fields of the topic definitions are typed columns, inserted without json parsing
+# mode: typed_columns
+# optional, a batch is inserted when one of the limits is reached
+# max_batch_rows: 10000
+# max_batch_bytes: 10485760
+# max_batch_period_ms: 5000
+# optional, retries with an exponential backoff when the server cannot be reached
+# max_retries: 8
+# optional, the batches that could not be inserted are written in {spool_folder}/{database}/{table}/{archive}/{file}
+# spool_folder: spool
+# optional for every output, what to do with the rows it fails to write
+# fail (default): the file is not parsed
+# skip: the rows are dropped and counted in the log
+# dlq: the rows are written to the dead_letter output with the output name and the error
+#   in the 'dead_letters' topic, the dead_letter output must write json: file, kafka, http...
+# on_error: dlq
+# dead_letter:
+#   type: file
+#   folder: dead_letters
+# batches of tuples are posted to the url
+# format: ndjson (default) or splunk_hec, the splunk events time is the sort field of the topic
+# token: sent as 'Authorization: Bearer <token>', or 'Authorization: Splunk <token>' for splunk_hec
+# - type: http
+#   url: https://splunk:8088/services/collector/event
+#   format: splunk_hec
+# token:
+# headers:
+#   X-Splunk-Request-Channel: 00000000-0000-0000-0000-000000000000
+# gzip: true
+# max_batch_rows: 1000
+# max_batch_bytes: 5242880
+# max_retries: 5
+# documents are sent with the _bulk API, the tuple id is the document _id
+# index: {client_context} and {topic} are replaced, defaults to {client_context}_{topic}
+# install_templates: create the index templates from the topic definitions before the parsing
+# - type: opensearch
+#   url: https://localhost:9200
+#   login: admin
+# password:
+# index: '{client_context}_{topic}'
+# max_batch_rows: 5000
+# max_batch_bytes: 10485760
+# max_retries: 5
+# install_templates: false
+# the outputs are uploaded to an S3 compatible bucket with multipart uploads
+# keys: {prefix}/{client_context}/{archive}/{topic}/{file}.jsonl
+# a _manifest.json object listing the row count of every object is written once the archive is processed
+# format: jsonl (default) or parquet
+# region, access_key_id and secret_access_key default to the AWS_* environment variables
+# - type: s3
+#   bucket: dfir
+#   endpoint: http://localhost:9000
+# region: us-east-1
+# access_key_id:
+# secret_access_key:
+# prefix: collections
+# format: jsonl
+# part_size: 10485760
+# one parquet file per topic and archive, completed once the archive is processed: {folder}/{topic}/{archive}.parquet
+# the fields of the topic definition are typed columns, the other fields are stored in the json 'data' column
+# compression: uncompressed, snappy (default), gzip, lz4, zstd
+# - type: parquet
+#   folder: parquet
+#   row_group_size: 100000
+#   compression: zstd
 
 "#;
 
@@ -305,6 +622,11 @@ mod tests {
 
     use std::collections::HashMap;
 
+    use crate::writer::{
+        clickhouse_writer::ClickhouseOptions, dead_letter::ErrorPolicy, file_writer::FileOptions,
+        kafka_schema::KafkaOptions,
+    };
+
     use super::*;
 
     #[test]
@@ -355,6 +677,8 @@ mod tests {
         // "\\HKLM\\SAM"
         let file_output = OutputConfig::file {
             folder: "output".to_string(),
+            options: FileOptions::default(),
+            errors: ErrorPolicy::default(),
         };
 
         let mut params = HashMap::new();
@@ -365,12 +689,18 @@ mod tests {
             "100000".to_owned(),
         );
         params.insert("compression.codec".to_owned(), "lz4".to_owned());
-        let kafka_outptut = OutputConfig::kafka { params };
+        let kafka_outptut = OutputConfig::kafka {
+            params,
+            options: KafkaOptions::default(),
+            errors: ErrorPolicy::default(),
+        };
 
         let clickhouse_output = OutputConfig::clickhouse {
             server: "localhost:8123".to_owned(),
             login: Some("default".to_owned()),
             password: None,
+            options: ClickhouseOptions::default(),
+            errors: ErrorPolicy::default(),
         };
 
         let config = Configuration {
@@ -383,6 +713,7 @@ mod tests {
             input_is_decompressed: false,
             parsers: vec![srum_parser, csv_parser, hive_parser, evtx_parser],
             output: vec![file_output, kafka_outptut, clickhouse_output],
+            ..Default::default()
         };
 
         let serialized = serde_yml::to_string(&config).unwrap();
@@ -391,4 +722,36 @@ mod tests {
         let conf = serde_yml::from_str::<Configuration>(&serialized).unwrap();
         assert_eq!("test", conf.client_context);
     }
+
+    #[test]
+    fn dead_letters_topic() {
+        let file_output = |on_error| OutputConfig::file {
+            folder: "output".to_string(),
+            options: FileOptions::default(),
+            errors: ErrorPolicy {
+                on_error,
+                dead_letter: None,
+            },
+        };
+        let mut config = Configuration {
+            client_context: "test".to_string(),
+            output: vec![file_output(OnError::skip)],
+            ..Default::default()
+        };
+        let topics = config.list_topics().unwrap();
+        assert!(topics.is_empty());
+
+        config.output.push(file_output(OnError::dlq));
+        let topics = config.list_topics().unwrap();
+        assert_eq!(1, topics.len());
+        assert_eq!(DEAD_LETTERS_TABLE_NAME, topics[0].table_name);
+        assert_eq!(
+            full_topic_name("test", DEAD_LETTERS_TABLE_NAME),
+            topics[0].topic_name
+        );
+        assert_eq!(
+            dead_letters_fields().len(),
+            topics[0].partial_field_def.len()
+        );
+    }
 }
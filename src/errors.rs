@@ -13,6 +13,9 @@ pub enum Error {
     #[error(transparent)]
     EvtxError(#[from] evtx::err::EvtxError),
 
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
@@ -25,6 +28,15 @@ pub enum Error {
     #[error(transparent)]
     NtHive(#[from] nt_hive::NtHiveError),
 
+    #[error(transparent)]
+    ObjectStore(#[from] object_store::Error),
+
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    #[error(transparent)]
+    Arrow(#[from] arrow_schema::ArrowError),
+
     #[error(transparent)]
     ParseInt(#[from] std::num::ParseIntError),
 
@@ -34,6 +46,12 @@ pub enum Error {
     #[error(transparent)]
     ParseDate(#[from] chrono::ParseError),
 
+    #[error(transparent)]
+    ParseAddr(#[from] std::net::AddrParseError),
+
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+
     #[error(transparent)]
     SerdeJson(#[from] serde_json::Error),
 
@@ -52,6 +70,21 @@ pub enum Error {
     #[error("line{0}, column: {1} named: '{2}' parsing error:{3} ")]
     CsvParsing(usize, usize, String, String),
 
+    #[error("invalid value '{0}': {1}")]
+    InvalidValue(String, String),
+
+    #[error("line {0}, parsing error: {1}")]
+    LineParsing(usize, String),
+
+    #[error("Regex log configuration error: {0}")]
+    RegexLogConfiguration(String),
+
+    #[error("Record mapping configuration error: {0}")]
+    RecordConfiguration(String),
+
+    #[error(transparent)]
+    Xml(#[from] quick_xml::Error),
+
     #[error("data field already set")]
     DataField(),
 
@@ -67,9 +100,21 @@ pub enum Error {
     #[error("Kafka ressource error '{0}'")]
     KafkaRessource(String),
 
+    #[error("Schema registry error: {0}")]
+    SchemaRegistry(String),
+
     #[error("Some error occured while flushing the output")]
     OutputFlush(),
 
+    #[error("Output file '{0}' already exists")]
+    OutputFileExists(String),
+
+    #[error("OpenSearch error: {0}")]
+    OpenSearch(String),
+
+    #[error("HTTP output error: {0}")]
+    HttpOutput(String),
+
     #[error("Ordering field '{0}' for table '{1}' is not defined in the partial_field_definition")]
     OrderingFieldNotDefined(String, String),
 
@@ -86,4 +131,7 @@ pub enum Error {
         "for table:'{0}', sort field: {1}, is not defined the partial_field_def field definition"
     )]
     ClickhouseSortField(String, String),
+
+    #[error("{0} rows could not be inserted in table '{1}', they are written in the spool: {2}")]
+    ClickhouseSpooled(usize, String, String),
 }
@@ -1,21 +1,28 @@
 use std::{
     fs::File,
     hash::{DefaultHasher, Hash, Hasher},
+    io::{BufRead, BufReader},
+    net::{IpAddr, Ipv4Addr},
     path::Path,
     usize,
 };
 
 use crate::{
+    configuration::DataType,
     errors::Error,
     output::{Fields, OUTPUT_DATE_FORMAT_UTC, Output, OutputConfig, Tuple},
 };
 use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone, Utc};
 use csv::StringRecord;
-use log::error;
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value, json};
 
-use super::csv_mapping::CsvMapping;
+use super::{
+    csv_mapping::{CsvEncoding, CsvMapping},
+    csv_transform::ColumnPlan,
+    parse_errors::ParseErrors,
+};
 ///
 /// Alloue 10 Mo au buffer de lecture
 ///
@@ -39,14 +46,11 @@ where
 {
     let mapping = CsvMapping::load(mapping_path)?;
 
-    let mut reader: csv::Reader<_> = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .delimiter(mapping.csv_delimiter as u8)
-        .buffer_capacity(BUFFER_CAPACITY)
-        .from_path(path)?;
+    let mut reader = open_reader(path, &mapping)?;
 
-    let headers: &csv::StringRecord = reader.headers()?;
-    let (converters, sort_col) = create_converters(headers, &mapping);
+    let headers = source_headers(&mut reader, &mapping)?;
+    let plan = ColumnPlan::new(&headers, &mapping.transforms)?;
+    let (converters, sort_col) = create_converters(plan.headers(), &mapping);
 
     let mut output = Output::new(
         output_config,
@@ -56,55 +60,150 @@ where
         &mapping.topic,
     )?;
 
+    let mut errors = ParseErrors::new(
+        best_effort,
+        &mapping.topic,
+        fields,
+        client_context,
+        output_config,
+    );
+
     parse(
         reader,
+        &plan,
         &converters,
         sort_col,
+        mapping.csv_delimiter,
         fields,
         &mut output,
-        best_effort,
+        &mut errors,
         skip_lines,
     )?;
 
+    errors.complete()?;
+    output.complete()?;
     Ok(output.num_rows())
 }
 
 type SortColumn = usize;
 
+type CsvReader = csv::Reader<BufReader<DecodeReaderBytes<File, Vec<u8>>>>;
+
+///
+/// The file is transcoded to UTF-8 while being read
+/// The lines before the header are skipped, they do not have to be valid csv
+///
+fn open_reader<P: AsRef<Path>>(path: P, mapping: &CsvMapping) -> Result<CsvReader, Error> {
+    let mut decoder = BufReader::new(open_decoder(path, mapping.encoding)?);
+    let mut preamble = Vec::new();
+    for _ in 0..mapping.header_line {
+        preamble.clear();
+        decoder.read_until(b'\n', &mut preamble)?;
+    }
+
+    let reader = csv::ReaderBuilder::new()
+        .has_headers(mapping.columns.is_none())
+        .delimiter(mapping.csv_delimiter as u8)
+        .buffer_capacity(BUFFER_CAPACITY)
+        .from_reader(decoder);
+    Ok(reader)
+}
+
+///
+/// The column names, read from the file or defined by the mapping for header-less files
+///
+fn source_headers(reader: &mut CsvReader, mapping: &CsvMapping) -> Result<StringRecord, Error> {
+    match &mapping.columns {
+        Some(columns) => Ok(StringRecord::from(columns.clone())),
+        None => Ok(reader.headers()?.clone()),
+    }
+}
+
+///
+/// Files concatenated after a log rotation contain the header several times
+///
+fn is_header(record: &StringRecord, header: &StringRecord) -> bool {
+    record.len() == header.len()
+        && record
+            .iter()
+            .zip(header.iter())
+            .all(|(value, name)| value.trim_start_matches('\u{feff}') == name)
+}
+
+///
+/// Open a file, transcoding it to UTF-8
+/// The encoding is detected from the BOM and defaults to the provided one, or UTF-8
+///
+pub fn open_decoder<P: AsRef<Path>>(
+    path: P,
+    encoding: Option<CsvEncoding>,
+) -> Result<DecodeReaderBytes<File, Vec<u8>>, Error> {
+    let file = File::open(path)?;
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(encoding.map(|encoding| encoding.encoding()))
+        .bom_override(true)
+        .strip_bom(true)
+        .build(file);
+    Ok(decoder)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn parse(
-    mut csv_reader: csv::Reader<File>,
+    mut csv_reader: CsvReader,
+    plan: &ColumnPlan,
     converters: &[Converter],
     sort_column: SortColumn,
+    delimiter: char,
     fields: &Fields,
     output: &mut Output,
-    best_effort: bool,
+    errors: &mut ParseErrors,
     skip_lines: usize,
 ) -> Result<(), Error> {
+    let header = if csv_reader.has_headers() {
+        Some(csv_reader.headers()?.clone())
+    } else {
+        None
+    };
     for (line_nb, line) in csv_reader.records().enumerate() {
         if line_nb < skip_lines {
             continue;
         }
+        let record = match line {
+            Ok(record) => record,
+            Err(err) => {
+                errors.reject(err.into(), line_nb, "")?;
+                continue;
+            }
+        };
+        if let Some(header) = &header
+            && is_header(&record, header)
+        {
+            continue;
+        }
 
-        let json_line = convert_line(&converters, line, sort_column, line_nb);
-        match json_line {
+        match convert_line(converters, &plan.apply(&record), sort_column, line_nb) {
             Ok((data, sort_data)) => {
                 let mut tuple = Tuple::new(fields);
                 tuple.set_data(data, sort_data)?;
                 output.write(tuple)?;
             }
-            Err(err) => {
-                if best_effort {
-                    error!("Skipping Line {line_nb} caused by error: {err}");
-                } else {
-                    return Err(err);
-                }
-            }
+            Err(err) => errors.reject(err, line_nb, &raw_content(&record, delimiter))?,
         }
     }
 
     Ok(())
 }
 
+///
+/// Rebuild the line from the record, to store the rejected content
+///
+fn raw_content(record: &StringRecord, delimiter: char) -> String {
+    record
+        .iter()
+        .collect::<Vec<&str>>()
+        .join(&delimiter.to_string())
+}
+
 ///
 /// the first line of the csv is parsed to associate field names with the ones defined in the mapping
 ///
@@ -122,32 +221,7 @@ fn create_converters(
                 sort_pos = pos;
             }
         }
-        let converter = match field {
-            Some(field_type) => match field_type {
-                FieldType::String { mandatory }
-                | FieldType::Integer { mandatory }
-                | FieldType::Float { mandatory } => Converter {
-                    field_name: field_name.to_owned(),
-                    mandatory: *mandatory,
-                    field_type: field_type.clone(),
-                    ..Default::default()
-                },
-                FieldType::Date {
-                    mandatory,
-                    input_date_format: _,
-                } => Converter {
-                    field_name: field_name.to_owned(),
-                    mandatory: *mandatory,
-                    field_type: field_type.clone(),
-                    default_date_format: mapping.default_date_format.clone(),
-                },
-            },
-            None => Converter {
-                field_name: field_name.to_owned(),
-                ..Default::default()
-            },
-        };
-
+        let converter = Converter::new(field_name, field, &mapping.default_date_format);
         converters.push(converter);
     }
     (converters, sort_pos)
@@ -158,15 +232,19 @@ fn create_converters(
 ///
 fn convert_line(
     converters: &[Converter],
-    record: Result<StringRecord, csv::Error>,
+    record: &StringRecord,
     sort_column: usize,
     line_num: usize,
 ) -> Result<(Value, Option<i64>), Error> {
-    let record = record?;
     let mut map: Map<String, Value> = Map::with_capacity(record.len());
     let mut sort_data = None;
     for (column, data) in record.iter().enumerate() {
-        let conv = &converters[column];
+        let Some(conv) = converters.get(column) else {
+            return Err(Error::LineParsing(
+                line_num,
+                format!("unexpected column {column}"),
+            ));
+        };
         if data.is_empty() {
             if conv.mandatory {
                 return Err(Error::CsvMandatoryField(
@@ -179,7 +257,10 @@ fn convert_line(
             continue;
         }
         if sort_column == column {
-            sort_data = Some(conv.to_sort_data(data)?);
+            let value = conv.to_sort_data(data).map_err(|e| {
+                Error::CsvParsing(line_num, column, conv.field_name.clone(), e.to_string())
+            })?;
+            sort_data = Some(value);
         }
         match conv.to_json_value(data) {
             Ok(value) => {
@@ -210,6 +291,31 @@ pub enum DateFormat {
     Pattern(String, bool),
 }
 
+///
+/// Unit of the unix epoch timestamps
+///
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub enum EpochUnit {
+    #[default]
+    Seconds,
+    Milliseconds,
+    Microseconds,
+}
+
+const DEFAULT_TRUE_VALUES: [&str; 5] = ["true", "1", "yes", "y", "on"];
+const DEFAULT_FALSE_VALUES: [&str; 5] = ["false", "0", "no", "n", "off"];
+const DEFAULT_ARRAY_DELIMITER: char = ';';
+
+///
+/// Seconds between 1601-01-01 (FILETIME origin) and 1970-01-01
+///
+const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+///
+/// Seconds between 1899-12-30 (OLE automation date origin) and 1970-01-01
+///
+const OLE_EPOCH_OFFSET: f64 = 2_209_161_600.0;
+
 ///
 /// liste les formats de sortie possibles
 ///
@@ -233,20 +339,153 @@ pub enum FieldType {
         mandatory: bool,
         input_date_format: Option<DateFormat>,
     },
+    // tokens are compared case insensitively
+    Boolean {
+        #[serde(default)]
+        mandatory: bool,
+        true_values: Option<Vec<String>>,
+        false_values: Option<Vec<String>>,
+    },
+    // hexadecimal integer, with or without the 0x prefix
+    HexInteger {
+        #[serde(default)]
+        mandatory: bool,
+    },
+    // octal integer, with or without the 0o prefix
+    OctalInteger {
+        #[serde(default)]
+        mandatory: bool,
+    },
+    // unsigned 64 bits integer
+    Unsigned {
+        #[serde(default)]
+        mandatory: bool,
+    },
+    // unix timestamp
+    Epoch {
+        #[serde(default)]
+        mandatory: bool,
+        #[serde(default)]
+        unit: EpochUnit,
+    },
+    // windows FILETIME, decimal or hexadecimal with the 0x prefix
+    FileTime {
+        #[serde(default)]
+        mandatory: bool,
+    },
+    // OLE automation date: days since 1899-12-30
+    OleDate {
+        #[serde(default)]
+        mandatory: bool,
+    },
+    // written lowercase without braces
+    Guid {
+        #[serde(default)]
+        mandatory: bool,
+    },
+    Ipv4 {
+        #[serde(default)]
+        mandatory: bool,
+    },
+    // IPv4 addresses are written as IPv4-mapped IPv6 addresses
+    Ipv6 {
+        #[serde(default)]
+        mandatory: bool,
+    },
+    // values split on the delimiter, each item is converted with the item_type (String by default)
+    Array {
+        #[serde(default)]
+        mandatory: bool,
+        delimiter: Option<char>,
+        item_type: Option<Box<FieldType>>,
+    },
 }
 impl Default for FieldType {
     fn default() -> Self {
         Self::String { mandatory: false }
     }
 }
+impl FieldType {
+    ///
+    /// the type used in the topic definition
+    ///
+    pub fn data_type(&self) -> DataType {
+        match self {
+            FieldType::String { .. } => DataType::String,
+            FieldType::Integer { .. } => DataType::Int64,
+            FieldType::Float { .. } => DataType::Float,
+            FieldType::Date { .. }
+            | FieldType::Epoch { .. }
+            | FieldType::FileTime { .. }
+            | FieldType::OleDate { .. } => DataType::Date,
+            FieldType::Boolean { .. } => DataType::Boolean,
+            FieldType::HexInteger { .. }
+            | FieldType::OctalInteger { .. }
+            | FieldType::Unsigned { .. } => DataType::Uint64,
+            FieldType::Guid { .. } => DataType::Uuid,
+            FieldType::Ipv4 { .. } => DataType::Ipv4,
+            FieldType::Ipv6 { .. } => DataType::Ipv6,
+            FieldType::Array { item_type, .. } => DataType::Array(Box::new(
+                item_type
+                    .as_ref()
+                    .map(|item_type| item_type.data_type())
+                    .unwrap_or(DataType::String),
+            )),
+        }
+    }
+
+    pub fn is_mandatory(&self) -> bool {
+        match self {
+            FieldType::String { mandatory }
+            | FieldType::Integer { mandatory }
+            | FieldType::Float { mandatory }
+            | FieldType::Date { mandatory, .. }
+            | FieldType::Boolean { mandatory, .. }
+            | FieldType::HexInteger { mandatory }
+            | FieldType::OctalInteger { mandatory }
+            | FieldType::Unsigned { mandatory }
+            | FieldType::Epoch { mandatory, .. }
+            | FieldType::FileTime { mandatory }
+            | FieldType::OleDate { mandatory }
+            | FieldType::Guid { mandatory }
+            | FieldType::Ipv4 { mandatory }
+            | FieldType::Ipv6 { mandatory }
+            | FieldType::Array { mandatory, .. } => *mandatory,
+        }
+    }
+}
+
+///
+/// Convert a text value to its json representation
+/// Fields without definition are converted to String
+///
 #[derive(Default)]
-struct Converter {
+pub struct Converter {
     pub field_name: String,
     pub mandatory: bool,
     field_type: FieldType,
     default_date_format: DateFormat,
 }
 impl Converter {
+    pub fn new(
+        field_name: &str,
+        field_type: Option<&FieldType>,
+        default_date_format: &DateFormat,
+    ) -> Self {
+        match field_type {
+            Some(field_type) => Converter {
+                field_name: field_name.to_owned(),
+                mandatory: field_type.is_mandatory(),
+                field_type: field_type.clone(),
+                default_date_format: default_date_format.clone(),
+            },
+            None => Converter {
+                field_name: field_name.to_owned(),
+                ..Default::default()
+            },
+        }
+    }
+
     pub fn to_json_value(&self, value: &str) -> Result<serde_json::Value, Error> {
         match &self.field_type {
             FieldType::String { .. } => Ok(json!(value)),
@@ -268,12 +507,67 @@ impl Converter {
                 let value = Self::convert_date(value, input_date_format)?;
                 Ok(json!(value))
             }
+            FieldType::Boolean {
+                mandatory: _,
+                true_values,
+                false_values,
+            } => {
+                let value = Self::convert_bool(value, true_values, false_values)?;
+                Ok(json!(value))
+            }
+            FieldType::HexInteger { .. }
+            | FieldType::OctalInteger { .. }
+            | FieldType::Unsigned { .. } => {
+                let value = self.convert_unsigned(value)?;
+                Ok(json!(value))
+            }
+            FieldType::Epoch { .. } | FieldType::FileTime { .. } | FieldType::OleDate { .. } => {
+                let date = self.convert_date_time(value)?;
+                Ok(json!(date.format(OUTPUT_DATE_FORMAT_UTC).to_string()))
+            }
+            FieldType::Guid { .. } => {
+                let value = Self::convert_guid(value)?;
+                Ok(json!(value))
+            }
+            FieldType::Ipv4 { .. } => {
+                let value: Ipv4Addr = value.trim().parse()?;
+                Ok(json!(value.to_string()))
+            }
+            FieldType::Ipv6 { .. } => {
+                let value = match value.trim().parse::<IpAddr>()? {
+                    IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                    IpAddr::V6(ip) => ip,
+                };
+                Ok(json!(value.to_string()))
+            }
+            FieldType::Array {
+                mandatory: _,
+                delimiter,
+                item_type,
+            } => {
+                let converter = Converter::new(
+                    &self.field_name,
+                    item_type.as_deref(),
+                    &self.default_date_format,
+                );
+                let values = value
+                    .split(delimiter.unwrap_or(DEFAULT_ARRAY_DELIMITER))
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| converter.to_json_value(item))
+                    .collect::<Result<Vec<Value>, Error>>()?;
+                Ok(Value::Array(values))
+            }
         }
     }
 
     pub fn to_sort_data(&self, value: &str) -> Result<i64, Error> {
         match &self.field_type {
-            FieldType::String { .. } => {
+            FieldType::String { .. }
+            | FieldType::Guid { .. }
+            | FieldType::Ipv4 { .. }
+            | FieldType::Ipv6 { .. }
+            | FieldType::Array { .. } => {
                 let mut hasher = DefaultHasher::new();
                 value.hash(&mut hasher);
                 let hash = hasher.finish();
@@ -297,9 +591,134 @@ impl Converter {
                 let value = Self::convert_to_timestamp(value, input_date_format)?;
                 Ok(value)
             }
+            FieldType::Boolean {
+                mandatory: _,
+                true_values,
+                false_values,
+            } => {
+                let value = Self::convert_bool(value, true_values, false_values)?;
+                Ok(value as i64)
+            }
+            FieldType::HexInteger { .. }
+            | FieldType::OctalInteger { .. }
+            | FieldType::Unsigned { .. } => {
+                let value = self.convert_unsigned(value)?;
+                Ok(value as i64)
+            }
+            FieldType::Epoch { .. } | FieldType::FileTime { .. } | FieldType::OleDate { .. } => {
+                let date = self.convert_date_time(value)?;
+                Ok(date.timestamp_millis())
+            }
         }
     }
 
+    fn convert_bool(
+        value: &str,
+        true_values: &Option<Vec<String>>,
+        false_values: &Option<Vec<String>>,
+    ) -> Result<bool, Error> {
+        let value = value.trim();
+        let is_in = |values: &Option<Vec<String>>, defaults: &[&str]| match values {
+            Some(values) => values.iter().any(|v| v.eq_ignore_ascii_case(value)),
+            None => defaults.iter().any(|v| v.eq_ignore_ascii_case(value)),
+        };
+        if is_in(true_values, &DEFAULT_TRUE_VALUES) {
+            Ok(true)
+        } else if is_in(false_values, &DEFAULT_FALSE_VALUES) {
+            Ok(false)
+        } else {
+            Err(Error::InvalidValue(
+                value.to_owned(),
+                "not a boolean".to_owned(),
+            ))
+        }
+    }
+
+    fn convert_unsigned(&self, value: &str) -> Result<u64, Error> {
+        let value = value.trim();
+        let value = match &self.field_type {
+            FieldType::HexInteger { .. } => {
+                let digits = value
+                    .strip_prefix("0x")
+                    .or_else(|| value.strip_prefix("0X"))
+                    .unwrap_or(value);
+                u64::from_str_radix(digits, 16)?
+            }
+            FieldType::OctalInteger { .. } => {
+                let digits = value
+                    .strip_prefix("0o")
+                    .or_else(|| value.strip_prefix("0O"))
+                    .unwrap_or(value);
+                u64::from_str_radix(digits, 8)?
+            }
+            _ => value.parse()?,
+        };
+        Ok(value)
+    }
+
+    fn convert_date_time(&self, value: &str) -> Result<DateTime<Utc>, Error> {
+        let value = value.trim();
+        let date = match &self.field_type {
+            FieldType::Epoch { mandatory: _, unit } => {
+                let timestamp: i64 = value.parse()?;
+                match unit {
+                    EpochUnit::Seconds => DateTime::from_timestamp(timestamp, 0),
+                    EpochUnit::Milliseconds => DateTime::from_timestamp_millis(timestamp),
+                    EpochUnit::Microseconds => DateTime::from_timestamp_micros(timestamp),
+                }
+            }
+            FieldType::FileTime { .. } => {
+                // a FILETIME is unsigned, negative values are rejected by the parsing
+                let filetime: u64 = match value
+                    .strip_prefix("0x")
+                    .or_else(|| value.strip_prefix("0X"))
+                {
+                    Some(digits) => u64::from_str_radix(digits, 16)?,
+                    None => value.parse()?,
+                };
+                DateTime::from_timestamp(
+                    (filetime / 10_000_000) as i64 - FILETIME_EPOCH_OFFSET,
+                    (filetime % 10_000_000) as u32 * 100,
+                )
+            }
+            FieldType::OleDate { .. } => {
+                let days: f64 = value.parse()?;
+                let millis = (days * 86_400_000.0 - OLE_EPOCH_OFFSET * 1000.0).round();
+                DateTime::from_timestamp_millis(millis as i64)
+            }
+            _ => None,
+        };
+        date.ok_or(Error::InvalidValue(
+            value.to_owned(),
+            "date out of range".to_owned(),
+        ))
+    }
+
+    fn convert_guid(value: &str) -> Result<String, Error> {
+        let digits: String = value
+            .trim()
+            .trim_start_matches('{')
+            .trim_end_matches('}')
+            .chars()
+            .filter(|c| *c != '-')
+            .collect();
+        if digits.len() != 32 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::InvalidValue(
+                value.to_owned(),
+                "not a GUID".to_owned(),
+            ));
+        }
+        let digits = digits.to_ascii_lowercase();
+        Ok(format!(
+            "{}-{}-{}-{}-{}",
+            &digits[0..8],
+            &digits[8..12],
+            &digits[12..16],
+            &digits[16..20],
+            &digits[20..32]
+        ))
+    }
+
     fn convert_date(value: &str, input_date_format: &DateFormat) -> Result<String, Error> {
         let date = match input_date_format {
             DateFormat::Rfc2822 => {
@@ -488,6 +907,178 @@ mod tests {
         converter.to_json_value(value).expect_err("Invalid Date");
     }
 
+    #[test]
+    fn boolean() {
+        let converter = Converter {
+            field_type: FieldType::Boolean {
+                mandatory: false,
+                true_values: None,
+                false_values: None,
+            },
+            ..Default::default()
+        };
+        assert_eq!(json!(true), converter.to_json_value("Yes").unwrap());
+        assert_eq!(json!(false), converter.to_json_value("0").unwrap());
+        converter
+            .to_json_value("maybe")
+            .expect_err("Invalid Boolean");
+
+        let converter = Converter {
+            field_type: FieldType::Boolean {
+                mandatory: false,
+                true_values: Some(vec!["Y".to_owned()]),
+                false_values: Some(vec!["N".to_owned()]),
+            },
+            ..Default::default()
+        };
+        assert_eq!(json!(true), converter.to_json_value("y").unwrap());
+        assert_eq!(1, converter.to_sort_data("Y").unwrap());
+        converter
+            .to_json_value("true")
+            .expect_err("Invalid Boolean");
+    }
+
+    #[test]
+    fn unsigned() {
+        let converter = Converter {
+            field_type: FieldType::HexInteger { mandatory: false },
+            ..Default::default()
+        };
+        let res = converter.to_json_value("0xC2E23C53E23C4E43").unwrap();
+        assert_eq!(res.as_u64().unwrap(), 0xC2E23C53E23C4E43);
+        assert_eq!(json!(255), converter.to_json_value("ff").unwrap());
+        converter.to_json_value("0xZZ").expect_err("Invalid Hex");
+
+        let converter = Converter {
+            field_type: FieldType::OctalInteger { mandatory: false },
+            ..Default::default()
+        };
+        assert_eq!(json!(493), converter.to_json_value("0755").unwrap());
+        converter.to_json_value("8").expect_err("Invalid Octal");
+
+        let converter = Converter {
+            field_type: FieldType::Unsigned { mandatory: false },
+            ..Default::default()
+        };
+        assert_eq!(
+            json!(u64::MAX),
+            converter.to_json_value("18446744073709551615").unwrap()
+        );
+        converter.to_json_value("-1").expect_err("Invalid Unsigned");
+    }
+
+    #[test]
+    fn timestamps() {
+        let converter = Converter {
+            field_type: FieldType::Epoch {
+                mandatory: false,
+                unit: EpochUnit::Seconds,
+            },
+            ..Default::default()
+        };
+        let res = converter.to_json_value("1609556645").unwrap();
+        assert_eq!(res.as_str().unwrap(), "2021-01-02 03:04:05.000");
+        assert_eq!(1609556645000, converter.to_sort_data("1609556645").unwrap());
+
+        let converter = Converter {
+            field_type: FieldType::Epoch {
+                mandatory: false,
+                unit: EpochUnit::Microseconds,
+            },
+            ..Default::default()
+        };
+        let res = converter.to_json_value("1609556645123456").unwrap();
+        assert_eq!(res.as_str().unwrap(), "2021-01-02 03:04:05.123");
+
+        let converter = Converter {
+            field_type: FieldType::FileTime { mandatory: false },
+            ..Default::default()
+        };
+        let res = converter.to_json_value("132540302451230000").unwrap();
+        assert_eq!(res.as_str().unwrap(), "2021-01-02 03:04:05.123");
+        let res = converter.to_json_value("0x1d6e0b3ee074530").unwrap();
+        assert_eq!(res.as_str().unwrap(), "2021-01-02 03:04:05.123");
+        converter
+            .to_json_value("-1")
+            .expect_err("Negative FileTime");
+        converter
+            .to_json_value("-132540302451230000")
+            .expect_err("Negative FileTime");
+
+        let converter = Converter {
+            field_type: FieldType::OleDate { mandatory: false },
+            ..Default::default()
+        };
+        let res = converter.to_json_value("44198.5").unwrap();
+        assert_eq!(res.as_str().unwrap(), "2021-01-02 12:00:00.000");
+        converter.to_json_value("ABCD").expect_err("Invalid Date");
+    }
+
+    #[test]
+    fn guid_and_ip() {
+        let converter = Converter {
+            field_type: FieldType::Guid { mandatory: false },
+            ..Default::default()
+        };
+        let res = converter
+            .to_json_value("{54849625-5478-4994-A5BA-3E3B0328C30D}")
+            .unwrap();
+        assert_eq!(
+            res.as_str().unwrap(),
+            "54849625-5478-4994-a5ba-3e3b0328c30d"
+        );
+        converter.to_json_value("{5484}").expect_err("Invalid Guid");
+
+        let converter = Converter {
+            field_type: FieldType::Ipv4 { mandatory: false },
+            ..Default::default()
+        };
+        assert_eq!(
+            json!("10.0.0.2"),
+            converter.to_json_value("10.0.0.2").unwrap()
+        );
+        converter
+            .to_json_value("fe80::1")
+            .expect_err("Invalid IPv4");
+
+        let converter = Converter {
+            field_type: FieldType::Ipv6 { mandatory: false },
+            ..Default::default()
+        };
+        assert_eq!(
+            json!("fe80::1"),
+            converter.to_json_value("FE80::1").unwrap()
+        );
+        assert_eq!(
+            json!("::ffff:10.0.0.2"),
+            converter.to_json_value("10.0.0.2").unwrap()
+        );
+    }
+
+    #[test]
+    fn array() {
+        let converter = Converter {
+            field_type: FieldType::Array {
+                mandatory: false,
+                delimiter: Some('|'),
+                item_type: Some(Box::new(FieldType::Integer { mandatory: false })),
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            json!([1, 2, 3]),
+            converter.to_json_value("1| 2|3|").unwrap()
+        );
+        converter.to_json_value("1|a").expect_err("Invalid Integer");
+
+        let field_type: FieldType =
+            serde_yml::from_str("type: Array\nitem_type:\n  type: Ipv4").unwrap();
+        assert!(matches!(
+            field_type.data_type(),
+            DataType::Array(item) if matches!(*item, DataType::Ipv4)
+        ));
+    }
+
     pub const DATA_PATH: &str = "data/parser/NTFSInfo.csv";
     pub const DATA_ERRORS_PATH: &str = "data/parser/NTFSError.csv";
     pub const MAPPING_PATH: &str = "data/ntfs_info.map.yaml";
@@ -506,24 +1097,22 @@ mod tests {
 
         let mapping = CsvMapping::load(MAPPING_PATH).unwrap();
 
-        let mut reader: csv::Reader<_> = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .delimiter(mapping.csv_delimiter as u8)
-            .buffer_capacity(BUFFER_CAPACITY)
-            .from_path(DATA_PATH)
-            .unwrap();
+        let mut reader = open_reader(DATA_PATH, &mapping).unwrap();
 
         let headers: &csv::StringRecord = reader.headers().unwrap();
-        let (converters, sort_col) = create_converters(headers, &mapping);
+        let plan = ColumnPlan::new(headers, &mapping.transforms).unwrap();
+        let (converters, sort_col) = create_converters(plan.headers(), &mapping);
 
         let now = Instant::now();
         parse(
             reader,
+            &plan,
             &converters,
             sort_col,
+            mapping.csv_delimiter,
             &fields,
             &mut output,
-            false,
+            &mut ParseErrors::new(false, &mapping.topic, &fields, "", &[]),
             0,
         )
         .unwrap();
@@ -556,24 +1145,22 @@ mod tests {
 
         let mapping = CsvMapping::load(MAPPING_PATH).unwrap();
 
-        let mut reader: csv::Reader<_> = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .delimiter(mapping.csv_delimiter as u8)
-            .buffer_capacity(BUFFER_CAPACITY)
-            .from_path(DATA_PATH)
-            .unwrap();
+        let mut reader = open_reader(DATA_PATH, &mapping).unwrap();
 
         let headers: &csv::StringRecord = reader.headers().unwrap();
-        let (converters, sort_col) = create_converters(headers, &mapping);
+        let plan = ColumnPlan::new(headers, &mapping.transforms).unwrap();
+        let (converters, sort_col) = create_converters(plan.headers(), &mapping);
 
         let now = Instant::now();
         parse(
             reader,
+            &plan,
             &converters,
             sort_col,
+            mapping.csv_delimiter,
             &fields,
             &mut output,
-            false,
+            &mut ParseErrors::new(false, &mapping.topic, &fields, "", &[]),
             1,
         )
         .unwrap();
@@ -603,23 +1190,21 @@ mod tests {
 
         let mapping = CsvMapping::load(MAPPING_PATH).unwrap();
 
-        let mut reader: csv::Reader<_> = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .delimiter(mapping.csv_delimiter as u8)
-            .buffer_capacity(BUFFER_CAPACITY)
-            .from_path(DATA_ERRORS_PATH)
-            .unwrap();
+        let mut reader = open_reader(DATA_ERRORS_PATH, &mapping).unwrap();
 
         let headers: &csv::StringRecord = reader.headers().unwrap();
-        let (converters, sort_col) = create_converters(headers, &mapping);
+        let plan = ColumnPlan::new(headers, &mapping.transforms).unwrap();
+        let (converters, sort_col) = create_converters(plan.headers(), &mapping);
 
         parse(
             reader,
+            &plan,
             &converters,
             sort_col,
+            mapping.csv_delimiter,
             &fields,
             &mut output,
-            false,
+            &mut ParseErrors::new(false, &mapping.topic, &fields, "", &[]),
             0,
         )
         .expect_err("msg");
@@ -639,20 +1224,49 @@ mod tests {
 
         let mapping = CsvMapping::load(MAPPING_PATH).unwrap();
 
-        let mut reader: csv::Reader<_> = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .delimiter(mapping.csv_delimiter as u8)
-            .buffer_capacity(BUFFER_CAPACITY)
-            .from_path(DATA_ERRORS_PATH)
-            .unwrap();
+        let mut reader = open_reader(DATA_ERRORS_PATH, &mapping).unwrap();
 
         let headers: &csv::StringRecord = reader.headers().unwrap();
-        let (converters, sort_col) = create_converters(headers, &mapping);
+        let plan = ColumnPlan::new(headers, &mapping.transforms).unwrap();
+        let (converters, sort_col) = create_converters(plan.headers(), &mapping);
+
+        let error_output = MemoryWriter::new(10);
+        let error_buffer = error_output.get_buffer();
+        let error_output = Output {
+            list: vec![Box::new(error_output)],
+            num_rows: 0,
+        };
+        let mut errors = ParseErrors::with_output(true, &mapping.topic, &fields, error_output);
 
         let now = Instant::now();
-        parse(reader, &converters, sort_col, &fields, &mut output, true, 0).unwrap();
+        parse(
+            reader,
+            &plan,
+            &converters,
+            sort_col,
+            mapping.csv_delimiter,
+            &fields,
+            &mut output,
+            &mut errors,
+            0,
+        )
+        .unwrap();
         println!("Parse {} rows in {:.2?}", output.num_rows(), now.elapsed());
 
+        //the invalid line is written to the parse_errors topic
+        assert_eq!(1, errors.num_errors());
+        let json: serde_json::Value = serde_json::from_str(&error_buffer.borrow()[0]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!(0, data["Line"]);
+        assert_eq!("CsvParsing", data["Kind"]);
+        assert_eq!("CreationDate", data["ColumnName"]);
+        assert!(
+            data["RawContent"]
+                .as_str()
+                .unwrap()
+                .contains(",ERROR_NOT_DATE,")
+        );
+
         let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[0]).unwrap();
         let object = json.as_object().unwrap();
         let data = object.get("data").unwrap();
@@ -663,4 +1277,235 @@ mod tests {
             r#"{"ComputerName":"VM-WIN10-DEV","VolumeID":"0xC2E23C53E23C4E43","File":"$Bitmap","ParentName":"\\","SizeInBytes":1950016,"Attributes":"....HN....S..","CreationDate":"2016-01-22 03:08:51.337","LastModificationDate":"2016-01-22 03:08:51.337","LastAccessDate":"2016-01-22 03:08:51.337","LastAttrChangeDate":"2016-01-22 03:08:51.337","FileNameCreationDate":"2016-01-22 03:08:51.337","FileNameLastModificationDate":"2016-01-22 03:08:51.337","FileNameLastAccessDate":"2016-01-22 03:08:51.337","FileNameLastAttrModificationDate":"2016-01-22 03:08:51.337","USN":"0x0000000000000000","FRN":"0x0006000000000006","ParentFRN":"0x0005000000000005","FilenameID":2,"DataID":4,"RecordInUse":"Y","OwnerId":0,"FilenameFlags":3,"SecDescrID":256,"FilenameIndex":0,"DataIndex":0,"SnapshotID":"{00000000-0000-0000-0000-000000000000}"}"#
         );
     }
+
+    pub const DATA_UTF16_PATH: &str = "data/parser/NTFSInfo_utf16.csv";
+    pub const DATA_CP1252_PATH: &str = "data/parser/NTFSInfo_cp1252.csv";
+
+    #[test]
+    fn parse_encodings() {
+        init_log();
+        let fields = Fields::new("mymachine", "NTFSINFO", "mymachine_ORC.7z", "NTFSINFO.csv");
+
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+
+        // UTF-16LE detected from the BOM, even if another encoding is configured
+        let mut mapping = CsvMapping::load(MAPPING_PATH).unwrap();
+        mapping.encoding = Some(CsvEncoding::Windows1252);
+        let mut reader = open_reader(DATA_UTF16_PATH, &mapping).unwrap();
+        let headers: &csv::StringRecord = reader.headers().unwrap();
+        assert_eq!("ComputerName", &headers[0]);
+        let plan = ColumnPlan::new(headers, &mapping.transforms).unwrap();
+        let (converters, sort_col) = create_converters(plan.headers(), &mapping);
+        parse(
+            reader,
+            &plan,
+            &converters,
+            sort_col,
+            mapping.csv_delimiter,
+            &fields,
+            &mut output,
+            &mut ParseErrors::new(false, &mapping.topic, &fields, "", &[]),
+            0,
+        )
+        .unwrap();
+        assert_eq!(2, output.num_rows());
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[1]).unwrap();
+        assert_eq!("$Bitmap", json["data"]["File"]);
+
+        // Windows-1252 without BOM
+        let mut reader = open_reader(DATA_CP1252_PATH, &mapping).unwrap();
+        let headers: &csv::StringRecord = reader.headers().unwrap();
+        let plan = ColumnPlan::new(headers, &mapping.transforms).unwrap();
+        let (converters, sort_col) = create_converters(plan.headers(), &mapping);
+        parse(
+            reader,
+            &plan,
+            &converters,
+            sort_col,
+            mapping.csv_delimiter,
+            &fields,
+            &mut output,
+            &mut ParseErrors::new(false, &mapping.topic, &fields, "", &[]),
+            0,
+        )
+        .unwrap();
+        assert_eq!(4, output.num_rows());
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[3]).unwrap();
+        assert_eq!("Résumé.txt", json["data"]["File"]);
+
+        // the same file read as UTF-8 is invalid
+        mapping.encoding = None;
+        let mut reader = open_reader(DATA_CP1252_PATH, &mapping).unwrap();
+        let headers: &csv::StringRecord = reader.headers().unwrap();
+        let plan = ColumnPlan::new(headers, &mapping.transforms).unwrap();
+        let (converters, sort_col) = create_converters(plan.headers(), &mapping);
+        parse(
+            reader,
+            &plan,
+            &converters,
+            sort_col,
+            mapping.csv_delimiter,
+            &fields,
+            &mut output,
+            &mut ParseErrors::new(false, &mapping.topic, &fields, "", &[]),
+            0,
+        )
+        .expect_err("invalid UTF-8");
+    }
+
+    #[test]
+    fn parse_transforms() {
+        init_log();
+        let fields = Fields::new("mymachine", "NTFSINFO", "mymachine_ORC.7z", "NTFSINFO.csv");
+
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+
+        let mut mapping = CsvMapping::load(MAPPING_PATH).unwrap();
+        mapping.transforms = serde_yml::from_str(
+            r#"
+- !Concat { fields: [ParentName, File], separator: '\', to: FullPath }
+- !NormalizePath { fields: [FullPath] }
+- !Lowercase { fields: [FullPath] }
+- !Rename { from: SizeInBytes, to: Size }
+- !Drop { fields: [ComputerName, VolumeID] }
+"#,
+        )
+        .unwrap();
+        mapping
+            .fields
+            .insert("Size".to_owned(), FieldType::Unsigned { mandatory: false });
+
+        let partial_fields = mapping.partial_fields().unwrap();
+        assert!(partial_fields.iter().any(|(name, _)| name == "FullPath"));
+        assert!(
+            partial_fields
+                .iter()
+                .any(|(name, data_type)| name == "Size" && matches!(data_type, DataType::Uint64))
+        );
+        assert!(
+            !partial_fields
+                .iter()
+                .any(|(name, _)| name == "ComputerName")
+        );
+
+        let mut reader = open_reader(DATA_PATH, &mapping).unwrap();
+        let headers: &csv::StringRecord = reader.headers().unwrap();
+        let plan = ColumnPlan::new(headers, &mapping.transforms).unwrap();
+        let (converters, sort_col) = create_converters(plan.headers(), &mapping);
+        parse(
+            reader,
+            &plan,
+            &converters,
+            sort_col,
+            mapping.csv_delimiter,
+            &fields,
+            &mut output,
+            &mut ParseErrors::new(false, &mapping.topic, &fields, "", &[]),
+            0,
+        )
+        .unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[1]).unwrap();
+        let data = &json["data"];
+        assert_eq!("\\$bitmap", data["FullPath"]);
+        assert_eq!(1950016, data["Size"]);
+        assert!(data.get("ComputerName").is_none());
+        assert!(data.get("SizeInBytes").is_none());
+    }
+
+    pub const DATA_ROTATED_PATH: &str = "data/parser/rotated.csv";
+    pub const DATA_NO_HEADER_PATH: &str = "data/parser/no_header.csv";
+
+    fn audit_mapping() -> CsvMapping {
+        let mut mapping = CsvMapping {
+            topic: "audit".to_owned(),
+            sort_field: Some("Time".to_owned()),
+            default_date_format: DateFormat::Pattern("%Y-%m-%d %H:%M:%S".to_owned(), false),
+            ..Default::default()
+        };
+        mapping.fields.insert(
+            "Time".to_owned(),
+            FieldType::Date {
+                mandatory: true,
+                input_date_format: None,
+            },
+        );
+        mapping
+    }
+
+    fn parse_audit(path: &str, mapping: &CsvMapping) -> Vec<String> {
+        let fields = Fields::new("mymachine", "audit", "mymachine_ORC.7z", "audit.csv");
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+
+        let mut reader = open_reader(path, mapping).unwrap();
+        let headers = source_headers(&mut reader, mapping).unwrap();
+        let plan = ColumnPlan::new(&headers, &mapping.transforms).unwrap();
+        let (converters, sort_col) = create_converters(plan.headers(), mapping);
+        parse(
+            reader,
+            &plan,
+            &converters,
+            sort_col,
+            mapping.csv_delimiter,
+            &fields,
+            &mut output,
+            &mut ParseErrors::new(false, &mapping.topic, &fields, "", &[]),
+            0,
+        )
+        .unwrap();
+        buffer.borrow().clone()
+    }
+
+    #[test]
+    fn parse_preamble_and_repeated_header() {
+        init_log();
+        let mapping = CsvMapping {
+            header_line: 2,
+            ..audit_mapping()
+        };
+        let rows = parse_audit(DATA_ROTATED_PATH, &mapping);
+        assert_eq!(3, rows.len());
+
+        let json: serde_json::Value = serde_json::from_str(&rows[2]).unwrap();
+        assert_eq!(
+            r#"{"Time":"2024-01-03 08:00:00.000","User":"alice","Action":"login"}"#,
+            serde_json::to_string(&json["data"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_positional() {
+        init_log();
+        let mapping = CsvMapping {
+            columns: Some(vec![
+                "Time".to_owned(),
+                "User".to_owned(),
+                "Action".to_owned(),
+            ]),
+            ..audit_mapping()
+        };
+        let rows = parse_audit(DATA_NO_HEADER_PATH, &mapping);
+        assert_eq!(2, rows.len());
+
+        let json: serde_json::Value = serde_json::from_str(&rows[1]).unwrap();
+        assert_eq!("bob", json["data"]["User"]);
+        assert_eq!("2024-01-02 10:05:00.000", json["data"]["Time"]);
+    }
 }
@@ -1,13 +1,41 @@
 use std::{collections::HashMap, fs, path::Path};
 
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
 use serde::{Deserialize, Serialize};
 
 use crate::{configuration::DataType, errors::Error};
 
-use super::csv::{DateFormat, FieldType};
+use super::{
+    csv::{DateFormat, FieldType},
+    csv_transform::Transform,
+};
 
 const DEFAULT_DELIMITER: char = ',';
 
+///
+/// Encodings supported for the csv files
+/// A BOM found at the start of the file always takes precedence
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum CsvEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+    // decoded as Windows-1252, as mandated by the WHATWG encoding standard
+    Latin1,
+}
+impl CsvEncoding {
+    pub fn encoding(&self) -> &'static Encoding {
+        match self {
+            CsvEncoding::Utf8 => UTF_8,
+            CsvEncoding::Utf16Le => UTF_16LE,
+            CsvEncoding::Utf16Be => UTF_16BE,
+            CsvEncoding::Windows1252 | CsvEncoding::Latin1 => WINDOWS_1252,
+        }
+    }
+}
+
 ///
 /// Defines convertion for each field
 /// If a field is not configured, the default value will be used
@@ -24,6 +52,18 @@ pub struct CsvMapping {
     #[serde(default)]
     pub csv_delimiter: char,
 
+    // Header-less files: the column names, by position
+    #[serde(default)]
+    pub columns: Option<Vec<String>>,
+
+    // Number of lines before the header, or before the first row of header-less files
+    #[serde(default)]
+    pub header_line: usize,
+
+    // File encoding, when not set the encoding is detected from the BOM and defaults to UTF-8
+    #[serde(default)]
+    pub encoding: Option<CsvEncoding>,
+
     //Default date format
     #[serde(default)]
     pub default_date_format: DateFormat,
@@ -31,6 +71,10 @@ pub struct CsvMapping {
     //mapping for each field
     #[serde(default)]
     pub fields: HashMap<String, FieldType>,
+
+    //transformations applied to the columns before the type conversion
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
 }
 impl Default for CsvMapping {
     fn default() -> Self {
@@ -38,8 +82,12 @@ impl Default for CsvMapping {
             topic: "".to_owned(),
             sort_field: None,
             csv_delimiter: DEFAULT_DELIMITER,
+            columns: None,
+            header_line: 0,
+            encoding: None,
             default_date_format: DateFormat::Rfc3339,
             fields: Default::default(),
+            transforms: Default::default(),
         }
     }
 }
@@ -56,17 +104,26 @@ impl CsvMapping {
         Ok(conf)
     }
 
-    pub fn partial_fields(&self) -> Vec<(String, DataType)> {
-        let mut res = Vec::with_capacity(self.fields.len());
-        for (name, field_type) in &self.fields {
-            match field_type {
-                FieldType::String { .. } => res.push((name.to_owned(), DataType::String)),
-                FieldType::Integer { .. } => res.push((name.to_owned(), DataType::Int64)),
-                FieldType::Float { .. } => res.push((name.to_owned(), DataType::Float)),
-                FieldType::Date { .. } => res.push((name.to_owned(), DataType::String)),
-            }
+    ///
+    /// The declared fields that survive the transformations, and the columns created by them
+    /// Created columns without declared type are Strings
+    ///
+    pub fn partial_fields(&self) -> Result<Vec<(String, DataType)>, Error> {
+        let mut names: Vec<String> = self.fields.keys().cloned().collect();
+        for transform in &self.transforms {
+            transform.apply_to_names(&mut names)?;
         }
-        res
+        Ok(names
+            .into_iter()
+            .map(|name| {
+                let data_type = self
+                    .fields
+                    .get(&name)
+                    .map(|field_type| field_type.data_type())
+                    .unwrap_or(DataType::String);
+                (name, data_type)
+            })
+            .collect())
     }
 }
 
@@ -0,0 +1,339 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::errors::Error;
+
+use super::{
+    csv::{Converter, DateFormat, FieldType, open_decoder},
+    csv_mapping::CsvMapping,
+};
+
+///
+/// Delimiters tried, in order of preference
+///
+const CANDIDATE_DELIMITERS: [char; 4] = [',', ';', '\t', '|'];
+
+///
+/// Date formats tried, in order of preference
+///
+fn candidate_date_formats() -> Vec<DateFormat> {
+    vec![
+        DateFormat::Pattern("%Y-%m-%d %H:%M:%S.%3f".to_owned(), false),
+        DateFormat::Pattern("%Y-%m-%d %H:%M:%S".to_owned(), false),
+        DateFormat::Rfc3339,
+        DateFormat::Pattern("%Y-%m-%dT%H:%M:%S%.f".to_owned(), false),
+        DateFormat::Pattern("%Y-%m-%d %H:%M:%S%.f %z".to_owned(), true),
+        DateFormat::Pattern("%Y/%m/%d %H:%M:%S%.f".to_owned(), false),
+        DateFormat::Pattern("%m/%d/%Y %H:%M:%S".to_owned(), false),
+        DateFormat::Pattern("%m/%d/%Y %I:%M:%S %p".to_owned(), false),
+        DateFormat::Pattern("%d/%m/%Y %H:%M:%S".to_owned(), false),
+        DateFormat::Rfc2822,
+    ]
+}
+
+///
+/// A mapping infered from the first rows of a csv file
+/// The columns are kept in the file order to write a readable mapping file
+///
+pub struct GeneratedMapping {
+    pub mapping: CsvMapping,
+    pub columns: Vec<String>,
+}
+
+///
+/// Read the header and the first rows of a csv file to infer a mapping:
+/// - the delimiter is the candidate giving the same number of columns (greater than one) on every row
+/// - a column is an Integer, Float or Date if every non empty sample can be converted
+/// - the most common date format becomes the default one
+/// - the sort field is the first Date column without empty values
+///
+pub fn generate_mapping<P: AsRef<Path>>(
+    path: P,
+    topic: &str,
+    sample_rows: usize,
+) -> Result<GeneratedMapping, Error> {
+    let sample = read_sample(path, sample_rows)?;
+    let delimiter = detect_delimiter(&sample);
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .delimiter(delimiter as u8)
+        .from_reader(sample.as_bytes());
+    let columns: Vec<String> = reader.headers()?.iter().map(str::to_owned).collect();
+    let mut values: Vec<Vec<String>> = vec![Vec::new(); columns.len()];
+    let mut has_empty = vec![false; columns.len()];
+    for record in reader.records() {
+        // the last line of the sample can be truncated
+        let Ok(record) = record else {
+            break;
+        };
+        for (pos, value) in record.iter().enumerate().take(columns.len()) {
+            if value.is_empty() {
+                has_empty[pos] = true;
+            } else {
+                values[pos].push(value.to_owned());
+            }
+        }
+    }
+
+    let inferred: Vec<Inferred> = values.iter().map(|samples| infer(samples)).collect();
+    let default_date_format = most_common_date_format(&inferred);
+
+    let mut fields = HashMap::with_capacity(columns.len());
+    let mut sort_field = None;
+    for (pos, (name, inferred)) in columns.iter().zip(inferred).enumerate() {
+        let field_type = match inferred {
+            Inferred::String => FieldType::String { mandatory: false },
+            Inferred::Integer => FieldType::Integer { mandatory: false },
+            Inferred::Float => FieldType::Float { mandatory: false },
+            Inferred::Date(date_format) => {
+                if sort_field.is_none() && !has_empty[pos] {
+                    sort_field = Some(name.to_owned());
+                }
+                let input_date_format = if same_format(&date_format, &default_date_format) {
+                    None
+                } else {
+                    Some(date_format)
+                };
+                FieldType::Date {
+                    mandatory: false,
+                    input_date_format,
+                }
+            }
+        };
+        fields.insert(name.to_owned(), field_type);
+    }
+
+    Ok(GeneratedMapping {
+        mapping: CsvMapping {
+            topic: topic.to_owned(),
+            sort_field,
+            csv_delimiter: delimiter,
+            default_date_format,
+            fields,
+            ..Default::default()
+        },
+        columns,
+    })
+}
+
+impl GeneratedMapping {
+    ///
+    /// Write the mapping in the style of the provided mapping files
+    ///
+    pub fn to_yaml(&self) -> Result<String, Error> {
+        let mapping = &self.mapping;
+        let mut yaml = String::new();
+        yaml.push_str(&format!("topic: {}\n", to_yaml_value(&mapping.topic)?));
+        if let Some(sort_field) = &mapping.sort_field {
+            yaml.push_str(&format!("sort_field: {}\n", to_yaml_value(sort_field)?));
+        }
+        yaml.push_str(&format!(
+            "csv_delimiter: {}\n",
+            to_yaml_value(&mapping.csv_delimiter)?
+        ));
+        yaml.push_str(&format!(
+            "default_date_format: {}\n",
+            to_yaml_value(&mapping.default_date_format)?
+        ));
+        yaml.push_str("\n#\n# field definition\n#\nfields:\n");
+        for name in &self.columns {
+            let Some(field_type) = mapping.fields.get(name) else {
+                continue;
+            };
+            yaml.push_str(&format!("  {}:\n", to_yaml_value(name)?));
+            let (type_name, input_date_format) = match field_type {
+                FieldType::Integer { .. } => ("Integer", None),
+                FieldType::Float { .. } => ("Float", None),
+                FieldType::Date {
+                    input_date_format, ..
+                } => ("Date", input_date_format.as_ref()),
+                _ => ("String", None),
+            };
+            yaml.push_str(&format!("    type: {type_name}\n"));
+            if let Some(date_format) = input_date_format {
+                let date_format = to_yaml_value(date_format)?.replace('\n', "\n    ");
+                yaml.push_str(&format!("    input_date_format: {date_format}\n"));
+            }
+            yaml.push('\n');
+        }
+        Ok(yaml)
+    }
+}
+
+fn to_yaml_value<T: serde::Serialize>(value: &T) -> Result<String, Error> {
+    let yaml = serde_yml::to_string(value).map_err(|e| Error::CsvConfiguration(e.to_string()))?;
+    Ok(yaml.trim_end().to_owned())
+}
+
+fn read_sample<P: AsRef<Path>>(path: P, sample_rows: usize) -> Result<String, Error> {
+    let reader = BufReader::new(open_decoder(path, None)?);
+    let mut sample = String::new();
+    // the header and the sample rows
+    for line in reader.lines().take(sample_rows + 1) {
+        sample.push_str(&line?);
+        sample.push('\n');
+    }
+    Ok(sample)
+}
+
+fn detect_delimiter(sample: &str) -> char {
+    let mut best = (CANDIDATE_DELIMITERS[0], 1);
+    for delimiter in CANDIDATE_DELIMITERS {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(delimiter as u8)
+            .from_reader(sample.as_bytes());
+        let counts: Vec<usize> = reader
+            .records()
+            .map_while(Result::ok)
+            .map(|record| record.len())
+            .collect();
+        let Some(first) = counts.first() else {
+            continue;
+        };
+        if *first > best.1 && counts.iter().all(|count| count == first) {
+            best = (delimiter, *first);
+        }
+    }
+    best.0
+}
+
+enum Inferred {
+    String,
+    Integer,
+    Float,
+    Date(DateFormat),
+}
+
+fn infer(samples: &[String]) -> Inferred {
+    if samples.is_empty() {
+        return Inferred::String;
+    }
+    // leading zeros are meaningful: identifiers, codes...
+    let has_leading_zero = samples
+        .iter()
+        .any(|value| value.len() > 1 && value.starts_with('0') && !value.starts_with("0."));
+    if has_leading_zero {
+        return Inferred::String;
+    }
+    if samples.iter().all(|value| value.parse::<i64>().is_ok()) {
+        return Inferred::Integer;
+    }
+    let is_float = samples
+        .iter()
+        .all(|value| value.parse::<f64>().is_ok() && value.chars().any(|c| c.is_ascii_digit()));
+    if is_float {
+        return Inferred::Float;
+    }
+    for date_format in candidate_date_formats() {
+        let converter = Converter::new(
+            "",
+            Some(&FieldType::Date {
+                mandatory: false,
+                input_date_format: None,
+            }),
+            &date_format,
+        );
+        if samples
+            .iter()
+            .all(|value| converter.to_json_value(value).is_ok())
+        {
+            return Inferred::Date(date_format);
+        }
+    }
+    Inferred::String
+}
+
+fn most_common_date_format(inferred: &[Inferred]) -> DateFormat {
+    let mut counts: Vec<(DateFormat, usize)> = Vec::new();
+    for inferred in inferred {
+        if let Inferred::Date(date_format) = inferred {
+            match counts
+                .iter_mut()
+                .find(|(format, _)| same_format(format, date_format))
+            {
+                Some((_, count)) => *count += 1,
+                None => counts.push((date_format.clone(), 1)),
+            }
+        }
+    }
+    // the first one wins in case of equality
+    let mut best: Option<(DateFormat, usize)> = None;
+    for (date_format, count) in counts {
+        if best
+            .as_ref()
+            .is_none_or(|(_, best_count)| count > *best_count)
+        {
+            best = Some((date_format, count));
+        }
+    }
+    best.map(|(date_format, _)| date_format).unwrap_or_default()
+}
+
+fn same_format(first: &DateFormat, second: &DateFormat) -> bool {
+    match (first, second) {
+        (DateFormat::Rfc3339, DateFormat::Rfc3339) | (DateFormat::Rfc2822, DateFormat::Rfc2822) => {
+            true
+        }
+        (DateFormat::Pattern(first, first_tz), DateFormat::Pattern(second, second_tz)) => {
+            first == second && first_tz == second_tz
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub const DATA_PATH: &str = "data/parser/NTFSInfo.csv";
+
+    #[test]
+    fn generate_ntfs_info() {
+        let generated = generate_mapping(DATA_PATH, "ntfs_info", 100).unwrap();
+        assert_eq!(',', generated.mapping.csv_delimiter);
+        assert_eq!("ComputerName", generated.columns[0]);
+        assert_eq!(
+            Some("CreationDate"),
+            generated.mapping.sort_field.as_deref()
+        );
+
+        let yaml = generated.to_yaml().unwrap();
+        let mapping: CsvMapping = serde_yml::from_str(&yaml).unwrap();
+        assert_eq!("ntfs_info", mapping.topic);
+        assert!(matches!(
+            mapping.default_date_format,
+            DateFormat::Pattern(ref pattern, false) if pattern == "%Y-%m-%d %H:%M:%S.%3f"
+        ));
+        assert!(matches!(
+            mapping.fields["SizeInBytes"],
+            FieldType::Integer { .. }
+        ));
+        assert!(matches!(
+            mapping.fields["LastAccessDate"],
+            FieldType::Date {
+                input_date_format: None,
+                ..
+            }
+        ));
+        // hexadecimal and leading zeros are kept as String
+        assert!(matches!(
+            mapping.fields["VolumeID"],
+            FieldType::String { .. }
+        ));
+        assert!(matches!(mapping.fields["USN"], FieldType::String { .. }));
+    }
+
+    #[test]
+    fn delimiter() {
+        assert_eq!(';', detect_delimiter("a;b;c\n1;2,5;3\n"));
+        assert_eq!('\t', detect_delimiter("a\tb\n1\t2\n"));
+        assert_eq!(',', detect_delimiter("a,b\n\"1;2\",3\n"));
+    }
+}
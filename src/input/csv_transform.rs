@@ -0,0 +1,316 @@
+use std::{borrow::Cow, rc::Rc};
+
+use csv::StringRecord;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Error;
+
+///
+/// Transformations applied to the csv columns, in order, before the type conversion
+/// The field types of the mapping refer to the transformed column names
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Transform {
+    Rename {
+        from: String,
+        to: String,
+    },
+    // concatenate columns, the separator is only inserted between non empty values
+    Concat {
+        fields: Vec<String>,
+        #[serde(default)]
+        separator: String,
+        to: String,
+    },
+    // every named capture group of the regex becomes a column
+    Extract {
+        from: String,
+        regex: String,
+    },
+    Lowercase {
+        fields: Vec<String>,
+    },
+    // use '\' as separator and remove duplicate and trailing separators
+    NormalizePath {
+        fields: Vec<String>,
+    },
+    Drop {
+        fields: Vec<String>,
+    },
+    Constant {
+        to: String,
+        value: String,
+    },
+}
+impl Transform {
+    ///
+    /// Apply the transformation to a list of column names, without knowing the csv header
+    ///
+    pub fn apply_to_names(&self, names: &mut Vec<String>) -> Result<(), Error> {
+        match self {
+            Transform::Rename { from, to } => {
+                names.retain(|name| name != from);
+                if !names.contains(to) {
+                    names.push(to.to_owned());
+                }
+            }
+            Transform::Concat { to, .. } | Transform::Constant { to, .. } => {
+                if !names.contains(to) {
+                    names.push(to.to_owned());
+                }
+            }
+            Transform::Extract { regex, .. } => {
+                let regex = Regex::new(regex)?;
+                for group in regex.capture_names().flatten() {
+                    if !names.iter().any(|name| name == group) {
+                        names.push(group.to_owned());
+                    }
+                }
+            }
+            Transform::Drop { fields } => names.retain(|name| !fields.contains(name)),
+            Transform::Lowercase { .. } | Transform::NormalizePath { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+///
+/// How an output column is computed from the csv record
+///
+#[derive(Clone, Debug)]
+enum Column {
+    Source(usize),
+    Concat(Vec<Column>, String),
+    Extract(Box<Column>, Rc<Regex>, usize),
+    Lowercase(Box<Column>),
+    NormalizePath(Box<Column>),
+    Constant(String),
+}
+impl Column {
+    fn eval<'a>(&'a self, record: &'a StringRecord) -> Cow<'a, str> {
+        match self {
+            Column::Source(pos) => Cow::Borrowed(record.get(*pos).unwrap_or("")),
+            Column::Concat(columns, separator) => {
+                let values: Vec<Cow<str>> = columns
+                    .iter()
+                    .map(|column| column.eval(record))
+                    .filter(|value| !value.is_empty())
+                    .collect();
+                Cow::Owned(values.join(separator))
+            }
+            Column::Extract(column, regex, group) => {
+                let value = column.eval(record);
+                let extracted = regex
+                    .captures(&value)
+                    .and_then(|captures| captures.get(*group))
+                    .map(|m| m.as_str().to_owned())
+                    .unwrap_or_default();
+                Cow::Owned(extracted)
+            }
+            Column::Lowercase(column) => Cow::Owned(column.eval(record).to_lowercase()),
+            Column::NormalizePath(column) => Cow::Owned(normalize_path(&column.eval(record))),
+            Column::Constant(value) => Cow::Borrowed(value),
+        }
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    for c in path.chars() {
+        let c = if c == '/' { '\\' } else { c };
+        if c == '\\' && result.ends_with('\\') {
+            continue;
+        }
+        result.push(c);
+    }
+    if result.len() > 1 && result.ends_with('\\') {
+        result.pop();
+    }
+    result
+}
+
+///
+/// The transformations resolved against the header of a csv file
+///
+#[derive(Debug)]
+pub struct ColumnPlan {
+    headers: StringRecord,
+    // None when there is no transformation
+    columns: Option<Vec<Column>>,
+}
+impl ColumnPlan {
+    pub fn new(headers: &StringRecord, transforms: &[Transform]) -> Result<Self, Error> {
+        if transforms.is_empty() {
+            return Ok(Self {
+                headers: headers.clone(),
+                columns: None,
+            });
+        }
+
+        let mut columns: Vec<(String, Column)> = headers
+            .iter()
+            .enumerate()
+            .map(|(pos, name)| (name.to_owned(), Column::Source(pos)))
+            .collect();
+
+        for transform in transforms {
+            match transform {
+                Transform::Rename { from, to } => {
+                    get_column(&columns, from)?;
+                    columns.retain(|(name, _)| name != to || name == from);
+                    if let Some(column) = columns.iter_mut().find(|(name, _)| name == from) {
+                        column.0 = to.to_owned();
+                    }
+                }
+                Transform::Concat {
+                    fields,
+                    separator,
+                    to,
+                } => {
+                    let sources = fields
+                        .iter()
+                        .map(|field| get_column(&columns, field))
+                        .collect::<Result<Vec<Column>, Error>>()?;
+                    set_column(&mut columns, to, Column::Concat(sources, separator.clone()));
+                }
+                Transform::Extract { from, regex } => {
+                    let source = get_column(&columns, from)?;
+                    let regex = Rc::new(Regex::new(regex)?);
+                    for (group, name) in regex.capture_names().enumerate() {
+                        if let Some(name) = name {
+                            let column =
+                                Column::Extract(Box::new(source.clone()), regex.clone(), group);
+                            set_column(&mut columns, name, column);
+                        }
+                    }
+                }
+                Transform::Lowercase { fields } => {
+                    for field in fields {
+                        let column = get_column(&columns, field)?;
+                        set_column(&mut columns, field, Column::Lowercase(Box::new(column)));
+                    }
+                }
+                Transform::NormalizePath { fields } => {
+                    for field in fields {
+                        let column = get_column(&columns, field)?;
+                        set_column(&mut columns, field, Column::NormalizePath(Box::new(column)));
+                    }
+                }
+                Transform::Drop { fields } => {
+                    columns.retain(|(name, _)| !fields.contains(name));
+                }
+                Transform::Constant { to, value } => {
+                    set_column(&mut columns, to, Column::Constant(value.clone()));
+                }
+            }
+        }
+
+        let headers = StringRecord::from(
+            columns
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<&str>>(),
+        );
+        Ok(Self {
+            headers,
+            columns: Some(columns.into_iter().map(|(_, column)| column).collect()),
+        })
+    }
+
+    ///
+    /// The column names after transformation
+    ///
+    pub fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    pub fn apply<'a>(&self, record: &'a StringRecord) -> Cow<'a, StringRecord> {
+        match &self.columns {
+            None => Cow::Borrowed(record),
+            Some(columns) => {
+                let mut result =
+                    StringRecord::with_capacity(record.as_slice().len(), columns.len());
+                for column in columns {
+                    result.push_field(&column.eval(record));
+                }
+                Cow::Owned(result)
+            }
+        }
+    }
+}
+
+fn get_column(columns: &[(String, Column)], name: &str) -> Result<Column, Error> {
+    columns
+        .iter()
+        .find(|(column_name, _)| column_name == name)
+        .map(|(_, column)| column.clone())
+        .ok_or(Error::CsvConfiguration(format!(
+            "transformation references the unknown column '{name}'"
+        )))
+}
+
+///
+/// Replace the column with the same name or add it at the end
+///
+fn set_column(columns: &mut Vec<(String, Column)>, name: &str, column: Column) {
+    match columns
+        .iter_mut()
+        .find(|(column_name, _)| column_name == name)
+    {
+        Some(existing) => existing.1 = column,
+        None => columns.push((name.to_owned(), column)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transform_columns() {
+        let transforms: Vec<Transform> = serde_yml::from_str(
+            r#"
+- !Rename { from: File, to: FileName }
+- !Concat { fields: [ParentName, FileName], separator: '\', to: FullPath }
+- !NormalizePath { fields: [FullPath] }
+- !Lowercase { fields: [FullPath] }
+- !Extract { from: FileName, regex: '\.(?P<Ext>\w+)$' }
+- !Drop { fields: [MD5] }
+- !Constant { to: Source, value: NTFSInfo }
+"#,
+        )
+        .unwrap();
+
+        let headers = StringRecord::from(vec!["ParentName", "File", "MD5"]);
+        let plan = ColumnPlan::new(&headers, &transforms).unwrap();
+        assert_eq!(
+            vec!["ParentName", "FileName", "FullPath", "Ext", "Source"],
+            plan.headers().iter().collect::<Vec<&str>>()
+        );
+
+        let record = StringRecord::from(vec!["\\Windows/System32\\", "Cmd.EXE", "abcd"]);
+        let result = plan.apply(&record);
+        assert_eq!(
+            vec![
+                "\\Windows/System32\\",
+                "Cmd.EXE",
+                "\\windows\\system32\\cmd.exe",
+                "EXE",
+                "NTFSInfo"
+            ],
+            result.iter().collect::<Vec<&str>>()
+        );
+
+        let mut names: Vec<String> = vec!["File".to_owned(), "MD5".to_owned()];
+        for transform in &transforms {
+            transform.apply_to_names(&mut names).unwrap();
+        }
+        assert_eq!(vec!["FileName", "FullPath", "Ext", "Source"], names);
+
+        let unknown = vec![Transform::Lowercase {
+            fields: vec!["Unknown".to_owned()],
+        }];
+        ColumnPlan::new(&headers, &unknown).expect_err("unknown column");
+    }
+}
@@ -5,6 +5,8 @@ use crate::{
     configuration::DataType,
     output::{Fields, OUTPUT_DATE_FORMAT_UTC, Output, OutputConfig, Tuple},
 };
+
+use super::parse_errors::ParseErrors;
 use chrono::{DateTime, FixedOffset, Utc};
 use evtx::ParserSettings;
 use serde_json::{Value, json};
@@ -15,6 +17,7 @@ pub const EVTX_TABLE_NAME: &str = "evtx";
 pub const EVTX_SORT_FIELD: &str = "System.TimeCreated";
 ///
 /// Parse a windows evtx log file
+/// Invalid records are skipped and written to the parse_errors topic
 ///
 pub fn parse_evtx<P: AsRef<Path>>(
     path: P,
@@ -29,34 +32,53 @@ pub fn parse_evtx<P: AsRef<Path>>(
         client_context,
         EVTX_TABLE_NAME,
     )?;
+    let mut errors = ParseErrors::new(true, EVTX_TABLE_NAME, fields, client_context, output_config);
 
-    parse(path, fields, &mut output)?;
+    parse(path, fields, &mut output, &mut errors)?;
+    errors.complete()?;
+    output.complete()?;
     Ok(output.num_rows())
 }
 
-fn parse<P: AsRef<Path>>(path: P, fields: &Fields, output: &mut Output) -> Result<(), Error> {
+fn parse<P: AsRef<Path>>(
+    path: P,
+    fields: &Fields,
+    output: &mut Output,
+    errors: &mut ParseErrors,
+) -> Result<(), Error> {
     let settings = ParserSettings::new().separate_json_attributes(true);
     let parser = EvtxParser::from_path(path).unwrap();
     let mut parser = parser.with_configuration(settings);
 
-    for record in parser.records_json_value() {
-        let record = record?;
-        let (event, sort_data) = format_event(record.data).unwrap();
-        let mut tuple = Tuple::new(fields);
-        tuple.set_data(event, Some(sort_data))?;
-        output.write(tuple)?;
+    for (record_nb, record) in parser.records_json_value().enumerate() {
+        let mut record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                errors.reject(err.into(), record_nb, "")?;
+                continue;
+            }
+        };
+        match format_event(&mut record.data) {
+            Ok((event, sort_data)) => {
+                let mut tuple = Tuple::new(fields);
+                tuple.set_data(event, Some(sort_data))?;
+                output.write(tuple)?;
+            }
+            Err(err) => errors.reject(err, record_nb, &record.data.to_string())?,
+        }
     }
     Ok(())
 }
 
 ///
 /// Simplify the output and format the TimeCreated date properly
+/// The record is left mostly untouched upon error, to be stored in the parse_errors topic
 ///
-fn format_event(mut event: Value) -> Result<(Value, i64), Error> {
-    let mut event = event
+fn format_event(record: &mut Value) -> Result<(Value, i64), Error> {
+    let event = record
         .as_object_mut()
         .ok_or(Error::JsonNotAndObject("event".to_owned()))?
-        .remove("Event")
+        .get_mut("Event")
         .ok_or(Error::Evtx("Event data not found".to_string()))?;
 
     let system = event
@@ -114,7 +136,7 @@ fn format_event(mut event: Value) -> Result<(Value, i64), Error> {
     let sort_data = datetime.timestamp();
     let value = json!(datetime.format(OUTPUT_DATE_FORMAT_UTC).to_string());
     system.insert("TimeCreated".to_owned(), value);
-    Ok((event, sort_data))
+    Ok((event.take(), sort_data))
 }
 
 pub fn evtx_fields() -> Vec<(String, DataType)> {
@@ -164,7 +186,8 @@ mod tests {
         };
 
         let now = Instant::now();
-        parse(EVTX_PATH, &fields, &mut output).unwrap();
+        let mut errors = ParseErrors::new(false, EVTX_TABLE_NAME, &fields, "", &[]);
+        parse(EVTX_PATH, &fields, &mut output, &mut errors).unwrap();
         println!("Parse {} rows in {:.2?}", output.num_rows(), now.elapsed());
 
         let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[0]).unwrap();
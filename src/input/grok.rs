@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::Error;
+
+///
+/// matches %{PATTERN} and %{PATTERN:field_name}
+///
+const GROK_REFERENCE: &str = r"%\{(\w+)(?::([\w.\[\]]+))?\}";
+
+///
+/// Protection against recursive pattern definitions
+///
+const MAX_DEPTH: usize = 16;
+
+///
+/// A subset of the logstash grok patterns, adapted to the regex crate syntax (no look-around)
+/// https://github.com/logstash-plugins/logstash-patterns-core/blob/main/patterns/legacy/grok-patterns
+///
+const BUILTIN_PATTERNS: [(&str, &str); 43] = [
+    ("USERNAME", r"[a-zA-Z0-9._-]+"),
+    ("USER", r"%{USERNAME}"),
+    ("INT", r"(?:[+-]?(?:[0-9]+))"),
+    ("BASE10NUM", r"(?:[+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+))"),
+    ("NUMBER", r"(?:%{BASE10NUM})"),
+    ("BASE16NUM", r"(?:0[xX]?[0-9a-fA-F]+)"),
+    ("POSINT", r"\b(?:[1-9][0-9]*)\b"),
+    ("NONNEGINT", r"\b(?:[0-9]+)\b"),
+    ("WORD", r"\b\w+\b"),
+    ("NOTSPACE", r"\S+"),
+    ("SPACE", r"\s*"),
+    ("DATA", r".*?"),
+    ("GREEDYDATA", r".*"),
+    ("QUOTEDSTRING", r#"(?:"(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*')"#),
+    (
+        "UUID",
+        r"[A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}",
+    ),
+    (
+        "IPV4",
+        r"(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)",
+    ),
+    ("IPV6", r"(?:[0-9A-Fa-f]{0,4}:){2,7}[0-9A-Fa-f]{0,4}"),
+    ("IP", r"(?:%{IPV6}|%{IPV4})"),
+    (
+        "HOSTNAME",
+        r"\b(?:[0-9A-Za-z][0-9A-Za-z-]{0,62})(?:\.(?:[0-9A-Za-z][0-9A-Za-z-]{0,62}))*\.?",
+    ),
+    ("IPORHOST", r"(?:%{IP}|%{HOSTNAME})"),
+    ("HOSTPORT", r"%{IPORHOST}:%{POSINT}"),
+    ("UNIXPATH", r"(?:/[^/\s]*)+"),
+    ("WINPATH", r"(?:[A-Za-z]+:|\\)(?:\\[^\\?*]*)+"),
+    ("PATH", r"(?:%{UNIXPATH}|%{WINPATH})"),
+    ("URIPATH", r"(?:/[A-Za-z0-9$.+!*'(){},~:;=@#%&_\-]*)+"),
+    (
+        "MONTH",
+        r"\b(?:[Jj]an(?:uary)?|[Ff]eb(?:ruary)?|[Mm]ar(?:ch)?|[Aa]pr(?:il)?|[Mm]ay|[Jj]un(?:e)?|[Jj]ul(?:y)?|[Aa]ug(?:ust)?|[Ss]ep(?:tember)?|[Oo]ct(?:ober)?|[Nn]ov(?:ember)?|[Dd]ec(?:ember)?)\b",
+    ),
+    ("MONTHNUM", r"(?:0?[1-9]|1[0-2])"),
+    ("MONTHDAY", r"(?:(?:0[1-9])|(?:[12][0-9])|(?:3[01])|[1-9])"),
+    (
+        "DAY",
+        r"\b(?:Mon(?:day)?|Tue(?:sday)?|Wed(?:nesday)?|Thu(?:rsday)?|Fri(?:day)?|Sat(?:urday)?|Sun(?:day)?)\b",
+    ),
+    ("YEAR", r"(?:\d\d){1,2}"),
+    ("HOUR", r"(?:2[0123]|[01]?[0-9])"),
+    ("MINUTE", r"(?:[0-5][0-9])"),
+    ("SECOND", r"(?:(?:[0-5]?[0-9]|60)(?:[:.,][0-9]+)?)"),
+    ("TIME", r"%{HOUR}:%{MINUTE}(?::%{SECOND})?"),
+    ("ISO8601_TIMEZONE", r"(?:Z|[+-]%{HOUR}(?::?%{MINUTE}))"),
+    (
+        "TIMESTAMP_ISO8601",
+        r"%{YEAR}-%{MONTHNUM}-%{MONTHDAY}[T ]%{HOUR}:?%{MINUTE}(?::?%{SECOND})?%{ISO8601_TIMEZONE}?",
+    ),
+    ("DATE_US", r"%{MONTHNUM}[/-]%{MONTHDAY}[/-]%{YEAR}"),
+    ("DATE_EU", r"%{MONTHDAY}[./-]%{MONTHNUM}[./-]%{YEAR}"),
+    ("DATESTAMP", r"(?:%{DATE_US}|%{DATE_EU})[- ]%{TIME}"),
+    ("SYSLOGTIMESTAMP", r"%{MONTH} +%{MONTHDAY} %{TIME}"),
+    ("HTTPDATE", r"%{MONTHDAY}/%{MONTH}/%{YEAR}:%{TIME} %{INT}"),
+    ("SYSLOGPROG", r"%{WORD}(?:\[%{POSINT}\])?"),
+    (
+        "LOGLEVEL",
+        r"(?:[Aa]lert|ALERT|[Tt]race|TRACE|[Dd]ebug|DEBUG|[Nn]otice|NOTICE|[Ii]nfo(?:rmation)?|INFO(?:RMATION)?|[Ww]arn(?:ing)?|WARN(?:ING)?|[Ee]rr(?:or)?|ERR(?:OR)?|[Cc]rit(?:ical)?|CRIT(?:ICAL)?|[Ff]atal|FATAL|[Ss]evere|SEVERE|[Ee]merg(?:ency)?|EMERG(?:ENCY)?)",
+    ),
+];
+
+///
+/// Convert a grok expression into a regular expression
+/// - %{PATTERN:field} is replaced by the named capture group (?P<field>...)
+/// - %{PATTERN} is replaced by a non capturing group
+///
+/// custom patterns are looked up before the builtin ones, allowing to override them
+///
+pub fn expand(
+    expression: &str,
+    custom_patterns: &HashMap<String, String>,
+) -> Result<String, Error> {
+    let reference = Regex::new(GROK_REFERENCE)?;
+    let builtin: HashMap<&str, &str> = BUILTIN_PATTERNS.into_iter().collect();
+    expand_recursive(expression, &reference, custom_patterns, &builtin, 0)
+}
+
+fn expand_recursive(
+    expression: &str,
+    reference: &Regex,
+    custom_patterns: &HashMap<String, String>,
+    builtin: &HashMap<&str, &str>,
+    depth: usize,
+) -> Result<String, Error> {
+    if depth > MAX_DEPTH {
+        return Err(Error::RegexLogConfiguration(format!(
+            "grok expression '{expression}' is too deeply nested"
+        )));
+    }
+
+    let mut result = String::with_capacity(expression.len());
+    let mut last = 0;
+    for captures in reference.captures_iter(expression) {
+        let whole = captures.get(0).unwrap();
+        let name = &captures[1];
+        let pattern = match custom_patterns.get(name) {
+            Some(pattern) => pattern.as_str(),
+            None => *builtin
+                .get(name)
+                .ok_or(Error::RegexLogConfiguration(format!(
+                    "unknown grok pattern '{name}'"
+                )))?,
+        };
+        let pattern = expand_recursive(pattern, reference, custom_patterns, builtin, depth + 1)?;
+
+        result.push_str(&expression[last..whole.start()]);
+        match captures.get(2) {
+            Some(field) => result.push_str(&format!("(?P<{}>{pattern})", field.as_str())),
+            None => result.push_str(&format!("(?:{pattern})")),
+        }
+        last = whole.end();
+    }
+    result.push_str(&expression[last..]);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_grok() {
+        let mut custom = HashMap::new();
+        custom.insert("APPID".to_owned(), r"app-%{INT}".to_owned());
+
+        let regex = expand(
+            r"^%{TIMESTAMP_ISO8601:TimeStamp} \[%{LOGLEVEL:Level}\] %{APPID:App} %{IP:Ip} %{GREEDYDATA:Message}$",
+            &custom,
+        )
+        .unwrap();
+        let regex = Regex::new(&regex).unwrap();
+
+        let captures = regex
+            .captures("2024-01-02 10:14:15.003 [WARN] app-12 10.0.0.2 disk is almost full")
+            .unwrap();
+        assert_eq!("2024-01-02 10:14:15.003", &captures["TimeStamp"]);
+        assert_eq!("WARN", &captures["Level"]);
+        assert_eq!("app-12", &captures["App"]);
+        assert_eq!("10.0.0.2", &captures["Ip"]);
+        assert_eq!("disk is almost full", &captures["Message"]);
+
+        expand("%{UNKNOWN:field}", &custom).expect_err("unknown pattern");
+
+        custom.insert("LOOP".to_owned(), "%{LOOP}".to_owned());
+        expand("%{LOOP}", &custom).expect_err("recursive pattern");
+    }
+}
@@ -3,9 +3,10 @@ use crate::{
     configuration::DataType,
     output::{Fields, OUTPUT_DATE_FORMAT_UTC, Output, OutputConfig, Tuple},
 };
-use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD as enc64};
+
+use super::hive_value::{HIVE_VALUE, HIVE_VALUE_HEX, HIVE_VALUE_LIST, decode_value};
 use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
-use nt_hive::{Hive, KeyNode, KeyValue, KeyValueData, KeyValueDataType, Result};
+use nt_hive::{Hive, KeyNode, KeyValue};
 use serde_json::json;
 use std::{fs::File, io::Read, path::Path};
 use zerocopy::SplitByteSlice;
@@ -16,12 +17,26 @@ pub const HIVE_SORT_FIELD: &str = HIVE_DATE;
 const HIVE_PATH: &str = "Path";
 const HIVE_CLASS: &str = "Class";
 const HIVE_DATE: &str = "TimeStamp";
-const HIVE_VALUE: &str = "Value";
+const HIVE_VALUE_TYPE: &str = "ValueType";
+const HIVE_VALUE_SIZE: &str = "ValueSize";
+const HIVE_VALUE_TIME: &str = "ValueTime";
+const HIVE_DECODE_ERROR: &str = "DecodeError";
 pub fn hive_fields() -> Vec<(String, DataType)> {
     vec![
         (HIVE_PATH.to_owned(), DataType::String),
         (HIVE_CLASS.to_owned(), DataType::String),
         (HIVE_DATE.to_owned(), DataType::Date),
+        // numbers and strings, mapped as strings
+        (HIVE_VALUE.to_owned(), DataType::String),
+        (HIVE_VALUE_HEX.to_owned(), DataType::String),
+        (
+            HIVE_VALUE_LIST.to_owned(),
+            DataType::Array(Box::new(DataType::String)),
+        ),
+        (HIVE_VALUE_TYPE.to_owned(), DataType::String),
+        (HIVE_VALUE_SIZE.to_owned(), DataType::Int64),
+        (HIVE_VALUE_TIME.to_owned(), DataType::Date),
+        (HIVE_DECODE_ERROR.to_owned(), DataType::String),
     ]
 }
 ///
@@ -43,6 +58,7 @@ pub fn parse_hive<P: AsRef<Path>>(
     )?;
 
     parse(path, root_name, fields, &mut output)?;
+    output.complete()?;
     Ok(output.num_rows())
 }
 
@@ -129,13 +145,7 @@ where
                         value_name.push_str("Default");
                     }
 
-                    let value = match parse_key_value(key_value) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            // println!("{e:?}");
-                            serde_json::Value::String(format!("{e}"))
-                        }
-                    };
+                    let decoded = decode_value(&key_value);
 
                     let path = format!("{path}\\{value_name}");
                     let mut data = serde_json::Map::new();
@@ -144,7 +154,18 @@ where
                     if let Some(class) = &class_name {
                         data.insert(HIVE_CLASS.to_owned(), json!(class));
                     }
-                    data.insert(HIVE_VALUE.to_owned(), value);
+                    data.extend(decoded.columns);
+                    data.insert(HIVE_VALUE_TYPE.to_owned(), json!(decoded.value_type));
+                    data.insert(HIVE_VALUE_SIZE.to_owned(), json!(decoded.size));
+                    if let Some(time) = decoded.time {
+                        data.insert(
+                            HIVE_VALUE_TIME.to_owned(),
+                            json!(time.format(OUTPUT_DATE_FORMAT_UTC).to_string()),
+                        );
+                    }
+                    if let Some(error) = decoded.error {
+                        data.insert(HIVE_DECODE_ERROR.to_owned(), json!(error));
+                    }
 
                     let mut tuple = Tuple::new(fields);
                     tuple.set_data(serde_json::Value::Object(data), Some(timestamp))?;
@@ -159,72 +180,6 @@ where
     Ok(())
 }
 
-///
-/// translate hive values to serde_json::Value
-///
-fn parse_key_value<B>(value: KeyValue<B>) -> Result<serde_json::Value, Error>
-where
-    B: SplitByteSlice,
-{
-    let json_value = match value.data_type() {
-        Ok(value_type) => match value_type {
-            KeyValueDataType::RegSZ | KeyValueDataType::RegExpandSZ => {
-                let string_data = value.string_data()?;
-                json!(string_data)
-            }
-            KeyValueDataType::RegBinary => {
-                let binary_data = value.data()?;
-                match binary_data {
-                    KeyValueData::Small(data) => {
-                        let binary = enc64.encode(data);
-                        json!(binary)
-                    }
-                    KeyValueData::Big(iter) => {
-                        let data = iter.collect::<Result<Vec<_>>>()?;
-
-                        let data_size = value.data_size();
-                        let mut value: Vec<u8> = Vec::with_capacity(data_size as usize);
-                        for slice in data {
-                            value.extend_from_slice(slice);
-                        }
-
-                        json!(enc64.encode(&value))
-                    }
-                }
-            }
-            KeyValueDataType::RegDWord | KeyValueDataType::RegDWordBigEndian => {
-                let dword_data = value.dword_data()?;
-                json!(dword_data)
-            }
-            KeyValueDataType::RegMultiSZ => {
-                let multi_string_data = value.multi_string_data()?.collect::<Result<Vec<_>>>()?;
-
-                let string_value = multi_string_data.join("");
-                json!(string_value)
-            }
-            KeyValueDataType::RegQWord => {
-                let qword_data = value.qword_data()?;
-                json!(qword_data)
-            }
-            _ => serde_json::Value::Null,
-        },
-        Err(e) => {
-            let bytes = value.data().unwrap().into_vec().unwrap();
-            let as_string = String::from_utf8_lossy(&bytes)
-                .to_string()
-                .replace("\0", "");
-
-            let value = format!(
-                "{{\"DataString\":\"{as_string}\", \"DataBase64\":\"{}\", \"Error\":\"{e}\"}}",
-                enc64.encode(&bytes)
-            );
-            json!(value)
-        }
-    };
-
-    Ok(json_value)
-}
-
 //
 // parse TimeStamp in the FILETIME format: the number of 100-nanosecond intervals since January 1, 1601 (UTC).
 //
@@ -274,6 +229,48 @@ mod tests {
 
         let value = data.get("Value").unwrap().as_str().unwrap();
         assert_eq!(value, "sz-test");
+        assert_eq!("REG_SZ", data["ValueType"]);
+        assert!(data.get("DecodeError").is_none());
+    }
+
+    #[test]
+    fn typed_values() {
+        init_log();
+        let output = MemoryWriter::new(1000);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+        let fields = Fields::new("mymachine", "testhive", "mymachine_ORC.7z", "testhive");
+        parse("data/parser/testhive", "kernel", &fields, &mut output).unwrap();
+
+        let rows: Vec<serde_json::Value> = buffer
+            .borrow()
+            .iter()
+            .map(|row| serde_json::from_str::<serde_json::Value>(row).unwrap()["data"].clone())
+            .collect();
+        let value = |path: &str| {
+            rows.iter()
+                .find(|row| row["Path"] == format!("kernel\\data-test\\{path}"))
+                .unwrap()
+                .clone()
+        };
+
+        let multi_sz = value("reg-multi-sz");
+        assert_eq!("REG_MULTI_SZ", multi_sz["ValueType"]);
+        assert!(multi_sz["Value"].is_string());
+        assert!(multi_sz["ValueList"].is_array());
+
+        let dword = value("dword");
+        assert_eq!("REG_DWORD", dword["ValueType"]);
+        assert_eq!(4, dword["ValueSize"]);
+        assert!(dword["Value"].is_u64());
+
+        let binary = value("binary");
+        assert_eq!("REG_BINARY", binary["ValueType"]);
+        assert!(binary["Value"].is_string());
+        assert!(binary["ValueHex"].is_string());
     }
 
     #[test]
@@ -309,10 +306,11 @@ mod tests {
             "\\SAM\\Domains\\Account\\Groups\\Names\\None\\Default"
         );
 
-        let value = data.get("Value").unwrap().as_str().unwrap();
-        assert_eq!(
-            value,
-            "{\"DataString\":\"\", \"DataBase64\":\"\", \"Error\":\"The key value data type at offset 0x00001800 is 0x00000201, which is not supported\"}"
-        );
+        // the type stores the RID of the group, the value is kept with its raw type
+        assert_eq!("0x00000201", data["ValueType"]);
+        assert_eq!(0, data["ValueSize"]);
+        assert_eq!("", data["Value"]);
+        assert_eq!("", data["ValueHex"]);
+        assert!(data.get("DecodeError").is_none());
     }
 }
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use log::{info, warn};
+use nt_hive::Hive;
+
+use crate::{
+    Error,
+    source_path::{CollectedFile, SourcePaths, is_backup_copy},
+};
+
+///
+/// Placeholders of the hive root name
+///
+const USERNAME_PLACEHOLDER: &str = "{username}";
+const SID_PLACEHOLDER: &str = "{SID}";
+
+///
+/// Used when the user owning the hive cannot be found
+///
+const UNKNOWN_USER: &str = "unknown";
+
+const PROFILE_LIST: &str = "Microsoft\\Windows NT\\CurrentVersion\\ProfileList";
+const SOFTWARE_HIVE: &str = "software";
+const SOFTWARE_HIVE_PATH: &str = "\\config\\software";
+const PROFILE_IMAGE_PATH: &str = "ProfileImagePath";
+
+///
+/// Folders containing the user profiles, compared in lowercase
+///
+const PROFILE_FOLDERS: [&str; 2] = ["users", "documents and settings"];
+
+pub fn has_placeholder(root_name: &str) -> bool {
+    root_name.contains(USERNAME_PLACEHOLDER) || root_name.contains(SID_PLACEHOLDER)
+}
+
+///
+/// The user profiles of a computer, read from the ProfileList key of the SOFTWARE hive
+///
+#[derive(Default, Debug)]
+pub struct UserProfiles {
+    // lowercase user name -> SID
+    sids: HashMap<String, String>,
+}
+impl UserProfiles {
+    ///
+    /// Read the SOFTWARE hive of a collection, the profiles are empty when it cannot be found
+    /// The live hive is preferred to the copies of the RegBack folder or of the volume shadow copies,
+    /// and a hive found in a config folder to a file only named SOFTWARE
+    ///
+    pub fn load(files: &[CollectedFile], source_paths: &SourcePaths) -> Self {
+        let software = files
+            .iter()
+            .filter_map(|file| {
+                let original_path = source_paths
+                    .original_path(&file.relative_path)
+                    .to_lowercase()
+                    .replace('/', "\\");
+                let in_config = original_path.ends_with(SOFTWARE_HIVE_PATH);
+                if !in_config && !file.file_name.eq_ignore_ascii_case(SOFTWARE_HIVE) {
+                    return None;
+                }
+                let is_copy = is_backup_copy(&file.relative_path) || is_backup_copy(&original_path);
+                Some(((is_copy, !in_config), file))
+            })
+            .min_by_key(|(rank, _)| *rank)
+            .map(|(_, file)| file);
+        let Some(software) = software else {
+            warn!("no SOFTWARE hive found, the SID of the users cannot be resolved");
+            return Self::default();
+        };
+        match Self::from_software_hive(&software.path) {
+            Ok(profiles) => {
+                info!(
+                    "{} user profiles read from '{}'",
+                    profiles.sids.len(),
+                    software.relative_path
+                );
+                profiles
+            }
+            Err(e) => {
+                warn!(
+                    "could not read the user profiles of '{}': {e}",
+                    software.relative_path
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn from_software_hive<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let data = fs::read(path)?;
+        let hive = Hive::without_validation(data.as_ref())?;
+        let root = hive.root_key_node()?;
+
+        let mut sids = HashMap::new();
+        if let Some(profile_list) = root.subpath(PROFILE_LIST)
+            && let Some(profiles) = profile_list?.subkeys()
+        {
+            for profile in profiles? {
+                let profile = profile?;
+                let sid = profile.name()?.to_string_lossy();
+                if let Some(image_path) = profile.value(PROFILE_IMAGE_PATH)
+                    && let Some(username) = username_from_profile(&image_path?.string_data()?)
+                {
+                    sids.insert(username.to_lowercase(), sid);
+                }
+            }
+        }
+        Ok(Self { sids })
+    }
+
+    ///
+    /// Replace the placeholders of the root name with the user owning the hive
+    /// - {username}: the profile folder in the original path of the hive
+    /// - {SID}: the SID of the profile, the user name is used when it cannot be found
+    ///
+    pub fn root_name(&self, template: &str, original_path: &str) -> String {
+        if !has_placeholder(template) {
+            return template.to_owned();
+        }
+        let username = username_from_path(original_path);
+        let sid = username.and_then(|username| self.sids.get(&username.to_lowercase()));
+        if template.contains(SID_PLACEHOLDER) && sid.is_none() {
+            warn!("the SID of the user owning '{original_path}' cannot be found");
+        }
+        let username = username.unwrap_or(UNKNOWN_USER);
+        template
+            .replace(USERNAME_PLACEHOLDER, username)
+            .replace(SID_PLACEHOLDER, sid.map_or(username, String::as_str))
+    }
+}
+
+///
+/// The folder following the profile folder: C:\Users\bob\NTUSER.DAT gives bob
+///
+fn username_from_path(path: &str) -> Option<&str> {
+    let mut components = path.split(['\\', '/']);
+    while let Some(component) = components.next() {
+        if PROFILE_FOLDERS.contains(&component.to_lowercase().as_str()) {
+            return components.next().filter(|username| !username.is_empty());
+        }
+    }
+    None
+}
+
+///
+/// The last folder of the profile path: %SystemDrive%\Users\bob gives bob
+///
+fn username_from_profile(image_path: &str) -> Option<&str> {
+    image_path
+        .trim_end_matches(['\\', '/'])
+        .rsplit(['\\', '/'])
+        .next()
+        .filter(|username| !username.is_empty() && !username.contains(':'))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::source_path::{PathLayout, collect_files};
+
+    use super::*;
+
+    #[test]
+    fn root_name() {
+        let mut profiles = UserProfiles::default();
+        profiles.sids.insert(
+            "bob".to_owned(),
+            "S-1-5-21-3623811015-3361044348-30300820-1013".to_owned(),
+        );
+
+        assert_eq!(
+            "\\HKU\\bob",
+            profiles.root_name("\\HKU\\{username}", "C:\\Users\\bob\\NTUSER.DAT")
+        );
+        assert_eq!(
+            "\\HKU\\S-1-5-21-3623811015-3361044348-30300820-1013_Classes",
+            profiles.root_name(
+                "\\HKU\\{SID}_Classes",
+                "C:\\Users\\Bob\\AppData\\Local\\Microsoft\\Windows\\UsrClass.dat"
+            )
+        );
+        // the SID is unknown, the user name is used
+        assert_eq!(
+            "\\HKU\\alice",
+            profiles.root_name("\\HKU\\{SID}", "C/Users/alice/NTUSER.DAT")
+        );
+        assert_eq!(
+            "\\HKU\\unknown",
+            profiles.root_name("\\HKU\\{username}", "NTUSER.DAT")
+        );
+        assert_eq!("\\HKLM\\SAM", profiles.root_name("\\HKLM\\SAM", "SAM"));
+    }
+
+    #[test]
+    fn profile_path() {
+        assert_eq!(
+            Some("bob"),
+            username_from_profile("%SystemDrive%\\Users\\bob")
+        );
+        assert_eq!(
+            Some("systemprofile"),
+            username_from_profile("%systemroot%\\system32\\config\\systemprofile\\")
+        );
+        assert_eq!(None, username_from_profile("C:"));
+        assert_eq!(
+            Some("bob"),
+            username_from_path("\\Documents and Settings\\bob\\NTUSER.DAT")
+        );
+    }
+
+    #[test]
+    fn profile_list() {
+        let profiles = UserProfiles::from_software_hive("data/parser/SOFTWARE.hive").unwrap();
+        assert_eq!(3, profiles.sids.len());
+        assert_eq!(
+            "S-1-5-21-3623811015-3361044348-30300820-1013",
+            profiles.sids["bob"]
+        );
+        assert_eq!("S-1-5-18", profiles.sids["systemprofile"]);
+        assert_eq!(
+            "\\HKU\\S-1-5-21-3623811015-3361044348-30300820-1014",
+            profiles.root_name("\\HKU\\{SID}", "C:\\Users\\Alice\\NTUSER.DAT")
+        );
+    }
+
+    #[test]
+    fn live_software_hive() {
+        let root = "data/temp/hive_profiles";
+        let _ = fs::remove_dir_all(root);
+        let config = format!("{root}/C/Windows/System32/config");
+        let vss_config = format!("{root}/vss1/C/Windows/System32/config");
+        fs::create_dir_all(format!("{config}/RegBack")).unwrap();
+        fs::create_dir_all(&vss_config).unwrap();
+        // the copies have no profile, they come first in the path order
+        fs::copy("data/parser/testhive", format!("{config}/RegBack/SOFTWARE")).unwrap();
+        fs::copy("data/parser/testhive", format!("{vss_config}/SOFTWARE")).unwrap();
+        fs::copy("data/parser/SOFTWARE.hive", format!("{config}/SOFTWARE")).unwrap();
+
+        let files = collect_files(Path::new(root)).unwrap();
+        let profiles = UserProfiles::load(&files, &SourcePaths::default());
+        assert_eq!(3, profiles.sids.len());
+
+        let source_paths = SourcePaths::new(&[PathLayout::kape], Path::new(root)).unwrap();
+        let profiles = UserProfiles::load(&files, &source_paths);
+        assert_eq!(3, profiles.sids.len());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn no_profile_list() {
+        let profiles = UserProfiles::from_software_hive("data/parser/testhive").unwrap();
+        assert!(profiles.sids.is_empty());
+    }
+}
@@ -0,0 +1,537 @@
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD as enc64};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use nt_hive::{KeyValue, NtHiveError};
+use serde_json::{Map, Value, json};
+use zerocopy::SplitByteSlice;
+
+const REG_NONE: u32 = 0;
+const REG_SZ: u32 = 1;
+const REG_EXPAND_SZ: u32 = 2;
+const REG_BINARY: u32 = 3;
+const REG_DWORD: u32 = 4;
+const REG_DWORD_BIG_ENDIAN: u32 = 5;
+const REG_LINK: u32 = 6;
+const REG_MULTI_SZ: u32 = 7;
+const REG_RESOURCE_LIST: u32 = 8;
+const REG_FULL_RESOURCE_DESCRIPTOR: u32 = 9;
+const REG_RESOURCE_REQUIREMENTS_LIST: u32 = 10;
+const REG_QWORD: u32 = 11;
+
+///
+/// Embedded dates are only detected in this range, to limit the false positives
+///
+const MIN_DETECTED_YEAR: i32 = 1990;
+const MAX_DETECTED_YEAR: i32 = 2100;
+
+///
+/// Number of seconds between the FILETIME epoch (1601-01-01) and the unix epoch
+///
+const FILETIME_UNIX_OFFSET: i64 = 11_644_473_600;
+
+///
+/// Size of the data union of a partial resource descriptor on 64 and 32 bits systems
+///
+const RESOURCE_UNION_SIZES: [usize; 2] = [16, 12];
+
+///
+/// Columns of a decoded value
+/// Value is always a scalar, the dynamic mappings of the outputs give a single type to a field:
+/// - Value: the string, the number, or the base64 encoded data
+/// - ValueHex: the data in hexadecimal, for the binary values
+/// - ValueList: the strings of a REG_MULTI_SZ, joined by new lines in Value
+/// - ValueResources: the decoded resource descriptors
+///
+pub const HIVE_VALUE: &str = "Value";
+pub const HIVE_VALUE_HEX: &str = "ValueHex";
+pub const HIVE_VALUE_LIST: &str = "ValueList";
+pub const HIVE_VALUE_RESOURCES: &str = "ValueResources";
+
+///
+/// A registry value decoded according to its type
+/// When the decoding fails, the value is kept as binary and the error is reported
+///
+#[derive(Debug, Default)]
+pub struct DecodedValue {
+    pub value_type: String,
+    pub size: u32,
+    pub columns: Map<String, Value>,
+    // a FILETIME or SYSTEMTIME found in binary or QWORD data
+    pub time: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+pub fn decode_value<B>(key_value: &KeyValue<B>) -> DecodedValue
+where
+    B: SplitByteSlice,
+{
+    let mut decoded = DecodedValue {
+        size: key_value.data_size(),
+        ..Default::default()
+    };
+    // the unknown types are kept, some keys store data in the type such as the SAM user names
+    let type_id = match key_value.data_type() {
+        Ok(data_type) => data_type as u32,
+        Err(NtHiveError::UnsupportedKeyValueDataType { actual, .. }) => actual,
+        Err(e) => {
+            decoded.error = Some(e.to_string());
+            return decoded;
+        }
+    };
+    decoded.value_type = type_name(type_id);
+
+    let data = match key_value.data().and_then(|data| data.into_vec()) {
+        Ok(data) => data,
+        Err(e) => {
+            decoded.error = Some(e.to_string());
+            return decoded;
+        }
+    };
+    match decode_data(type_id, &data) {
+        Ok((columns, time)) => {
+            decoded.columns = columns;
+            decoded.time = time;
+        }
+        Err(e) => {
+            decoded.columns = binary(&data);
+            decoded.error = Some(e);
+        }
+    }
+    decoded
+}
+
+fn type_name(type_id: u32) -> String {
+    let name = match type_id {
+        REG_NONE => "REG_NONE",
+        REG_SZ => "REG_SZ",
+        REG_EXPAND_SZ => "REG_EXPAND_SZ",
+        REG_BINARY => "REG_BINARY",
+        REG_DWORD => "REG_DWORD",
+        REG_DWORD_BIG_ENDIAN => "REG_DWORD_BIG_ENDIAN",
+        REG_LINK => "REG_LINK",
+        REG_MULTI_SZ => "REG_MULTI_SZ",
+        REG_RESOURCE_LIST => "REG_RESOURCE_LIST",
+        REG_FULL_RESOURCE_DESCRIPTOR => "REG_FULL_RESOURCE_DESCRIPTOR",
+        REG_RESOURCE_REQUIREMENTS_LIST => "REG_RESOURCE_REQUIREMENTS_LIST",
+        REG_QWORD => "REG_QWORD",
+        _ => return format!("0x{type_id:08x}"),
+    };
+    name.to_owned()
+}
+
+///
+/// The columns of a decoded value and the date it contains, if any
+///
+type DecodedData = (Map<String, Value>, Option<DateTime<Utc>>);
+
+fn decode_data(type_id: u32, data: &[u8]) -> Result<DecodedData, String> {
+    let mut columns = Map::new();
+    let mut time = None;
+    let value = match type_id {
+        // the string ends at the first NUL
+        REG_SZ | REG_EXPAND_SZ | REG_LINK => {
+            json!(utf16_strings(data)?.into_iter().next().unwrap_or_default())
+        }
+        REG_MULTI_SZ => {
+            // an empty string ends the list
+            let strings: Vec<String> = utf16_strings(data)?
+                .into_iter()
+                .take_while(|value| !value.is_empty())
+                .collect();
+            let value = json!(strings.join("\n"));
+            columns.insert(HIVE_VALUE_LIST.to_owned(), json!(strings));
+            value
+        }
+        REG_DWORD => json!(u32::from_le_bytes(fixed_size(data)?)),
+        REG_DWORD_BIG_ENDIAN => json!(u32::from_be_bytes(fixed_size(data)?)),
+        REG_QWORD => {
+            let qword = u64::from_le_bytes(fixed_size(data)?);
+            time = from_filetime(qword);
+            json!(qword)
+        }
+        REG_RESOURCE_LIST | REG_FULL_RESOURCE_DESCRIPTOR | REG_RESOURCE_REQUIREMENTS_LIST => {
+            let resources = match type_id {
+                REG_RESOURCE_LIST => resource_list(data)?,
+                REG_FULL_RESOURCE_DESCRIPTOR => full_resource_descriptor(data)?,
+                _ => requirements_list(data)?,
+            };
+            let mut columns = binary(data);
+            columns.insert(HIVE_VALUE_RESOURCES.to_owned(), resources);
+            return Ok((columns, None));
+        }
+        _ => return Ok((binary(data), detect_time(data))),
+    };
+    columns.insert(HIVE_VALUE.to_owned(), value);
+    Ok((columns, time))
+}
+
+///
+/// Binary data is stored in base64 in Value and in hexadecimal in ValueHex
+///
+fn binary(data: &[u8]) -> Map<String, Value> {
+    let mut columns = Map::new();
+    columns.insert(HIVE_VALUE.to_owned(), json!(enc64.encode(data)));
+    columns.insert(HIVE_VALUE_HEX.to_owned(), json!(hex(data)));
+    columns
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn fixed_size<const N: usize>(data: &[u8]) -> Result<[u8; N], String> {
+    data.try_into()
+        .map_err(|_| format!("expected {N} bytes, found {}", data.len()))
+}
+
+///
+/// The NUL separated UTF-16 strings, a trailing odd byte is ignored
+///
+fn utf16_strings(data: &[u8]) -> Result<Vec<String>, String> {
+    let chars: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    let mut chars = chars.as_slice();
+    // the NUL terminators are not part of the strings
+    while let Some((0, rest)) = chars.split_last() {
+        chars = rest;
+    }
+    chars
+        .split(|c| *c == 0)
+        .map(|value| String::from_utf16(value).map_err(|e| e.to_string()))
+        .collect()
+}
+
+fn from_filetime(filetime: u64) -> Option<DateTime<Utc>> {
+    let seconds = (filetime / 10_000_000) as i64 - FILETIME_UNIX_OFFSET;
+    let nanos = (filetime % 10_000_000) as u32 * 100;
+    DateTime::from_timestamp(seconds, nanos).filter(is_plausible)
+}
+
+fn from_systemtime(data: &[u8; 16]) -> Option<DateTime<Utc>> {
+    let field = |pos: usize| u16::from_le_bytes([data[pos * 2], data[pos * 2 + 1]]) as u32;
+    let (year, month, day_of_week, day) = (field(0), field(1), field(2), field(3));
+    let (hour, minute, second, millis) = (field(4), field(5), field(6), field(7));
+    if day_of_week > 6 || millis > 999 {
+        return None;
+    }
+    Utc.with_ymd_and_hms(year as i32, month, day, hour, minute, second)
+        .single()
+        .and_then(|date| date.checked_add_signed(chrono::Duration::milliseconds(millis as i64)))
+        .filter(is_plausible)
+}
+
+fn is_plausible(date: &DateTime<Utc>) -> bool {
+    (MIN_DETECTED_YEAR..=MAX_DETECTED_YEAR).contains(&date.year())
+}
+
+///
+/// A FILETIME is stored in 8 bytes, a SYSTEMTIME in 16 bytes
+///
+fn detect_time(data: &[u8]) -> Option<DateTime<Utc>> {
+    match data.len() {
+        8 => from_filetime(u64::from_le_bytes(data.try_into().ok()?)),
+        16 => from_systemtime(data.try_into().ok()?),
+        _ => None,
+    }
+}
+
+///
+/// Read little endian integers
+///
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(format!("unexpected end of data at offset {}", self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(fixed_size(self.bytes(2)?)?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(fixed_size(self.bytes(4)?)?))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(fixed_size(self.bytes(8)?)?))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+///
+/// CM_RESOURCE_LIST: a count followed by the full resource descriptors
+///
+fn resource_list(data: &[u8]) -> Result<Value, String> {
+    with_union_size(data, |reader, union_size| {
+        let count = reader.u32()?;
+        let mut list = Vec::new();
+        for _ in 0..count {
+            list.push(read_full_descriptor(reader, union_size)?);
+        }
+        Ok(json!(list))
+    })
+}
+
+fn full_resource_descriptor(data: &[u8]) -> Result<Value, String> {
+    with_union_size(data, read_full_descriptor)
+}
+
+///
+/// The size of the descriptors depends on the system that wrote them:
+/// the first size that reads the whole data is used
+///
+fn with_union_size(
+    data: &[u8],
+    read: impl Fn(&mut Reader, usize) -> Result<Value, String>,
+) -> Result<Value, String> {
+    let mut first = None;
+    for union_size in RESOURCE_UNION_SIZES {
+        let mut reader = Reader::new(data);
+        let result = read(&mut reader, union_size);
+        if result.is_ok() && reader.is_empty() {
+            return result;
+        }
+        first.get_or_insert(result);
+    }
+    first.unwrap_or(Err("no resource descriptor".to_owned()))
+}
+
+///
+/// CM_FULL_RESOURCE_DESCRIPTOR
+///
+fn read_full_descriptor(reader: &mut Reader, union_size: usize) -> Result<Value, String> {
+    let interface_type = reader.u32()? as i32;
+    let bus_number = reader.u32()?;
+    let version = reader.u16()?;
+    let revision = reader.u16()?;
+    let count = reader.u32()?;
+    let mut resources = Vec::new();
+    for _ in 0..count {
+        resources.push(read_partial_descriptor(reader, union_size)?);
+    }
+    Ok(json!({
+        "InterfaceType": interface_type,
+        "BusNumber": bus_number,
+        "Version": version,
+        "Revision": revision,
+        "Resources": resources,
+    }))
+}
+
+///
+/// CM_PARTIAL_RESOURCE_DESCRIPTOR
+///
+fn read_partial_descriptor(reader: &mut Reader, union_size: usize) -> Result<Value, String> {
+    let resource_type = reader.u8()?;
+    let share_disposition = reader.u8()?;
+    let flags = reader.u16()?;
+    let mut union = Reader::new(reader.bytes(union_size)?);
+
+    let mut resource = Map::new();
+    resource.insert("Type".to_owned(), json!(resource_type_name(resource_type)));
+    resource.insert("ShareDisposition".to_owned(), json!(share_disposition));
+    resource.insert("Flags".to_owned(), json!(flags));
+    match resource_type {
+        // Port, Memory, MemoryLarge
+        1 | 3 | 7 => {
+            resource.insert("Start".to_owned(), json!(union.u64()?));
+            resource.insert("Length".to_owned(), json!(union.u32()?));
+        }
+        // Interrupt
+        2 => {
+            resource.insert("Level".to_owned(), json!(union.u32()?));
+            resource.insert("Vector".to_owned(), json!(union.u32()?));
+            let affinity = if union_size == 16 {
+                union.u64()?
+            } else {
+                union.u32()? as u64
+            };
+            resource.insert("Affinity".to_owned(), json!(affinity));
+        }
+        // Dma
+        4 => {
+            resource.insert("Channel".to_owned(), json!(union.u32()?));
+            resource.insert("Port".to_owned(), json!(union.u32()?));
+        }
+        // DeviceSpecific, the data follows the descriptor
+        5 => {
+            let size = union.u32()? as usize;
+            resource.insert("Data".to_owned(), json!(hex(reader.bytes(size)?)));
+        }
+        // BusNumber
+        6 => {
+            resource.insert("Start".to_owned(), json!(union.u32()?));
+            resource.insert("Length".to_owned(), json!(union.u32()?));
+        }
+        _ => {
+            resource.insert("Data".to_owned(), json!(hex(union.data)));
+        }
+    }
+    Ok(Value::Object(resource))
+}
+
+fn resource_type_name(resource_type: u8) -> String {
+    let name = match resource_type {
+        0 => "Null",
+        1 => "Port",
+        2 => "Interrupt",
+        3 => "Memory",
+        4 => "Dma",
+        5 => "DeviceSpecific",
+        6 => "BusNumber",
+        7 => "MemoryLarge",
+        128 => "ConfigData",
+        129 => "DevicePrivate",
+        130 => "PcCardConfig",
+        131 => "MfCardConfig",
+        _ => return resource_type.to_string(),
+    };
+    name.to_owned()
+}
+
+///
+/// IO_RESOURCE_REQUIREMENTS_LIST: the header is decoded, the alternative lists are only kept in the binary columns
+///
+fn requirements_list(data: &[u8]) -> Result<Value, String> {
+    let mut reader = Reader::new(data);
+    let list_size = reader.u32()?;
+    let interface_type = reader.u32()? as i32;
+    let bus_number = reader.u32()?;
+    let slot_number = reader.u32()?;
+    reader.bytes(12)?;
+    let alternative_lists = reader.u32()?;
+
+    Ok(json!({
+        "ListSize": list_size,
+        "InterfaceType": interface_type,
+        "BusNumber": bus_number,
+        "SlotNumber": slot_number,
+        "AlternativeLists": alternative_lists,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(value: &str) -> Vec<u8> {
+        value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn strings() {
+        let (columns, _) = decode_data(REG_SZ, &utf16("C:\\Windows\0")).unwrap();
+        assert_eq!(json!({"Value": "C:\\Windows"}), Value::Object(columns));
+
+        let (columns, _) = decode_data(REG_MULTI_SZ, &utf16("first\0second\0\0")).unwrap();
+        assert_eq!(
+            json!({"Value": "first\nsecond", "ValueList": ["first", "second"]}),
+            Value::Object(columns)
+        );
+
+        let (columns, _) = decode_data(REG_LINK, &utf16("\\Registry\\Machine")).unwrap();
+        assert_eq!(json!("\\Registry\\Machine"), columns[HIVE_VALUE]);
+
+        // unpaired surrogate
+        decode_data(REG_SZ, &[0x00, 0xd8, 0x41, 0x00]).expect_err("invalid utf-16");
+    }
+
+    #[test]
+    fn numbers() {
+        let (columns, _) = decode_data(REG_DWORD, &[42, 0, 0, 0]).unwrap();
+        assert_eq!(json!(42), columns[HIVE_VALUE]);
+        let (columns, _) = decode_data(REG_DWORD_BIG_ENDIAN, &[0, 0, 0, 42]).unwrap();
+        assert_eq!(json!(42), columns[HIVE_VALUE]);
+        decode_data(REG_DWORD, &[42, 0]).expect_err("too short");
+
+        // 2024-01-02 10:12:13 UTC as a FILETIME
+        let filetime: u64 = (1_704_190_333 + FILETIME_UNIX_OFFSET as u64) * 10_000_000;
+        let (columns, time) = decode_data(REG_QWORD, &filetime.to_le_bytes()).unwrap();
+        assert_eq!(json!(filetime), columns[HIVE_VALUE]);
+        assert_eq!(1_704_190_333, time.unwrap().timestamp());
+
+        let (_, time) = decode_data(REG_QWORD, &1u64.to_le_bytes()).unwrap();
+        assert!(time.is_none());
+    }
+
+    #[test]
+    fn binaries() {
+        let (columns, time) = decode_data(REG_BINARY, &[0xde, 0xad, 0xbe, 0xef]).unwrap();
+        assert_eq!(
+            json!({"Value": "3q2-7w", "ValueHex": "deadbeef"}),
+            Value::Object(columns)
+        );
+        assert!(time.is_none());
+
+        // 2024-01-02 10:12:13.500, a tuesday, as a SYSTEMTIME
+        let systemtime: Vec<u8> = [2024u16, 1, 2, 2, 10, 12, 13, 500]
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .collect();
+        let (_, time) = decode_data(REG_NONE, &systemtime).unwrap();
+        assert_eq!(1_704_190_333_500, time.unwrap().timestamp_millis());
+
+        assert_eq!("0x00000201", type_name(0x201));
+        let (columns, _) = decode_data(0x201, &[]).unwrap();
+        assert_eq!(json!({"Value": "", "ValueHex": ""}), Value::Object(columns));
+    }
+
+    #[test]
+    fn resources() {
+        // one full descriptor on the internal bus with a port and an interrupt, 64 bits layout
+        let mut data = Vec::new();
+        data.extend(1u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(2u32.to_le_bytes());
+        data.extend([1, 1, 0x11, 0]);
+        data.extend(0x3f8u64.to_le_bytes());
+        data.extend(8u32.to_le_bytes());
+        data.extend([0; 4]);
+        data.extend([2, 1, 0, 0]);
+        data.extend(4u32.to_le_bytes());
+        data.extend(36u32.to_le_bytes());
+        data.extend(u64::MAX.to_le_bytes());
+
+        let (columns, _) = decode_data(REG_RESOURCE_LIST, &data).unwrap();
+        // the value stays a scalar, the descriptors are in their own column
+        assert!(columns[HIVE_VALUE].is_string());
+        let resources = &columns[HIVE_VALUE_RESOURCES][0]["Resources"];
+        assert_eq!("Port", resources[0]["Type"]);
+        assert_eq!(0x3f8, resources[0]["Start"]);
+        assert_eq!(8, resources[0]["Length"]);
+        assert_eq!("Interrupt", resources[1]["Type"]);
+        assert_eq!(36, resources[1]["Vector"]);
+        assert_eq!(u64::MAX, resources[1]["Affinity"]);
+
+        let (columns, _) = decode_data(REG_FULL_RESOURCE_DESCRIPTOR, &data[4..]).unwrap();
+        assert_eq!(
+            "Port",
+            columns[HIVE_VALUE_RESOURCES]["Resources"][0]["Type"]
+        );
+
+        decode_data(REG_RESOURCE_LIST, &data[..10]).expect_err("truncated");
+    }
+}
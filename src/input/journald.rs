@@ -0,0 +1,236 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Seek},
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value, json};
+
+use crate::{
+    Error,
+    configuration::DataType,
+    output::{Fields, Output, OutputConfig, Tuple},
+};
+
+use super::text_file::{BUFFER_CAPACITY, format_date};
+
+pub const JOURNALD_TABLE_NAME: &str = "journald";
+pub const JOURNALD_SORT_FIELD: &str = JOURNALD_DATE;
+
+const JOURNALD_DATE: &str = "TimeStamp";
+const JOURNALD_REALTIME: &str = "__REALTIME_TIMESTAMP";
+
+///
+/// Fields that are always numbers
+///
+const JOURNALD_INTEGER_FIELDS: [&str; 7] = [
+    "PRIORITY",
+    "SYSLOG_FACILITY",
+    "SYSLOG_PID",
+    "_PID",
+    "_UID",
+    "_GID",
+    "__MONOTONIC_TIMESTAMP",
+];
+
+pub fn journald_fields() -> Vec<(String, DataType)> {
+    vec![
+        (JOURNALD_DATE.to_owned(), DataType::Date),
+        ("_HOSTNAME".to_owned(), DataType::String),
+        ("SYSLOG_IDENTIFIER".to_owned(), DataType::String),
+        ("_SYSTEMD_UNIT".to_owned(), DataType::String),
+        ("_COMM".to_owned(), DataType::String),
+        ("_PID".to_owned(), DataType::Int64),
+        ("_UID".to_owned(), DataType::Int64),
+        ("PRIORITY".to_owned(), DataType::Int64),
+        ("MESSAGE".to_owned(), DataType::String),
+    ]
+}
+
+///
+/// Parse a systemd journal export file, as created by 'journalctl -o export'
+/// https://systemd.io/JOURNAL_EXPORT_FORMATS/
+///
+pub fn parse_journald<P: AsRef<Path>>(
+    path: P,
+    client_context: &str,
+    fields: &Fields,
+    output_config: &[OutputConfig],
+) -> Result<usize, Error> {
+    let mut output = Output::new(
+        output_config,
+        &fields.archive_name,
+        &fields.archive_file,
+        client_context,
+        JOURNALD_TABLE_NAME,
+    )?;
+
+    parse(path, fields, &mut output)?;
+    output.complete()?;
+    Ok(output.num_rows())
+}
+
+///
+/// Entries are separated by an empty line
+/// - text fields are written as: FIELD=value\n
+/// - binary fields are written as: FIELD\n, followed by the little endian 64bits size, the data and \n
+///
+/// The size of a binary field cannot exceed the rest of the file, a corrupted size is not allocated
+///
+fn parse<P: AsRef<Path>>(path: P, fields: &Fields, output: &mut Output) -> Result<(), Error> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::with_capacity(BUFFER_CAPACITY, file);
+    let mut entry = Map::new();
+    let mut line = Vec::new();
+    // line numbers start at 0, as in the csv and text parsers
+    for line_nb in 0.. {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if line.ends_with(b"\n") {
+            line.pop();
+        }
+        if line.is_empty() {
+            write_entry(entry, fields, output)?;
+            entry = Map::new();
+            continue;
+        }
+
+        let (name, value) = match line.iter().position(|c| *c == b'=') {
+            Some(pos) => (
+                String::from_utf8_lossy(&line[..pos]).to_string(),
+                String::from_utf8_lossy(&line[pos + 1..]).to_string(),
+            ),
+            None => {
+                let mut size = [0; 8];
+                reader.read_exact(&mut size)?;
+                let size = u64::from_le_bytes(size);
+                let remaining = file_len.saturating_sub(reader.stream_position()?);
+                if size > remaining {
+                    return Err(Error::LineParsing(
+                        line_nb,
+                        format!(
+                            "binary field size {size} exceeds the {remaining} remaining bytes of the file"
+                        ),
+                    ));
+                }
+                let mut value = vec![0; size as usize];
+                reader.read_exact(&mut value)?;
+                let mut end_of_line = [0; 1];
+                reader.read_exact(&mut end_of_line)?;
+                (
+                    String::from_utf8_lossy(&line).to_string(),
+                    String::from_utf8_lossy(&value).to_string(),
+                )
+            }
+        };
+
+        let value = if JOURNALD_INTEGER_FIELDS.contains(&name.as_str()) {
+            match value.parse::<i64>() {
+                Ok(v) => json!(v),
+                Err(_) => json!(value),
+            }
+        } else {
+            json!(value)
+        };
+        entry.insert(name, value);
+    }
+    write_entry(entry, fields, output)?;
+    Ok(())
+}
+
+fn write_entry(
+    mut entry: Map<String, Value>,
+    fields: &Fields,
+    output: &mut Output,
+) -> Result<(), Error> {
+    if entry.is_empty() {
+        return Ok(());
+    }
+    let realtime = entry
+        .get(JOURNALD_REALTIME)
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse::<i64>().ok())
+        .and_then(DateTime::<Utc>::from_timestamp_micros);
+
+    let sort_data = match realtime {
+        Some(date) => {
+            entry.insert(JOURNALD_DATE.to_owned(), json!(format_date(&date)));
+            Some(date.timestamp())
+        }
+        None => None,
+    };
+
+    let mut tuple = Tuple::new(fields);
+    tuple.set_data(Value::Object(entry), sort_data)?;
+    output.write(tuple)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init_log, writer::file_writer::MemoryWriter};
+
+    use super::*;
+
+    pub const JOURNAL_PATH: &str = "data/parser/journal.export";
+
+    #[test]
+    fn parse_export() {
+        init_log();
+        let fields = Fields::new(
+            "mymachine",
+            "journal.export",
+            "mymachine_ORC.7z",
+            "journal.export",
+        );
+
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+
+        parse(JOURNAL_PATH, &fields, &mut output).unwrap();
+        assert_eq!(2, output.num_rows());
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[0]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!("2024-01-02 10:14:15.003", data["TimeStamp"]);
+        assert_eq!("srv01", data["_HOSTNAME"]);
+        assert_eq!(1234, data["_PID"]);
+        assert_eq!(6, data["PRIORITY"]);
+        assert_eq!("Started Session 1 of User bob.", data["MESSAGE"]);
+
+        //binary field
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[1]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!("first line\nsecond line", data["MESSAGE"]);
+        assert_eq!("sshd", data["SYSLOG_IDENTIFIER"]);
+    }
+
+    #[test]
+    fn corrupted_size() {
+        let folder = "data/temp/journald";
+        std::fs::create_dir_all(folder).unwrap();
+        let path = format!("{folder}/corrupted.export");
+        let mut content = b"_HOSTNAME=srv01\nMESSAGE\n".to_vec();
+        content.extend_from_slice(&u64::MAX.to_le_bytes());
+        content.extend_from_slice(b"short\n\n");
+        std::fs::write(&path, content).unwrap();
+
+        let fields = Fields::new("mymachine", "journal.export", "archive", "journal.export");
+        let mut output = Output {
+            list: vec![Box::new(MemoryWriter::new(10))],
+            num_rows: 0,
+        };
+        match parse(&path, &fields, &mut output) {
+            Err(Error::LineParsing(line, _)) => assert_eq!(1, line),
+            _ => panic!("the corrupted size must be rejected"),
+        }
+    }
+}
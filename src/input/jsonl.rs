@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::{
+    errors::Error,
+    output::{Fields, Output, OutputConfig, Tuple},
+};
+
+use super::{
+    parse_errors::ParseErrors,
+    record_mapping::{RecordConverter, RecordMapping},
+    text_file::for_each_line,
+};
+
+///
+/// Parse a json lines file (one json object per line)
+/// Velociraptor, Hayabusa or EZTools --json outputs
+///
+pub fn parse_jsonl<P, M>(
+    path: P,
+    client_context: &str,
+    fields: &Fields,
+    output_config: &[OutputConfig],
+    mapping_path: M,
+    best_effort: bool,
+) -> Result<usize, Error>
+where
+    P: AsRef<Path>,
+    M: AsRef<Path>,
+{
+    let mapping = RecordMapping::load(mapping_path)?;
+    let converter = RecordConverter::new(&mapping);
+
+    let mut output = Output::new(
+        output_config,
+        &fields.archive_name,
+        &fields.archive_file,
+        client_context,
+        &mapping.topic,
+    )?;
+
+    let mut errors = ParseErrors::new(
+        best_effort,
+        &mapping.topic,
+        fields,
+        client_context,
+        output_config,
+    );
+
+    parse(path, fields, &converter, &mut output, &mut errors)?;
+    errors.complete()?;
+    output.complete()?;
+    Ok(output.num_rows())
+}
+
+fn parse<P: AsRef<Path>>(
+    path: P,
+    fields: &Fields,
+    converter: &RecordConverter,
+    output: &mut Output,
+    errors: &mut ParseErrors,
+) -> Result<(), Error> {
+    for_each_line(path, |line_nb, line| {
+        // a BOM can be found at the beginning of files written on windows
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        let result = serde_json::from_str::<Value>(line)
+            .map_err(|e| Error::LineParsing(line_nb, e.to_string()))
+            .and_then(|record| converter.convert(record, line_nb));
+        match result {
+            Ok((data, sort_data)) => {
+                let mut tuple = Tuple::new(fields);
+                tuple.set_data(data, sort_data)?;
+                output.write(tuple)?;
+            }
+            Err(err) => errors.reject(err, line_nb, line)?,
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init_log, writer::file_writer::MemoryWriter};
+
+    use super::*;
+
+    pub const DATA_PATH: &str = "data/parser/hayabusa.jsonl";
+    pub const MAPPING_PATH: &str = "data/hayabusa.map.yaml";
+
+    #[test]
+    fn parse_records() {
+        init_log();
+        let fields = Fields::new(
+            "mymachine",
+            "hayabusa",
+            "mymachine_ORC.7z",
+            "hayabusa.jsonl",
+        );
+
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+
+        let mapping = RecordMapping::load(MAPPING_PATH).unwrap();
+        let converter = RecordConverter::new(&mapping);
+        let error_output = MemoryWriter::new(10);
+        let error_buffer = error_output.get_buffer();
+        let error_output = Output {
+            list: vec![Box::new(error_output)],
+            num_rows: 0,
+        };
+        let mut errors = ParseErrors::with_output(true, &mapping.topic, &fields, error_output);
+        parse(DATA_PATH, &fields, &converter, &mut output, &mut errors).unwrap();
+        assert_eq!(2, output.num_rows());
+        assert_eq!(1, errors.num_errors());
+
+        let json: serde_json::Value = serde_json::from_str(&error_buffer.borrow()[0]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!(2, data["Line"]);
+        assert_eq!("LineParsing", data["Kind"]);
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[0]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!("2024-01-02 10:12:13.456", data["Timestamp"]);
+        assert_eq!(4624, data["EventID"]);
+        assert_eq!("10.0.0.2", data["Details.SrcIP"]);
+
+        //the invalid line is not skipped
+        let mut errors = ParseErrors::new(false, &mapping.topic, &fields, "", &[]);
+        parse(DATA_PATH, &fields, &converter, &mut output, &mut errors).expect_err("invalid json");
+    }
+}
@@ -1,6 +1,22 @@
 pub mod csv;
 pub mod csv_mapping;
+pub mod csv_mapping_generator;
+pub mod csv_transform;
 pub mod evtx;
+pub mod grok;
 pub mod hive;
+pub mod hive_profiles;
+pub mod hive_value;
+pub mod journald;
+pub mod jsonl;
+pub mod parse_errors;
+pub mod record_mapping;
+pub mod regex_log;
+pub mod regex_log_mapping;
+pub mod setupapi;
 pub mod srum;
 pub mod srum_model;
+pub mod syslog;
+pub mod text_file;
+pub mod w3c;
+pub mod xml_records;
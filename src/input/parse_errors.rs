@@ -0,0 +1,224 @@
+use log::error;
+use serde_json::json;
+
+use crate::{
+    configuration::DataType,
+    errors::Error,
+    output::{Fields, Output, OutputConfig, Tuple},
+};
+
+pub const PARSE_ERRORS_TABLE_NAME: &str = "parse_errors";
+
+///
+/// Field definition of the parse_errors topic
+/// The archive and the file are stored in the common metadata fields
+///
+pub fn parse_errors_fields() -> Vec<(String, DataType)> {
+    vec![
+        ("Topic".to_string(), DataType::String),
+        ("Line".to_string(), DataType::Int64),
+        ("Column".to_string(), DataType::Int64),
+        ("ColumnName".to_string(), DataType::String),
+        ("Kind".to_string(), DataType::String),
+        ("Message".to_string(), DataType::String),
+        ("RawContent".to_string(), DataType::String),
+    ]
+}
+
+///
+/// Handle the lines or records rejected by a parser
+/// - in best effort mode, the error is logged and written to the parse_errors topic
+/// - otherwise, the error is returned and stops the parsing
+///
+/// The output is only created when the first error occurs, to avoid empty files
+///
+pub struct ParseErrors<'a> {
+    best_effort: bool,
+    topic: String,
+    fields: &'a Fields,
+    client_context: &'a str,
+    output_config: &'a [OutputConfig],
+    output: Option<Output>,
+}
+impl<'a> ParseErrors<'a> {
+    pub fn new(
+        best_effort: bool,
+        topic: &str,
+        fields: &'a Fields,
+        client_context: &'a str,
+        output_config: &'a [OutputConfig],
+    ) -> Self {
+        Self {
+            best_effort,
+            topic: topic.to_owned(),
+            fields,
+            client_context,
+            output_config,
+            output: None,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_output(best_effort: bool, topic: &str, fields: &'a Fields, output: Output) -> Self {
+        Self {
+            best_effort,
+            topic: topic.to_owned(),
+            fields,
+            client_context: "",
+            output_config: &[],
+            output: Some(output),
+        }
+    }
+
+    ///
+    /// The topic the rejected data was intended to, for parsers writing several topics
+    ///
+    pub fn set_topic(&mut self, topic: &str) {
+        self.topic = topic.to_owned();
+    }
+
+    ///
+    /// Reject a line or a record.
+    /// line_nb is the line number for text files and the record number otherwise
+    ///
+    pub fn reject(&mut self, error: Error, line_nb: usize, raw_content: &str) -> Result<(), Error> {
+        if !self.best_effort {
+            return Err(error);
+        }
+        error!("Skipping Line {line_nb} caused by error: {error}");
+
+        let (column, column_name) = match &error {
+            Error::CsvParsing(_, column, name, _) | Error::CsvMandatoryField(_, column, name) => {
+                (Some(*column), Some(name.as_str()))
+            }
+            _ => (None, None),
+        };
+        let data = json!({
+            "Topic": self.topic,
+            "Line": line_nb,
+            "Column": column,
+            "ColumnName": column_name,
+            "Kind": error_kind(&error),
+            "Message": error.to_string(),
+            "RawContent": raw_content,
+        });
+
+        if self.output.is_none() {
+            // the error file must not overwrite the data file
+            let file_name = format!("{}.{PARSE_ERRORS_TABLE_NAME}", self.fields.archive_file);
+            self.output = Some(Output::new(
+                self.output_config,
+                &self.fields.archive_name,
+                &file_name,
+                self.client_context,
+                PARSE_ERRORS_TABLE_NAME,
+            )?);
+        }
+        let mut tuple = Tuple::new(self.fields);
+        tuple.set_data(data, None)?;
+        if let Some(output) = &mut self.output {
+            output.write(tuple)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Called once the file is completely parsed
+    ///
+    pub fn complete(&mut self) -> Result<(), Error> {
+        match &mut self.output {
+            Some(output) => output.complete(),
+            None => Ok(()),
+        }
+    }
+
+    ///
+    /// returns the number of rejected lines or records
+    ///
+    pub fn num_errors(&self) -> usize {
+        self.output.as_ref().map_or(0, |output| output.num_rows())
+    }
+}
+
+///
+/// A stable name for the kind of error, used to query the data quality
+///
+fn error_kind(error: &Error) -> &'static str {
+    match error {
+        Error::CsvParsing(..) => "CsvParsing",
+        Error::CsvMandatoryField(..) => "CsvMandatoryField",
+        Error::Csv(_) => "Csv",
+        Error::LineParsing(..) => "LineParsing",
+        Error::SerdeJson(_) => "Json",
+        Error::Xml(_) => "Xml",
+        Error::EvtxError(_) | Error::Evtx(_) => "Evtx",
+        Error::JsonNotAndObject(_) | Error::JsonNotaString(_) => "JsonStructure",
+        Error::ParseInt(_)
+        | Error::ParseFloat(_)
+        | Error::ParseDate(_)
+        | Error::ParseAddr(_)
+        | Error::InvalidValue(..) => "InvalidValue",
+        Error::Io(_) => "Io",
+        _ => "Other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init_log, writer::file_writer::MemoryWriter};
+
+    use super::*;
+
+    #[test]
+    fn reject() {
+        init_log();
+        let fields = Fields::new(
+            "mymachine",
+            "NTFSInfo.csv",
+            "mymachine_ORC.7z",
+            "NTFSInfo.csv",
+        );
+
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+        let mut errors = ParseErrors::with_output(true, "ntfs_info", &fields, output);
+        errors
+            .reject(
+                Error::CsvParsing(3, 2, "SizeInBytes".to_owned(), "invalid digit".to_owned()),
+                3,
+                "a,b,c",
+            )
+            .unwrap();
+        errors
+            .reject(Error::LineParsing(5, "truncated".to_owned()), 5, "")
+            .unwrap();
+        assert_eq!(2, errors.num_errors());
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[0]).unwrap();
+        assert_eq!("NTFSInfo.csv", json["original_file"]);
+        assert_eq!("mymachine_ORC.7z", json["archive_name"]);
+        let data = json.get("data").unwrap();
+        assert_eq!("ntfs_info", data["Topic"]);
+        assert_eq!(3, data["Line"]);
+        assert_eq!(2, data["Column"]);
+        assert_eq!("SizeInBytes", data["ColumnName"]);
+        assert_eq!("CsvParsing", data["Kind"]);
+        assert_eq!("a,b,c", data["RawContent"]);
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[1]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!("LineParsing", data["Kind"]);
+        assert!(data["Column"].is_null());
+
+        //without best effort, the error is returned
+        let mut errors = ParseErrors::new(false, "ntfs_info", &fields, "", &[]);
+        errors
+            .reject(Error::LineParsing(1, "invalid".to_owned()), 1, "")
+            .expect_err("not best effort");
+        assert_eq!(0, errors.num_errors());
+    }
+}
@@ -0,0 +1,214 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{configuration::DataType, errors::Error};
+
+use super::csv::{Converter, DateFormat, FieldType};
+
+const DEFAULT_SEPARATOR: &str = ".";
+
+///
+/// Defines how structured records (json lines, xml elements) are converted
+/// Declared fields are converted with their type, the other fields are kept as is
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RecordMapping {
+    #[serde(default)]
+    pub topic: String,
+
+    #[serde(default)]
+    pub sort_field: Option<String>,
+
+    // nested objects are flattened: {"a":{"b":1}} becomes {"a.b":1}
+    #[serde(default)]
+    pub flatten: bool,
+
+    // separator used to build the flattened field names, defaults to '.'
+    #[serde(default)]
+    pub flatten_separator: Option<String>,
+
+    // xml only: name of the elements holding a record
+    #[serde(default)]
+    pub record_element: Option<String>,
+
+    //Default date format
+    #[serde(default)]
+    pub default_date_format: DateFormat,
+
+    //mapping for each field, flattened fields are referenced with their flattened name
+    #[serde(default)]
+    pub fields: HashMap<String, FieldType>,
+}
+impl RecordMapping {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        if !path.as_ref().exists() {
+            return Err(Error::RecordConfiguration(format!(
+                "file does not exists: {}",
+                path.as_ref().to_string_lossy()
+            )));
+        }
+        let yaml_file: String = fs::read_to_string(path)?;
+
+        let conf = serde_yml::from_str::<RecordMapping>(&yaml_file)
+            .map_err(|e| Error::RecordConfiguration(e.to_string()))?;
+        Ok(conf)
+    }
+
+    pub fn partial_fields(&self) -> Vec<(String, DataType)> {
+        self.fields
+            .iter()
+            .map(|(name, field_type)| (name.to_owned(), field_type.data_type()))
+            .collect()
+    }
+}
+
+///
+/// Convert a json record according to a mapping
+///
+pub struct RecordConverter {
+    flatten_separator: Option<String>,
+    converters: Vec<Converter>,
+    sort_field: Option<String>,
+}
+impl RecordConverter {
+    pub fn new(mapping: &RecordMapping) -> Self {
+        let flatten_separator = mapping.flatten.then(|| {
+            mapping
+                .flatten_separator
+                .clone()
+                .unwrap_or(DEFAULT_SEPARATOR.to_owned())
+        });
+        let converters = mapping
+            .fields
+            .iter()
+            .map(|(name, field_type)| {
+                Converter::new(name, Some(field_type), &mapping.default_date_format)
+            })
+            .collect();
+        Self {
+            flatten_separator,
+            converters,
+            sort_field: mapping.sort_field.clone(),
+        }
+    }
+
+    ///
+    /// Convert a record to the output json object and its sort data
+    ///
+    pub fn convert(&self, record: Value, record_nb: usize) -> Result<(Value, Option<i64>), Error> {
+        let Value::Object(record) = record else {
+            return Err(Error::LineParsing(
+                record_nb,
+                "the record is not an object".to_owned(),
+            ));
+        };
+        let mut map = match &self.flatten_separator {
+            Some(separator) => {
+                let mut map = Map::with_capacity(record.len());
+                flatten("", record, separator, &mut map);
+                map
+            }
+            None => record,
+        };
+
+        let mut sort_data = None;
+        for conv in &self.converters {
+            let text = match map.get(&conv.field_name) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(value)) => value.to_owned(),
+                Some(value) => value.to_string(),
+            };
+            if text.is_empty() {
+                if conv.mandatory {
+                    return Err(Error::LineParsing(
+                        record_nb,
+                        format!("field '{}' is mandatory", conv.field_name),
+                    ));
+                }
+                //do not insert empty fields
+                map.remove(&conv.field_name);
+                continue;
+            }
+            let value = conv.to_json_value(&text).map_err(|e| {
+                Error::LineParsing(
+                    record_nb,
+                    format!("field '{}' parsing error: {e}", conv.field_name),
+                )
+            })?;
+            if self.sort_field.as_ref() == Some(&conv.field_name) {
+                sort_data = Some(conv.to_sort_data(&text)?);
+            }
+            map.insert(conv.field_name.clone(), value);
+        }
+        Ok((Value::Object(map), sort_data))
+    }
+}
+
+fn flatten(
+    prefix: &str,
+    object: Map<String, Value>,
+    separator: &str,
+    result: &mut Map<String, Value>,
+) {
+    for (key, value) in object {
+        let name = if prefix.is_empty() {
+            key
+        } else {
+            format!("{prefix}{separator}{key}")
+        };
+        match value {
+            Value::Object(child) => flatten(&name, child, separator, result),
+            value => {
+                result.insert(name, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn convert_record() {
+        let mut mapping = RecordMapping {
+            sort_field: Some("Event.TimeCreated".to_owned()),
+            flatten: true,
+            ..Default::default()
+        };
+        mapping.fields.insert(
+            "Event.TimeCreated".to_owned(),
+            FieldType::Date {
+                mandatory: true,
+                input_date_format: None,
+            },
+        );
+        mapping.fields.insert(
+            "Event.EventID".to_owned(),
+            FieldType::Integer { mandatory: false },
+        );
+        let converter = RecordConverter::new(&mapping);
+
+        let record = json!({
+            "Computer": "mymachine",
+            "Event": {"TimeCreated": "2024-01-02T10:12:13Z", "EventID": "4624", "Tags": ["a", "b"]}
+        });
+        let (data, sort_data) = converter.convert(record, 0).unwrap();
+        assert_eq!(
+            r#"{"Computer":"mymachine","Event.TimeCreated":"2024-01-02 10:12:13.000","Event.EventID":4624,"Event.Tags":["a","b"]}"#,
+            serde_json::to_string(&data).unwrap()
+        );
+        assert_eq!(Some(1704190333000), sort_data);
+
+        converter
+            .convert(json!({"Event": {"EventID": 1}}), 1)
+            .expect_err("mandatory date");
+        converter
+            .convert(json!(["not", "an", "object"]), 2)
+            .expect_err("not an object");
+    }
+}
@@ -0,0 +1,299 @@
+use std::path::Path;
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::{
+    errors::Error,
+    output::{Fields, Output, OutputConfig, Tuple},
+};
+
+use super::{
+    csv::Converter,
+    parse_errors::ParseErrors,
+    regex_log_mapping::{Multiline, RegexLogMapping},
+    text_file::for_each_line,
+};
+
+///
+/// Parse a log file with a regex or a grok expression
+///
+pub fn parse_regex_log<P, M>(
+    path: P,
+    client_context: &str,
+    fields: &Fields,
+    output_config: &[OutputConfig],
+    mapping_path: M,
+    best_effort: bool,
+    skip_lines: usize,
+) -> Result<usize, Error>
+where
+    P: AsRef<Path>,
+    M: AsRef<Path>,
+{
+    let mapping = RegexLogMapping::load(mapping_path)?;
+    let parser = RegexLogParser::new(&mapping)?;
+
+    let mut output = Output::new(
+        output_config,
+        &fields.archive_name,
+        &fields.archive_file,
+        client_context,
+        &mapping.topic,
+    )?;
+
+    let mut errors = ParseErrors::new(
+        best_effort,
+        &mapping.topic,
+        fields,
+        client_context,
+        output_config,
+    );
+
+    parser.parse(path, fields, &mut output, &mut errors, skip_lines)?;
+
+    errors.complete()?;
+    output.complete()?;
+    Ok(output.num_rows())
+}
+
+///
+/// A record and the number of its first line
+///
+type Record = (usize, String);
+
+struct RegexLogParser {
+    line_regex: Regex,
+    multiline: Option<(Multiline, Regex)>,
+    // the converter of each capture group, the first group being the whole match
+    converters: Vec<Option<Converter>>,
+    sort_group: Option<usize>,
+}
+impl RegexLogParser {
+    fn new(mapping: &RegexLogMapping) -> Result<Self, Error> {
+        let line_regex = mapping.regex()?;
+        let multiline = match &mapping.multiline {
+            Some(multiline) => {
+                let regex = match multiline {
+                    Multiline::Start(regex) | Multiline::Continuation(regex) => Regex::new(regex)?,
+                };
+                Some((multiline.clone(), regex))
+            }
+            None => None,
+        };
+
+        let mut converters = Vec::with_capacity(line_regex.captures_len());
+        let mut sort_group = None;
+        for (group, name) in line_regex.capture_names().enumerate() {
+            let converter = name.map(|name| {
+                if mapping.sort_field.as_deref() == Some(name) {
+                    sort_group = Some(group);
+                }
+                Converter::new(name, mapping.fields.get(name), &mapping.default_date_format)
+            });
+            converters.push(converter);
+        }
+        if let Some(sort_field) = &mapping.sort_field
+            && sort_group.is_none()
+        {
+            return Err(Error::RegexLogConfiguration(format!(
+                "topic '{}': the sort field '{sort_field}' is not a capture group of the line regex",
+                mapping.topic
+            )));
+        }
+
+        Ok(Self {
+            line_regex,
+            multiline,
+            converters,
+            sort_group,
+        })
+    }
+
+    fn parse<P: AsRef<Path>>(
+        &self,
+        path: P,
+        fields: &Fields,
+        output: &mut Output,
+        errors: &mut ParseErrors,
+        skip_lines: usize,
+    ) -> Result<(), Error> {
+        let mut current: Option<Record> = None;
+        for_each_line(path, |line_nb, line| {
+            if line_nb < skip_lines {
+                return Ok(());
+            }
+            let is_new_record = match &self.multiline {
+                None => true,
+                Some((Multiline::Start(_), regex)) => regex.is_match(line),
+                Some((Multiline::Continuation(_), regex)) => !regex.is_match(line),
+            };
+
+            if !is_new_record && let Some((_, record)) = current.as_mut() {
+                record.push('\n');
+                record.push_str(line);
+                return Ok(());
+            }
+            if let Some(record) = current.take() {
+                self.write_record(record, fields, output, errors)?;
+            }
+            current = Some((line_nb, line.to_owned()));
+            Ok(())
+        })?;
+
+        if let Some(record) = current.take() {
+            self.write_record(record, fields, output, errors)?;
+        }
+        Ok(())
+    }
+
+    fn write_record(
+        &self,
+        record: Record,
+        fields: &Fields,
+        output: &mut Output,
+        errors: &mut ParseErrors,
+    ) -> Result<(), Error> {
+        let (line_nb, text) = record;
+        if text.trim().is_empty() {
+            return Ok(());
+        }
+        match self.convert_record(&text, line_nb) {
+            Ok((data, sort_data)) => {
+                let mut tuple = Tuple::new(fields);
+                tuple.set_data(data, sort_data)?;
+                output.write(tuple)?;
+            }
+            Err(err) => errors.reject(err, line_nb, &text)?,
+        }
+        Ok(())
+    }
+
+    ///
+    /// Convert a record to a json object
+    ///
+    fn convert_record(&self, text: &str, line_nb: usize) -> Result<(Value, Option<i64>), Error> {
+        let captures = self.line_regex.captures(text).ok_or(Error::LineParsing(
+            line_nb,
+            "the line does not match the regex".to_owned(),
+        ))?;
+
+        let mut map: Map<String, Value> = Map::with_capacity(self.converters.len());
+        let mut sort_data = None;
+        for (group, conv) in self.converters.iter().enumerate() {
+            let Some(conv) = conv else {
+                continue;
+            };
+            let data = captures.get(group).map(|m| m.as_str()).unwrap_or("");
+            if data.is_empty() {
+                if conv.mandatory {
+                    return Err(Error::CsvMandatoryField(
+                        line_nb,
+                        group,
+                        conv.field_name.clone(),
+                    ));
+                }
+                //do not parse or insert empty fields
+                continue;
+            }
+            if self.sort_group == Some(group) {
+                sort_data = Some(conv.to_sort_data(data)?);
+            }
+            match conv.to_json_value(data) {
+                Ok(value) => {
+                    map.insert(conv.field_name.clone(), value);
+                }
+                Err(e) => {
+                    return Err(Error::CsvParsing(
+                        line_nb,
+                        group,
+                        conv.field_name.clone(),
+                        e.to_string(),
+                    ));
+                }
+            }
+        }
+        Ok((Value::Object(map), sort_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init_log, writer::file_writer::MemoryWriter};
+
+    use super::*;
+
+    pub const DATA_PATH: &str = "data/parser/app.log";
+    pub const MAPPING_PATH: &str = "data/app_log.map.yaml";
+
+    #[test]
+    fn parse_multiline() {
+        init_log();
+        let fields = Fields::new("mymachine", "app.log", "mymachine_ORC.7z", "app.log");
+
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+
+        let mapping = RegexLogMapping::load(MAPPING_PATH).unwrap();
+        let parser = RegexLogParser::new(&mapping).unwrap();
+        let mut errors = ParseErrors::new(true, &mapping.topic, &fields, "", &[]);
+        parser
+            .parse(DATA_PATH, &fields, &mut output, &mut errors, 1)
+            .unwrap();
+        assert_eq!(3, output.num_rows());
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[1]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!(
+            r#"{"TimeStamp":"2024-01-02 10:14:16.120","Level":"ERROR","Thread":"worker-1","Message":"Unhandled exception\njava.lang.NullPointerException: null\n    at com.example.Worker.run(Worker.java:42)"}"#,
+            serde_json::to_string(data).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_error() {
+        init_log();
+        let fields = Fields::new("mymachine", "app.log", "mymachine_ORC.7z", "app.log");
+
+        let mut output = Output {
+            list: vec![Box::new(MemoryWriter::new(10))],
+            num_rows: 0,
+        };
+
+        let mapping = RegexLogMapping::load(MAPPING_PATH).unwrap();
+        let parser = RegexLogParser::new(&mapping).unwrap();
+
+        let mut errors = ParseErrors::new(false, &mapping.topic, &fields, "", &[]);
+
+        //the invalid record is not skipped
+        parser
+            .parse(DATA_PATH, &fields, &mut output, &mut errors, 1)
+            .expect_err("invalid date");
+
+        //the preamble is not skipped
+        parser
+            .parse(DATA_PATH, &fields, &mut output, &mut errors, 0)
+            .expect_err("preamble");
+    }
+
+    #[test]
+    fn unknown_sort_field() {
+        let mut mapping = RegexLogMapping {
+            topic: "app_log".to_owned(),
+            line_regex: Some(r"^(?P<TimeStamp>\S+) (?P<Message>.*)$".to_owned()),
+            sort_field: Some("TimeStamp".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(Some(1), RegexLogParser::new(&mapping).unwrap().sort_group);
+
+        mapping.sort_field = Some("Date".to_owned());
+        match RegexLogParser::new(&mapping) {
+            Err(Error::RegexLogConfiguration(_)) => {}
+            _ => panic!("the sort field must be a capture group"),
+        }
+    }
+}
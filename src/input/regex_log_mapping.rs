@@ -0,0 +1,127 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{configuration::DataType, errors::Error};
+
+use super::{
+    csv::{DateFormat, FieldType},
+    grok,
+};
+
+///
+/// Defines how lines spanning several lines are grouped
+/// The lines of a record are joined with '\n', use the (?s) flag in the line regex to match them with '.'
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Multiline {
+    // a new record starts with every line matching this regex
+    Start(String),
+    // lines matching this regex are appended to the previous record
+    Continuation(String),
+}
+
+///
+/// Defines how a log line is split in fields and the convertion for each field
+/// Fields are extracted with named capture groups, defined by a regex or a grok expression
+/// If a field is not configured, the default value will be used
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RegexLogMapping {
+    #[serde(default)]
+    pub topic: String,
+
+    #[serde(default)]
+    pub sort_field: Option<String>,
+
+    // regex with named capture groups
+    #[serde(default)]
+    pub line_regex: Option<String>,
+
+    // grok expression, used when line_regex is not defined
+    #[serde(default)]
+    pub grok: Option<String>,
+
+    // additional grok patterns
+    #[serde(default)]
+    pub grok_patterns: HashMap<String, String>,
+
+    // multi-line records
+    #[serde(default)]
+    pub multiline: Option<Multiline>,
+
+    //Default date format
+    #[serde(default)]
+    pub default_date_format: DateFormat,
+
+    //mapping for each field
+    #[serde(default)]
+    pub fields: HashMap<String, FieldType>,
+}
+impl RegexLogMapping {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        if !path.as_ref().exists() {
+            return Err(Error::RegexLogConfiguration(format!(
+                "file does not exists: {}",
+                path.as_ref().to_string_lossy()
+            )));
+        }
+        let yaml_file: String = fs::read_to_string(path)?;
+
+        let conf = serde_yml::from_str::<RegexLogMapping>(&yaml_file)
+            .map_err(|e| Error::RegexLogConfiguration(e.to_string()))?;
+        Ok(conf)
+    }
+
+    ///
+    /// Build the line regex, from the line_regex or the grok expression
+    ///
+    pub fn regex(&self) -> Result<Regex, Error> {
+        let expression = match (&self.line_regex, &self.grok) {
+            (Some(regex), None) => regex.to_owned(),
+            (None, Some(expression)) => grok::expand(expression, &self.grok_patterns)?,
+            _ => {
+                return Err(Error::RegexLogConfiguration(format!(
+                    "topic '{}': either line_regex or grok must be defined",
+                    self.topic
+                )));
+            }
+        };
+        Ok(Regex::new(&expression)?)
+    }
+
+    pub fn partial_fields(&self) -> Vec<(String, DataType)> {
+        self.fields
+            .iter()
+            .map(|(name, field_type)| (name.to_owned(), field_type.data_type()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub const MAPPING_PATH: &str = "data/app_log.map.yaml";
+
+    #[test]
+    fn load_mapping() {
+        let mapping = RegexLogMapping::load(MAPPING_PATH).unwrap();
+        assert_eq!("app_log", mapping.topic);
+        assert!(mapping.fields.contains_key("TimeStamp"));
+
+        let regex = mapping.regex().unwrap();
+        let names: Vec<&str> = regex.capture_names().flatten().collect();
+        assert_eq!(vec!["TimeStamp", "Level", "Thread", "Message"], names);
+
+        let invalid = RegexLogMapping {
+            line_regex: Some("(?P<a>.*)".to_owned()),
+            grok: Some("%{GREEDYDATA:a}".to_owned()),
+            ..Default::default()
+        };
+        invalid
+            .regex()
+            .expect_err("line_regex and grok are exclusive");
+    }
+}
@@ -0,0 +1,205 @@
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use log::error;
+use serde_json::{Map, Value, json};
+
+use crate::{
+    Error,
+    configuration::DataType,
+    output::{Fields, Output, OutputConfig, Tuple},
+};
+
+use super::text_file::{for_each_line, format_date, naive_to_utc};
+
+pub const SETUPAPI_TABLE_NAME: &str = "setupapi";
+pub const SETUPAPI_SORT_FIELD: &str = SETUPAPI_DATE;
+
+const SETUPAPI_DATE: &str = "TimeStamp";
+const SETUPAPI_END_DATE: &str = "EndTime";
+const SETUPAPI_TITLE: &str = "Title";
+const SETUPAPI_EXIT_STATUS: &str = "ExitStatus";
+const SETUPAPI_DETAILS: &str = "Details";
+
+const SETUPAPI_DATE_FORMAT: &str = "%Y/%m/%d %H:%M:%S%.3f";
+const BOOT_SESSION: &str = "[Boot Session: ";
+const SECTION_TITLE: &str = ">>>  [";
+const SECTION_START: &str = ">>>  Section start ";
+const SECTION_END: &str = "<<<  Section end ";
+const EXIT_STATUS: &str = "<<<  [Exit status: ";
+
+pub fn setupapi_fields() -> Vec<(String, DataType)> {
+    vec![
+        (SETUPAPI_DATE.to_owned(), DataType::Date),
+        (SETUPAPI_END_DATE.to_owned(), DataType::Date),
+        (SETUPAPI_TITLE.to_owned(), DataType::String),
+        (SETUPAPI_EXIT_STATUS.to_owned(), DataType::String),
+    ]
+}
+
+///
+/// Parse a Windows setupapi.dev.log file
+/// One row is written per boot session and per device installation section
+/// Dates are written in local time without timezone, they are considered to be UTC
+///
+pub fn parse_setupapi<P: AsRef<Path>>(
+    path: P,
+    client_context: &str,
+    fields: &Fields,
+    output_config: &[OutputConfig],
+) -> Result<usize, Error> {
+    let mut output = Output::new(
+        output_config,
+        &fields.archive_name,
+        &fields.archive_file,
+        client_context,
+        SETUPAPI_TABLE_NAME,
+    )?;
+
+    parse(path, fields, &mut output)?;
+    output.complete()?;
+    Ok(output.num_rows())
+}
+
+///
+/// An installation section in progress
+///
+#[derive(Default)]
+struct Section {
+    title: String,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    exit_status: Option<String>,
+    details: Vec<String>,
+}
+
+fn parse<P: AsRef<Path>>(path: P, fields: &Fields, output: &mut Output) -> Result<(), Error> {
+    let mut section: Option<Section> = None;
+    for_each_line(path, |line_nb, line| {
+        if let Some(boot) = line.strip_prefix(BOOT_SESSION) {
+            match parse_date(boot.trim_end_matches(']'), line_nb) {
+                Ok(date) => {
+                    let mut data = Map::new();
+                    data.insert(SETUPAPI_TITLE.to_owned(), json!("Boot Session"));
+                    data.insert(SETUPAPI_DATE.to_owned(), json!(format_date(&date)));
+                    let mut tuple = Tuple::new(fields);
+                    tuple.set_data(Value::Object(data), Some(date.timestamp()))?;
+                    output.write(tuple)?;
+                }
+                Err(e) => error!("Skipping Line {line_nb} caused by error: {e}"),
+            }
+        } else if let Some(title) = line.strip_prefix(SECTION_TITLE) {
+            //a section that has not been closed by an exit status
+            if let Some(previous) = section.take() {
+                write_section(previous, fields, output)?;
+            }
+            section = Some(Section {
+                title: title.trim_end_matches(']').to_owned(),
+                ..Default::default()
+            });
+        } else if let Some(current) = section.as_mut() {
+            if let Some(start) = line.strip_prefix(SECTION_START) {
+                match parse_date(start, line_nb) {
+                    Ok(date) => current.start = Some(date),
+                    Err(e) => error!("Line {line_nb}: {e}"),
+                }
+            } else if let Some(end) = line.strip_prefix(SECTION_END) {
+                match parse_date(end, line_nb) {
+                    Ok(date) => current.end = Some(date),
+                    Err(e) => error!("Line {line_nb}: {e}"),
+                }
+            } else if let Some(status) = line.strip_prefix(EXIT_STATUS) {
+                current.exit_status = Some(status.trim_end_matches(']').to_owned());
+                if let Some(current) = section.take() {
+                    write_section(current, fields, output)?;
+                }
+            } else if !line.trim().is_empty() {
+                current.details.push(line.trim().to_owned());
+            }
+        }
+        Ok(())
+    })?;
+
+    if let Some(previous) = section.take() {
+        write_section(previous, fields, output)?;
+    }
+    Ok(())
+}
+
+fn write_section(section: Section, fields: &Fields, output: &mut Output) -> Result<(), Error> {
+    let mut data = Map::new();
+    data.insert(SETUPAPI_TITLE.to_owned(), json!(section.title));
+    if let Some(start) = &section.start {
+        data.insert(SETUPAPI_DATE.to_owned(), json!(format_date(start)));
+    }
+    if let Some(end) = &section.end {
+        data.insert(SETUPAPI_END_DATE.to_owned(), json!(format_date(end)));
+    }
+    if let Some(exit_status) = &section.exit_status {
+        data.insert(SETUPAPI_EXIT_STATUS.to_owned(), json!(exit_status));
+    }
+    if !section.details.is_empty() {
+        data.insert(
+            SETUPAPI_DETAILS.to_owned(),
+            json!(section.details.join("\n")),
+        );
+    }
+    let mut tuple = Tuple::new(fields);
+    tuple.set_data(
+        Value::Object(data),
+        section.start.map(|date| date.timestamp()),
+    )?;
+    output.write(tuple)?;
+    Ok(())
+}
+
+fn parse_date(value: &str, line_nb: usize) -> Result<DateTime<Utc>, Error> {
+    let date = NaiveDateTime::parse_from_str(value.trim(), SETUPAPI_DATE_FORMAT)
+        .map_err(|e| Error::LineParsing(line_nb, format!("invalid date '{value}': {e}")))?;
+    Ok(naive_to_utc(&date))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init_log, writer::file_writer::MemoryWriter};
+
+    use super::*;
+
+    pub const SETUPAPI_PATH: &str = "data/parser/setupapi.dev.log";
+
+    #[test]
+    fn parse_device_install() {
+        init_log();
+        let fields = Fields::new(
+            "mymachine",
+            "setupapi.dev.log",
+            "mymachine_ORC.7z",
+            "setupapi.dev.log",
+        );
+
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+
+        parse(SETUPAPI_PATH, &fields, &mut output).unwrap();
+        assert_eq!(2, output.num_rows());
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[0]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!("Boot Session", data["Title"]);
+        assert_eq!("2024-01-02 09:00:00.500", data["TimeStamp"]);
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[1]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!(
+            "Device Install (Hardware initiated) - USB\\VID_0781&PID_5581\\4C530001231120115142",
+            data["Title"]
+        );
+        assert_eq!("2024-01-02 10:12:13.456", data["TimeStamp"]);
+        assert_eq!("2024-01-02 10:12:15.123", data["EndTime"]);
+        assert_eq!("SUCCESS", data["ExitStatus"]);
+    }
+}
@@ -9,7 +9,10 @@ use log::warn;
 use serde_json::json;
 use std::{collections::HashMap, path::Path};
 
-use super::srum_model::{ID_MAP_TABLE, SRUM_SORT_FIELD, srum_tables};
+use super::{
+    parse_errors::ParseErrors,
+    srum_model::{ID_MAP_TABLE, SRUM_SORT_FIELD, srum_tables},
+};
 
 ///
 /// Parse a Srum file.
@@ -71,6 +74,7 @@ impl SrumParser {
     ///
     /// Parse all SRUM tables
     /// it does it in a best effort mode: upon failure, it does not stop and only log the error.
+    /// Invalid rows are written to the parse_errors topic
     ///
     pub fn parse_all_tables(
         &self,
@@ -79,6 +83,7 @@ impl SrumParser {
         output_config: &[OutputConfig],
     ) -> Result<usize, Error> {
         let mut num_rows = 0;
+        let mut errors = ParseErrors::new(true, "", fields, client_context, output_config);
 
         for srum_table in srum_tables() {
             let mut output = Output::new(
@@ -89,14 +94,17 @@ impl SrumParser {
                 &srum_table.topic,
             )?;
 
-            if let Err(e) = self.parse_table(&srum_table.name, &mut output, fields) {
-                warn!(
+            errors.set_topic(srum_table.topic);
+            match self.parse_table(&srum_table.name, &mut output, fields, &mut errors) {
+                Ok(()) => output.complete()?,
+                Err(e) => warn!(
                     "Srum Table:'{}', Input:'{}/{}', Error: {e}",
                     &srum_table.topic, &fields.archive_name, &fields.archive_file
-                );
+                ),
             }
             num_rows += output.num_rows();
         }
+        errors.complete()?;
         Ok(num_rows)
     }
 
@@ -108,6 +116,7 @@ impl SrumParser {
         table_name: &str,
         output: &mut Output,
         fields: &Fields,
+        errors: &mut ParseErrors,
     ) -> Result<(), Error> {
         let table = self.db.table_by_name(table_name)?;
 
@@ -119,34 +128,43 @@ impl SrumParser {
             columns.push((col_name, col_type));
         }
 
-        for row in table.iter_records()? {
-            let row = row?;
-            let mut data = serde_json::Map::new();
-            let mut tuple = Tuple::new(fields);
-            let mut sort_data = None;
-            for (pos, column) in row.iter_values()?.enumerate() {
-                let (column_name, data_type) = &columns[pos];
-                let column = column?;
-
-                let value = match data_type {
-                    SrumDataType::String => self.get_string_from_index(column),
-                    SrumDataType::Date => {
-                        let date = self.get_date(column);
-                        if let Some(date) = date {
-                            if column_name.eq(SRUM_SORT_FIELD) {
-                                sort_data = Some(date.timestamp());
+        for (record_nb, row) in table.iter_records()?.enumerate() {
+            let converted = row.map_err(Error::from).and_then(|row| {
+                let mut data = serde_json::Map::new();
+                let mut sort_data = None;
+                for (pos, column) in row.iter_values()?.enumerate() {
+                    let (column_name, data_type) = &columns[pos];
+                    let column = column?;
+
+                    let value = match data_type {
+                        SrumDataType::String => self.get_string_from_index(column),
+                        SrumDataType::Date => {
+                            let date = self.get_date(column);
+                            if let Some(date) = date {
+                                if column_name.eq(SRUM_SORT_FIELD) {
+                                    sort_data = Some(date.timestamp());
+                                }
+                                json!(date.format(OUTPUT_DATE_FORMAT_UTC).to_string())
+                            } else {
+                                serde_json::Value::Null
                             }
-                            json!(date.format(OUTPUT_DATE_FORMAT_UTC).to_string())
-                        } else {
-                            serde_json::Value::Null
                         }
-                    }
-                    _ => self.get_data(column),
-                };
-                data.insert(column_name.to_owned(), value);
+                        _ => self.get_data(column),
+                    };
+                    data.insert(column_name.to_owned(), value);
+                }
+                Ok((data, sort_data))
+            });
+
+            match converted {
+                Ok((data, sort_data)) => {
+                    let mut tuple = Tuple::new(fields);
+                    tuple.set_data(serde_json::Value::Object(data), sort_data)?;
+                    output.write(tuple)?;
+                }
+                // the raw content of a database row is not available
+                Err(e) => errors.reject(e, record_nb, "")?,
             }
-            tuple.set_data(serde_json::Value::Object(data), sort_data)?;
-            output.write(tuple)?;
         }
 
         Ok(())
@@ -346,6 +364,8 @@ mod tests {
             "SRUDB.dat",
         );
 
+        let mut errors = ParseErrors::new(false, "", &fields, "", &[]);
+
         //
         // test network connectivity
         //
@@ -360,8 +380,13 @@ mod tests {
 
         // let table_name = tables.get("srum_network_connectivity_usage").unwrap();
         let now = Instant::now();
-        p.parse_table(NETWORK_CONNECTIVITY_USAGE, &mut output, &fields)
-            .unwrap();
+        p.parse_table(
+            NETWORK_CONNECTIVITY_USAGE,
+            &mut output,
+            &fields,
+            &mut errors,
+        )
+        .unwrap();
 
         {
             let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[0]).unwrap();
@@ -391,7 +416,7 @@ mod tests {
         };
 
         let now = Instant::now();
-        p.parse_table(APPLICATION_RESOURCES, &mut output, &fields)
+        p.parse_table(APPLICATION_RESOURCES, &mut output, &fields, &mut errors)
             .unwrap();
 
         {
@@ -0,0 +1,380 @@
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc};
+use log::error;
+use regex::{Captures, Regex};
+use serde_json::{Map, Value, json};
+
+use crate::{
+    Error,
+    configuration::DataType,
+    output::{Fields, Output, OutputConfig, Tuple},
+};
+
+use super::text_file::{for_each_line, format_date, naive_to_utc};
+
+pub const SYSLOG_TABLE_NAME: &str = "syslog";
+pub const SYSLOG_SORT_FIELD: &str = SYSLOG_DATE;
+
+const SYSLOG_DATE: &str = "TimeStamp";
+const SYSLOG_HOST: &str = "Host";
+const SYSLOG_PROGRAM: &str = "Program";
+const SYSLOG_PID: &str = "Pid";
+const SYSLOG_FACILITY: &str = "Facility";
+const SYSLOG_SEVERITY: &str = "Severity";
+const SYSLOG_MSG_ID: &str = "MsgId";
+const SYSLOG_STRUCTURED_DATA: &str = "StructuredData";
+const SYSLOG_MESSAGE: &str = "Message";
+
+pub fn syslog_fields() -> Vec<(String, DataType)> {
+    vec![
+        (SYSLOG_DATE.to_owned(), DataType::Date),
+        (SYSLOG_HOST.to_owned(), DataType::String),
+        (SYSLOG_PROGRAM.to_owned(), DataType::String),
+        (SYSLOG_PID.to_owned(), DataType::Int64),
+        (SYSLOG_FACILITY.to_owned(), DataType::String),
+        (SYSLOG_SEVERITY.to_owned(), DataType::String),
+        (SYSLOG_MESSAGE.to_owned(), DataType::String),
+    ]
+}
+
+///
+/// RFC5424: <PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG
+///
+const RFC5424_REGEX: &str =
+    r"^<(\d{1,3})>1 (\S+) (\S+) (\S+) (\S+) (\S+) (-|(?:\[(?:[^\]\\]|\\.)*\])+)(?: (.*))?$";
+
+///
+/// RFC3164 and the default format of syslog files (auth.log, messages, ...): Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG
+/// The PRI part is usually not written in files
+///
+const RFC3164_REGEX: &str = r"^(?:<(\d{1,3})>)?([A-Z][a-z]{2}) {1,2}(\d{1,2}) (\d{2}:\d{2}:\d{2}) (\S+) (?:([^:\[\s]+)(?:\[(\d+)\])?: ?)?(.*)$";
+
+///
+/// High precision format used by rsyslog and syslog-ng: YYYY-MM-DDThh:mm:ss.ffffff+zz:zz HOSTNAME TAG[PID]: MSG
+///
+const ISO_REGEX: &str =
+    r"^(?:<(\d{1,3})>)?(\d{4}-\d{2}-\d{2}T\S+) (\S+) (?:([^:\[\s]+)(?:\[(\d+)\])?: ?)?(.*)$";
+
+const FACILITIES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+const SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+///
+/// Parse a linux syslog file: auth.log, syslog, messages, secure, ...
+///
+/// RFC3164 dates do not contain the year, it is inferred:
+/// - the last entry of the file is considered to be in the provided year, or the year of the file modification date
+/// - the year is decremented for every january to december rollover found while going back in time
+///
+/// Dates without timezone are considered to be UTC
+///
+pub fn parse_syslog<P: AsRef<Path>>(
+    path: P,
+    client_context: &str,
+    fields: &Fields,
+    year: Option<i32>,
+    output_config: &[OutputConfig],
+) -> Result<usize, Error> {
+    let mut output = Output::new(
+        output_config,
+        &fields.archive_name,
+        &fields.archive_file,
+        client_context,
+        SYSLOG_TABLE_NAME,
+    )?;
+
+    let year = match year {
+        Some(year) => year,
+        None => {
+            let modified: DateTime<Utc> = path.as_ref().metadata()?.modified()?.into();
+            modified.year()
+        }
+    };
+
+    parse(path, year, fields, &mut output)?;
+    output.complete()?;
+    Ok(output.num_rows())
+}
+
+fn parse<P: AsRef<Path>>(
+    path: P,
+    last_year: i32,
+    fields: &Fields,
+    output: &mut Output,
+) -> Result<(), Error> {
+    let parser = SyslogParser::new();
+
+    //first pass to find the year of the first entry
+    let mut rollovers = 0;
+    let mut previous_month = 0;
+    for_each_line(&path, |_, line| {
+        if let Some(month) = parser.rfc3164_month(line) {
+            if month < previous_month {
+                rollovers += 1;
+            }
+            previous_month = month;
+        }
+        Ok(())
+    })?;
+
+    let mut year = last_year - rollovers;
+    let mut previous_month = 0;
+    for_each_line(&path, |line_nb, line| {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        match parser.parse_line(line, line_nb) {
+            Ok((date, mut data)) => {
+                let date = match date {
+                    LineDate::Full(date) => Some(date),
+                    LineDate::WithoutYear { month, day, time } => {
+                        if month < previous_month {
+                            year += 1;
+                        }
+                        previous_month = month;
+                        NaiveDate::from_ymd_opt(year, month, day)
+                            .map(|date| naive_to_utc(&date.and_time(time)))
+                    }
+                };
+                let Some(date) = date else {
+                    error!("Skipping Line {line_nb} caused by error: invalid date in '{line}'");
+                    return Ok(());
+                };
+                data.insert(SYSLOG_DATE.to_owned(), json!(format_date(&date)));
+                let mut tuple = Tuple::new(fields);
+                tuple.set_data(Value::Object(data), Some(date.timestamp()))?;
+                output.write(tuple)?;
+            }
+            Err(e) => error!("Skipping Line {line_nb} caused by error: {e}"),
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+///
+/// The date of a line, RFC3164 dates must be completed with the year
+///
+enum LineDate {
+    Full(DateTime<Utc>),
+    WithoutYear {
+        month: u32,
+        day: u32,
+        time: NaiveTime,
+    },
+}
+
+struct SyslogParser {
+    rfc5424: Regex,
+    rfc3164: Regex,
+    iso: Regex,
+}
+impl SyslogParser {
+    fn new() -> Self {
+        Self {
+            rfc5424: Regex::new(RFC5424_REGEX).unwrap(),
+            rfc3164: Regex::new(RFC3164_REGEX).unwrap(),
+            iso: Regex::new(ISO_REGEX).unwrap(),
+        }
+    }
+
+    ///
+    /// returns the month of a RFC3164 line
+    ///
+    fn rfc3164_month(&self, line: &str) -> Option<u32> {
+        let captures = self.rfc3164.captures(line)?;
+        month_number(captures.get(2)?.as_str())
+    }
+
+    fn parse_line(
+        &self,
+        line: &str,
+        line_nb: usize,
+    ) -> Result<(LineDate, Map<String, Value>), Error> {
+        let mut data = Map::new();
+        if let Some(captures) = self.rfc5424.captures(line) {
+            insert_priority(&mut data, &captures, 1);
+            let date = DateTime::parse_from_rfc3339(&captures[2])
+                .map_err(|e| Error::LineParsing(line_nb, e.to_string()))?;
+            insert_nil_value(&mut data, SYSLOG_HOST, &captures[3]);
+            insert_nil_value(&mut data, SYSLOG_PROGRAM, &captures[4]);
+            if let Ok(pid) = captures[5].parse::<i64>() {
+                data.insert(SYSLOG_PID.to_owned(), json!(pid));
+            }
+            insert_nil_value(&mut data, SYSLOG_MSG_ID, &captures[6]);
+            insert_nil_value(&mut data, SYSLOG_STRUCTURED_DATA, &captures[7]);
+            if let Some(message) = captures.get(8) {
+                let message = message.as_str().trim_start_matches('\u{feff}');
+                data.insert(SYSLOG_MESSAGE.to_owned(), json!(message));
+            }
+            return Ok((LineDate::Full(date.to_utc()), data));
+        }
+
+        if let Some(captures) = self.rfc3164.captures(line) {
+            insert_priority(&mut data, &captures, 1);
+            let month = month_number(&captures[2]).ok_or(Error::LineParsing(
+                line_nb,
+                format!("invalid month '{}'", &captures[2]),
+            ))?;
+            let day: u32 = captures[3].parse()?;
+            let time = NaiveTime::parse_from_str(&captures[4], "%H:%M:%S")?;
+            insert_message(&mut data, &captures, 5);
+            return Ok((LineDate::WithoutYear { month, day, time }, data));
+        }
+
+        if let Some(captures) = self.iso.captures(line) {
+            insert_priority(&mut data, &captures, 1);
+            let date = DateTime::parse_from_rfc3339(&captures[2])
+                .map_err(|e| Error::LineParsing(line_nb, e.to_string()))?;
+            insert_message(&mut data, &captures, 3);
+            return Ok((LineDate::Full(date.to_utc()), data));
+        }
+
+        Err(Error::LineParsing(
+            line_nb,
+            format!("unknown syslog format '{line}'"),
+        ))
+    }
+}
+
+///
+/// insert HOSTNAME TAG[PID]: MSG starting at the provided capture group
+///
+fn insert_message(data: &mut Map<String, Value>, captures: &Captures, first_group: usize) {
+    data.insert(SYSLOG_HOST.to_owned(), json!(&captures[first_group]));
+    if let Some(program) = captures.get(first_group + 1) {
+        data.insert(SYSLOG_PROGRAM.to_owned(), json!(program.as_str()));
+    }
+    if let Some(pid) = captures.get(first_group + 2)
+        && let Ok(pid) = pid.as_str().parse::<i64>()
+    {
+        data.insert(SYSLOG_PID.to_owned(), json!(pid));
+    }
+    if let Some(message) = captures.get(first_group + 3) {
+        data.insert(SYSLOG_MESSAGE.to_owned(), json!(message.as_str()));
+    }
+}
+
+///
+/// The PRI value is the facility * 8 + the severity
+///
+fn insert_priority(data: &mut Map<String, Value>, captures: &Captures, group: usize) {
+    let priority = captures
+        .get(group)
+        .and_then(|pri| pri.as_str().parse::<usize>().ok());
+    if let Some(priority) = priority {
+        if let Some(facility) = FACILITIES.get(priority / 8) {
+            data.insert(SYSLOG_FACILITY.to_owned(), json!(facility));
+        }
+        data.insert(SYSLOG_SEVERITY.to_owned(), json!(SEVERITIES[priority % 8]));
+    }
+}
+
+///
+/// RFC5424 uses '-' for empty values
+///
+fn insert_nil_value(data: &mut Map<String, Value>, name: &str, value: &str) {
+    if value != "-" {
+        data.insert(name.to_owned(), json!(value));
+    }
+}
+
+fn month_number(month: &str) -> Option<u32> {
+    let month = match month {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+    Some(month)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init_log, writer::file_writer::MemoryWriter};
+
+    use super::*;
+
+    pub const AUTH_LOG_PATH: &str = "data/parser/auth.log";
+
+    #[test]
+    fn parse_auth_log() {
+        init_log();
+        let fields = Fields::new("mymachine", "auth.log", "mymachine_ORC.7z", "auth.log");
+
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+
+        parse(AUTH_LOG_PATH, 2024, &fields, &mut output).unwrap();
+        assert_eq!(5, output.num_rows());
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[0]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!(
+            r#"{"Host":"srv01","Program":"sshd","Pid":1234,"Message":"Accepted password for bob from 10.0.0.2 port 51234 ssh2","TimeStamp":"2023-12-31 23:59:01.000"}"#,
+            serde_json::to_string(data).unwrap()
+        );
+
+        //year rollover
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[2]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!("2024-01-01 00:00:03.000", data["TimeStamp"]);
+        assert_eq!("CRON", data["Program"]);
+
+        //RFC5424
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[3]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!("2024-01-02 10:14:15.003", data["TimeStamp"]);
+        assert_eq!("auth", data["Facility"]);
+        assert_eq!("crit", data["Severity"]);
+        assert_eq!("ID47", data["MsgId"]);
+        assert_eq!("'su root' failed for bob on /dev/pts/8", data["Message"]);
+
+        //high precision format
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[4]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!("2024-01-02 11:00:00.123", data["TimeStamp"]);
+        assert_eq!("sudo", data["Program"]);
+    }
+}
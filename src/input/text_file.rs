@@ -0,0 +1,55 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+
+use crate::{Error, output::OUTPUT_DATE_FORMAT_UTC};
+
+///
+/// Allocate 10 MB to the read buffer
+///
+pub const BUFFER_CAPACITY: usize = 1024 * 1024 * 10;
+
+///
+/// Read a text file line by line and call the provided function for each line
+/// - invalid UTF-8 sequences are replaced, log files are rarely clean
+/// - the line terminator (\n or \r\n) is removed
+/// - line numbers start at 0
+///
+pub fn for_each_line<P, F>(path: P, mut f: F) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(usize, &str) -> Result<(), Error>,
+{
+    let file = File::open(path)?;
+    let mut reader = BufReader::with_capacity(BUFFER_CAPACITY, file);
+    let mut buffer = Vec::new();
+    let mut line_nb = 0;
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&buffer);
+        f(line_nb, line.trim_end_matches(['\r', '\n']))?;
+        line_nb += 1;
+    }
+    Ok(())
+}
+
+///
+/// Dates without timezone are considered to be UTC
+///
+pub fn naive_to_utc(date: &NaiveDateTime) -> DateTime<Utc> {
+    Utc.from_utc_datetime(date)
+}
+
+///
+/// Format a date the way every parser does
+///
+pub fn format_date(date: &DateTime<Utc>) -> String {
+    date.format(OUTPUT_DATE_FORMAT_UTC).to_string()
+}
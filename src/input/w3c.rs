@@ -0,0 +1,192 @@
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use log::error;
+use serde_json::{Map, Value, json};
+
+use crate::{
+    Error,
+    configuration::DataType,
+    output::{Fields, Output, OutputConfig, Tuple},
+};
+
+use super::text_file::{for_each_line, format_date, naive_to_utc};
+
+pub const W3C_TABLE_NAME: &str = "w3c";
+pub const W3C_SORT_FIELD: &str = W3C_DATE;
+
+const W3C_DATE: &str = "TimeStamp";
+const W3C_FIELDS_DIRECTIVE: &str = "#Fields:";
+const W3C_DATE_FIELD: &str = "date";
+const W3C_TIME_FIELD: &str = "time";
+const W3C_EMPTY_VALUE: &str = "-";
+
+///
+/// Fields that are always numbers
+///
+const W3C_INTEGER_FIELDS: [&str; 7] = [
+    "s-port",
+    "sc-status",
+    "sc-substatus",
+    "sc-win32-status",
+    "sc-bytes",
+    "cs-bytes",
+    "time-taken",
+];
+
+pub fn w3c_fields() -> Vec<(String, DataType)> {
+    vec![(W3C_DATE.to_owned(), DataType::Date)]
+}
+
+///
+/// Parse an IIS/W3C extended log file
+/// The columns are defined by the '#Fields:' directive, that can change anywhere in the file
+/// The 'date' and 'time' columns are merged in the TimeStamp field, W3C dates are always UTC
+/// https://www.w3.org/TR/WD-logfile.html
+///
+pub fn parse_w3c<P: AsRef<Path>>(
+    path: P,
+    client_context: &str,
+    fields: &Fields,
+    output_config: &[OutputConfig],
+) -> Result<usize, Error> {
+    let mut output = Output::new(
+        output_config,
+        &fields.archive_name,
+        &fields.archive_file,
+        client_context,
+        W3C_TABLE_NAME,
+    )?;
+
+    parse(path, fields, &mut output)?;
+    output.complete()?;
+    Ok(output.num_rows())
+}
+
+fn parse<P: AsRef<Path>>(path: P, fields: &Fields, output: &mut Output) -> Result<(), Error> {
+    let mut columns: Vec<String> = Vec::new();
+    for_each_line(path, |line_nb, line| {
+        if let Some(directive) = line.strip_prefix(W3C_FIELDS_DIRECTIVE) {
+            columns = directive.split_whitespace().map(|s| s.to_owned()).collect();
+            return Ok(());
+        }
+        if line.starts_with('#') || line.trim().is_empty() {
+            return Ok(());
+        }
+        match convert_line(&columns, line, line_nb) {
+            Ok((data, sort_data)) => {
+                let mut tuple = Tuple::new(fields);
+                tuple.set_data(data, sort_data)?;
+                output.write(tuple)?;
+            }
+            Err(e) => error!("Skipping Line {line_nb} caused by error: {e}"),
+        }
+        Ok(())
+    })
+}
+
+///
+/// Convert a log line to a json object
+///
+fn convert_line(
+    columns: &[String],
+    line: &str,
+    line_nb: usize,
+) -> Result<(Value, Option<i64>), Error> {
+    if columns.is_empty() {
+        return Err(Error::LineParsing(
+            line_nb,
+            format!("no '{W3C_FIELDS_DIRECTIVE}' directive found"),
+        ));
+    }
+    let values: Vec<&str> = line.split_whitespace().collect();
+    if values.len() != columns.len() {
+        return Err(Error::LineParsing(
+            line_nb,
+            format!(
+                "expecting {} columns, found {}",
+                columns.len(),
+                values.len()
+            ),
+        ));
+    }
+
+    let mut map = Map::with_capacity(columns.len());
+    let mut date = None;
+    let mut time = None;
+    for (column, value) in columns.iter().zip(values) {
+        match column.as_str() {
+            W3C_DATE_FIELD => date = Some(value),
+            W3C_TIME_FIELD => time = Some(value),
+            _ => {
+                if value == W3C_EMPTY_VALUE {
+                    continue;
+                }
+                let value = if W3C_INTEGER_FIELDS.contains(&column.as_str()) {
+                    match value.parse::<i64>() {
+                        Ok(v) => json!(v),
+                        Err(_) => json!(value),
+                    }
+                } else {
+                    json!(value)
+                };
+                map.insert(column.to_owned(), value);
+            }
+        }
+    }
+
+    let mut sort_data = None;
+    if let (Some(date), Some(time)) = (date, time) {
+        let date =
+            NaiveDateTime::parse_from_str(&format!("{date} {time}"), "%Y-%m-%d %H:%M:%S%.f")?;
+        let date = naive_to_utc(&date);
+        sort_data = Some(date.timestamp());
+        map.insert(W3C_DATE.to_owned(), json!(format_date(&date)));
+    }
+    Ok((Value::Object(map), sort_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init_log, writer::file_writer::MemoryWriter};
+
+    use super::*;
+
+    pub const IIS_PATH: &str = "data/parser/u_ex240102.log";
+
+    #[test]
+    fn parse_iis() {
+        init_log();
+        let fields = Fields::new(
+            "mymachine",
+            "u_ex240102.log",
+            "mymachine_ORC.7z",
+            "u_ex240102.log",
+        );
+
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+
+        parse(IIS_PATH, &fields, &mut output).unwrap();
+        assert_eq!(3, output.num_rows());
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[0]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!(
+            r#"{"s-ip":"10.0.0.1","cs-method":"GET","cs-uri-stem":"/index.html","s-port":443,"c-ip":"10.0.0.2","cs(User-Agent)":"Mozilla/5.0+(Windows+NT+10.0)","sc-status":200,"time-taken":15,"TimeStamp":"2024-01-02 10:14:15.000"}"#,
+            serde_json::to_string(data).unwrap()
+        );
+
+        //the fields directive changed
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[2]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!(
+            r#"{"cs-method":"POST","cs-uri-stem":"/upload.aspx","sc-status":500,"TimeStamp":"2024-01-02 11:00:00.000"}"#,
+            serde_json::to_string(data).unwrap()
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use quick_xml::{
+    Reader,
+    events::{Event, attributes::Attributes},
+};
+use serde_json::{Map, Value};
+
+use crate::{
+    errors::Error,
+    output::{Fields, Output, OutputConfig, Tuple},
+};
+
+use super::{
+    parse_errors::ParseErrors,
+    record_mapping::{RecordConverter, RecordMapping},
+    text_file::BUFFER_CAPACITY,
+};
+
+const TEXT_KEY: &str = "#text";
+const ATTRIBUTES_SUFFIX: &str = "_attributes";
+
+///
+/// Parse a xml file, every element named after the record_element of the mapping is a record
+/// Elements are converted to json the way the evtx parser does:
+/// - child elements become fields, repeated elements become arrays
+/// - attributes are stored in a '<name>_attributes' object
+/// - the text of an element having children is stored in the '#text' field
+///
+pub fn parse_xml_records<P, M>(
+    path: P,
+    client_context: &str,
+    fields: &Fields,
+    output_config: &[OutputConfig],
+    mapping_path: M,
+    best_effort: bool,
+) -> Result<usize, Error>
+where
+    P: AsRef<Path>,
+    M: AsRef<Path>,
+{
+    let mapping = RecordMapping::load(mapping_path)?;
+    let record_element = record_element(&mapping)?;
+    let converter = RecordConverter::new(&mapping);
+
+    let mut output = Output::new(
+        output_config,
+        &fields.archive_name,
+        &fields.archive_file,
+        client_context,
+        &mapping.topic,
+    )?;
+
+    let mut errors = ParseErrors::new(
+        best_effort,
+        &mapping.topic,
+        fields,
+        client_context,
+        output_config,
+    );
+
+    parse(
+        path,
+        fields,
+        record_element,
+        &converter,
+        &mut output,
+        &mut errors,
+    )?;
+    errors.complete()?;
+    output.complete()?;
+    Ok(output.num_rows())
+}
+
+fn record_element(mapping: &RecordMapping) -> Result<&str, Error> {
+    mapping.record_element.as_deref().ok_or_else(|| {
+        Error::RecordConfiguration(format!(
+            "topic '{}': record_element is mandatory for xml records",
+            mapping.topic
+        ))
+    })
+}
+
+///
+/// An element being read
+///
+#[derive(Default)]
+struct Element {
+    name: String,
+    attributes: Map<String, Value>,
+    children: Map<String, Value>,
+    text: String,
+}
+impl Element {
+    fn new(name: String, attributes: Map<String, Value>) -> Self {
+        Self {
+            name,
+            attributes,
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// Add the element to its parent, the attributes are stored in the '<name>_attributes' field
+    ///
+    fn close(self, parent: &mut Map<String, Value>) {
+        let value = if self.children.is_empty() {
+            Value::String(self.text)
+        } else {
+            let mut children = self.children;
+            if !self.text.is_empty() {
+                children.insert(TEXT_KEY.to_owned(), Value::String(self.text));
+            }
+            Value::Object(children)
+        };
+        if !self.attributes.is_empty() {
+            push_value(
+                parent,
+                format!("{}{ATTRIBUTES_SUFFIX}", self.name),
+                Value::Object(self.attributes),
+            );
+        }
+        push_value(parent, self.name, value);
+    }
+}
+
+fn parse<P: AsRef<Path>>(
+    path: P,
+    fields: &Fields,
+    record_element: &str,
+    converter: &RecordConverter,
+    output: &mut Output,
+    errors: &mut ParseErrors,
+) -> Result<(), Error> {
+    let file = File::open(path)?;
+    let mut reader = Reader::from_reader(BufReader::with_capacity(BUFFER_CAPACITY, file));
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut stack: Vec<Element> = Vec::new();
+    let mut record_nb = 0;
+    loop {
+        let closed = match reader.read_event_into(&mut buf)? {
+            Event::Start(start) => {
+                let name = String::from_utf8_lossy(start.local_name().as_ref()).to_string();
+                if !stack.is_empty() || name == record_element {
+                    stack.push(Element::new(name, attributes(start.attributes())?));
+                }
+                false
+            }
+            Event::Empty(empty) => {
+                let name = String::from_utf8_lossy(empty.local_name().as_ref()).to_string();
+                if !stack.is_empty() || name == record_element {
+                    stack.push(Element::new(name, attributes(empty.attributes())?));
+                    true
+                } else {
+                    false
+                }
+            }
+            Event::Text(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text.unescape()?);
+                }
+                false
+            }
+            Event::CData(data) => {
+                if let Some(element) = stack.last_mut() {
+                    element
+                        .text
+                        .push_str(&String::from_utf8_lossy(&data.into_inner()));
+                }
+                false
+            }
+            Event::End(_) => !stack.is_empty(),
+            Event::Eof => break,
+            _ => false,
+        };
+        buf.clear();
+
+        if !closed {
+            continue;
+        }
+        let Some(element) = stack.pop() else {
+            continue;
+        };
+        match stack.last_mut() {
+            Some(parent) => element.close(&mut parent.children),
+            None => {
+                // the record is wrapped in an object named after the record element
+                let mut record = Map::new();
+                element.close(&mut record);
+                write_record(
+                    Value::Object(record),
+                    record_nb,
+                    fields,
+                    converter,
+                    output,
+                    errors,
+                )?;
+                record_nb += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_record(
+    record: Value,
+    record_nb: usize,
+    fields: &Fields,
+    converter: &RecordConverter,
+    output: &mut Output,
+    errors: &mut ParseErrors,
+) -> Result<(), Error> {
+    // the converter consumes a copy, the record is serialized for the parse_errors topic only when rejected
+    match converter.convert(record.clone(), record_nb) {
+        Ok((data, sort_data)) => {
+            let mut tuple = Tuple::new(fields);
+            tuple.set_data(data, sort_data)?;
+            output.write(tuple)?;
+        }
+        Err(err) => errors.reject(err, record_nb, &record.to_string())?,
+    }
+    Ok(())
+}
+
+fn attributes(attributes: Attributes) -> Result<Map<String, Value>, Error> {
+    let mut map = Map::new();
+    for attribute in attributes {
+        let attribute = attribute.map_err(quick_xml::Error::InvalidAttr)?;
+        let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).to_string();
+        let value = attribute.unescape_value()?;
+        map.insert(key, Value::String(value.to_string()));
+    }
+    Ok(map)
+}
+
+///
+/// Repeated elements are converted to an array
+///
+fn push_value(parent: &mut Map<String, Value>, name: String, value: Value) {
+    match parent.get_mut(&name) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(previous) => {
+            let first = previous.take();
+            *previous = Value::Array(vec![first, value]);
+        }
+        None => {
+            parent.insert(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init_log, writer::file_writer::MemoryWriter};
+
+    use super::*;
+
+    pub const DATA_PATH: &str = "data/parser/events.xml";
+    pub const MAPPING_PATH: &str = "data/events_xml.map.yaml";
+
+    #[test]
+    fn parse_events() {
+        init_log();
+        let fields = Fields::new("mymachine", "events", "mymachine_ORC.7z", "events.xml");
+
+        let output = MemoryWriter::new(10);
+        let buffer = output.get_buffer();
+        let mut output = Output {
+            list: vec![Box::new(output)],
+            num_rows: 0,
+        };
+
+        let mapping = RecordMapping::load(MAPPING_PATH).unwrap();
+        let converter = RecordConverter::new(&mapping);
+        parse(
+            DATA_PATH,
+            &fields,
+            record_element(&mapping).unwrap(),
+            &converter,
+            &mut output,
+            &mut ParseErrors::new(false, &mapping.topic, &fields, "", &[]),
+        )
+        .unwrap();
+        assert_eq!(2, output.num_rows());
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[0]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!(4624, data["Event.System.EventID"]);
+        assert_eq!(
+            "2024-01-02 10:12:13.456",
+            data["Event.System.TimeCreated_attributes.SystemTime"]
+        );
+        assert_eq!(
+            r#"["admin","10.0.0.2"]"#,
+            serde_json::to_string(&data["Event.EventData.Data"]).unwrap()
+        );
+        assert_eq!(
+            r#"[{"Name":"TargetUserName"},{"Name":"IpAddress"}]"#,
+            serde_json::to_string(&data["Event.EventData.Data_attributes"]).unwrap()
+        );
+        assert_eq!(
+            "http://schemas.microsoft.com/win/2004/08/events/event",
+            data["Event_attributes.xmlns"]
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&buffer.borrow()[1]).unwrap();
+        let data = json.get("data").unwrap();
+        assert_eq!("a < b", data["Event.EventData.Data"]);
+    }
+}
@@ -2,7 +2,10 @@ pub mod archive_parser;
 pub mod configuration;
 pub mod errors;
 pub mod input;
+pub mod machine_identity;
 pub mod output;
+pub mod provenance;
+pub mod source_path;
 pub use errors::Error;
 pub mod writer;
 #[cfg(test)]
@@ -44,7 +47,11 @@ mod tests {
         configuration::{Configuration, ParserConfig, ParserType},
         init_log,
         output::OutputConfig,
-        writer::clickhouse_config,
+        writer::{
+            clickhouse_config::{self, ClickhouseMode},
+            clickhouse_writer::ClickhouseOptions,
+            dead_letter::ErrorPolicy,
+        },
     };
 
     #[tokio::test]
@@ -85,6 +92,8 @@ mod tests {
             server: "localhost:8123".to_owned(),
             login: None,
             password: None,
+            options: ClickhouseOptions::default(),
+            errors: ErrorPolicy::default(),
         };
 
         let context = "test_lib_end_to_end";
@@ -98,6 +107,7 @@ mod tests {
             decompression_threads: 0,
             parsers: vec![srum_config, evtx_config, hive_sam_config, csv_config],
             output: vec![clickhouse_config],
+            ..Default::default()
         };
 
         //
@@ -125,6 +135,7 @@ mod tests {
             false,
             "",
             0,
+            ClickhouseMode::json,
         )
         .await
         .unwrap();
@@ -0,0 +1,343 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use nt_hive::Hive;
+use quick_xml::{Reader, events::Event};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    Error,
+    configuration::{regex_deserializer, regex_serializer},
+    source_path::{collect_files, is_backup_copy},
+};
+
+///
+/// Names of the ORC files describing the collection
+///
+const ORC_CONFIG_FILES: [&str; 2] = ["config.xml", "statistics.json"];
+
+///
+/// Element, attribute or key names holding the computer name in the ORC files, compared in lowercase
+///
+const ORC_COMPUTER_KEYS: [&str; 4] = ["computername", "computer_name", "computer", "hostname"];
+
+///
+/// Capture group used by the archive_regex source, the first group is used when it is missing
+///
+const COMPUTER_GROUP: &str = "computer";
+
+///
+/// Where the computer name can be found, the sources are tried in order
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_camel_case_types)]
+pub enum IdentitySource {
+    // a regex applied to the archive name
+    archive_regex {
+        #[serde(deserialize_with = "regex_deserializer")]
+        #[serde(serialize_with = "regex_serializer")]
+        regex: Regex,
+    },
+    // the Config.xml or Statistics.json file written by DFIR ORC in the archive
+    orc_config,
+    // the ComputerName key of the SYSTEM hive found in the archive
+    system_hive {
+        #[serde(deserialize_with = "regex_deserializer")]
+        #[serde(serialize_with = "regex_serializer")]
+        file_filter: Regex,
+    },
+}
+
+///
+/// Resolve the computer name of an archive
+/// The names are trimmed and uppercased, Windows computer names being case insensitive,
+/// to keep the computer column consistent across the collections of the same host
+/// The archive name is used when no source gives a name, uppercased like the resolved names
+/// Without sources, the archive name is kept as is to keep the ids of the rows imported by previous versions
+///
+#[derive(Clone, Debug, Default)]
+pub struct MachineIdentity {
+    sources: Vec<IdentitySource>,
+}
+impl MachineIdentity {
+    pub fn new(sources: &[IdentitySource]) -> Self {
+        Self {
+            sources: sources.to_vec(),
+        }
+    }
+
+    pub fn resolve(&self, archive_name: &str, folder: &Path) -> String {
+        for source in &self.sources {
+            let result = match source {
+                IdentitySource::archive_regex { regex } => {
+                    Ok(from_archive_name(regex, archive_name))
+                }
+                IdentitySource::orc_config => from_orc_config(folder),
+                IdentitySource::system_hive { file_filter } => {
+                    from_system_hive(file_filter, folder)
+                }
+            };
+            match result {
+                Ok(Some(name)) => {
+                    let name = name.trim().to_uppercase();
+                    if !name.is_empty() {
+                        info!("Archive:'{archive_name}' computer name resolved to '{name}'");
+                        return name;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Archive:'{archive_name}' computer name resolution error: {e}"),
+            }
+        }
+        if self.sources.is_empty() {
+            archive_name.to_owned()
+        } else {
+            archive_name.trim().to_uppercase()
+        }
+    }
+}
+
+fn from_archive_name(regex: &Regex, archive_name: &str) -> Option<String> {
+    let captures = regex.captures(archive_name)?;
+    captures
+        .name(COMPUTER_GROUP)
+        .or(captures.get(1))
+        .or(captures.get(0))
+        .map(|m| m.as_str().to_owned())
+}
+
+///
+/// Find the files of the folder and its sub folders whose name match the predicate
+/// The copies found in the registry backups or the volume shadow copies come last
+///
+fn find_files(folder: &Path, predicate: impl Fn(&str) -> bool) -> Result<Vec<PathBuf>, Error> {
+    let mut files: Vec<_> = collect_files(folder)?
+        .into_iter()
+        .filter(|file| predicate(&file.file_name))
+        .collect();
+    // the files are sorted by relative path, the sort is stable
+    files.sort_by_key(|file| is_backup_copy(&file.relative_path));
+    Ok(files.into_iter().map(|file| file.path).collect())
+}
+
+fn from_orc_config(folder: &Path) -> Result<Option<String>, Error> {
+    let files = find_files(folder, |name| {
+        ORC_CONFIG_FILES.contains(&name.to_lowercase().as_str())
+    })?;
+    for file in files {
+        let is_json = file
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let name = if is_json {
+            let json: Value = serde_json::from_reader(BufReader::new(File::open(&file)?))?;
+            find_json_computer(&json)
+        } else {
+            find_xml_computer(&file)?
+        };
+        if name.is_some() {
+            return Ok(name);
+        }
+    }
+    Ok(None)
+}
+
+fn is_computer_key(name: &str) -> bool {
+    ORC_COMPUTER_KEYS.contains(&name.to_lowercase().as_str())
+}
+
+///
+/// Depth first search of the first non empty string stored under a computer key
+///
+fn find_json_computer(json: &Value) -> Option<String> {
+    match json {
+        Value::Object(map) => {
+            for (key, value) in map {
+                if is_computer_key(key)
+                    && let Value::String(name) = value
+                    && !name.trim().is_empty()
+                {
+                    return Some(name.to_owned());
+                }
+            }
+            map.values().find_map(find_json_computer)
+        }
+        Value::Array(values) => values.iter().find_map(find_json_computer),
+        _ => None,
+    }
+}
+
+///
+/// The first computer attribute, or the text of the first computer element
+///
+fn find_xml_computer(path: &Path) -> Result<Option<String>, Error> {
+    let mut reader = Reader::from_reader(BufReader::new(File::open(path)?));
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut in_computer = false;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(element) | Event::Empty(element) => {
+                for attribute in element.attributes() {
+                    let attribute = attribute.map_err(quick_xml::Error::InvalidAttr)?;
+                    let name = attribute.key.local_name();
+                    let key = String::from_utf8_lossy(name.as_ref());
+                    let value = attribute.unescape_value()?;
+                    if is_computer_key(&key) && !value.trim().is_empty() {
+                        return Ok(Some(value.to_string()));
+                    }
+                }
+                in_computer =
+                    is_computer_key(&String::from_utf8_lossy(element.local_name().as_ref()));
+            }
+            Event::Text(text) => {
+                let text = text.unescape()?;
+                if in_computer && !text.trim().is_empty() {
+                    return Ok(Some(text.to_string()));
+                }
+            }
+            Event::End(_) => in_computer = false,
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(None)
+}
+
+///
+/// An unreadable hive, like the empty RegBack copies of recent Windows versions, does not stop the search
+///
+fn from_system_hive(file_filter: &Regex, folder: &Path) -> Result<Option<String>, Error> {
+    for file in find_files(folder, |name| file_filter.is_match(name))? {
+        match computer_name(&file) {
+            Ok(Some(name)) => return Ok(Some(name)),
+            Ok(None) => {}
+            Err(e) => warn!("SYSTEM hive '{}' cannot be read: {e}", file.display()),
+        }
+    }
+    Ok(None)
+}
+
+fn computer_name(file: &Path) -> Result<Option<String>, Error> {
+    let data = fs::read(file)?;
+    let hive = Hive::without_validation(data.as_ref())?;
+    let root = hive.root_key_node()?;
+
+    // the control set in use, defaults to the first one
+    let mut control_set = 1;
+    if let Some(select) = root.subkey("Select")
+        && let Some(current) = select?.value("Current")
+    {
+        control_set = current?.dword_data()?;
+    }
+    let path = format!("ControlSet{control_set:03}\\Control\\ComputerName\\ComputerName");
+    if let Some(key) = root.subpath(&path)
+        && let Some(value) = key?.value("ComputerName")
+    {
+        return Ok(Some(value?.string_data()?));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMP_FOLDER: &str = "data/temp/machine_identity";
+
+    #[test]
+    fn archive_regex() {
+        let identity = MachineIdentity::new(&[IdentitySource::archive_regex {
+            regex: Regex::new("^ORC_[^_]+_(?P<computer>[^_]+)_").unwrap(),
+        }]);
+        let folder = Path::new(TEMP_FOLDER);
+        assert_eq!(
+            "WKS-042",
+            identity.resolve("ORC_Server_wks-042_General", folder)
+        );
+        // no match, the archive name is kept
+        assert_eq!("MACHINE1_2025", identity.resolve("machine1_2025", folder));
+        // without sources, the archive name is not changed
+        assert_eq!(
+            "machine1_2025",
+            MachineIdentity::default().resolve("machine1_2025", folder)
+        );
+
+        let identity = MachineIdentity::new(&[IdentitySource::archive_regex {
+            regex: Regex::new("^([a-z0-9]+)_").unwrap(),
+        }]);
+        assert_eq!("MACHINE1", identity.resolve("machine1_2025", folder));
+    }
+
+    #[test]
+    fn orc_config() {
+        let folder = format!("{TEMP_FOLDER}/orc_config");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let folder = Path::new(&folder);
+
+        let identity = MachineIdentity::new(&[IdentitySource::orc_config]);
+        assert_eq!("ARCHIVE", identity.resolve("archive", folder));
+
+        fs::write(
+            folder.join("Statistics.json"),
+            r#"{"dfir-orc":{"version":"10.2","outcome":{"computer_name":"wks-042 ","command":[]}}}"#,
+        )
+        .unwrap();
+        assert_eq!("WKS-042", identity.resolve("archive", folder));
+
+        fs::write(
+            folder.join("Config.xml"),
+            r#"<?xml version="1.0"?><wolf><log>orc.log</log><ComputerName>Srv-01</ComputerName></wolf>"#,
+        )
+        .unwrap();
+        // files are read in name order
+        assert_eq!("SRV-01", identity.resolve("archive", folder));
+
+        fs::write(
+            folder.join("Config.xml"),
+            r#"<?xml version="1.0"?><wolf computer="dc-02"><log>orc.log</log></wolf>"#,
+        )
+        .unwrap();
+        assert_eq!("DC-02", identity.resolve("archive", folder));
+        let _ = fs::remove_dir_all(folder);
+    }
+
+    #[test]
+    fn system_hive() {
+        // the test hive has no ComputerName key, the next source is used
+        let identity = MachineIdentity::new(&[
+            IdentitySource::system_hive {
+                file_filter: Regex::new("^testhive$").unwrap(),
+            },
+            IdentitySource::archive_regex {
+                regex: Regex::new("^(.+)$").unwrap(),
+            },
+        ]);
+        assert_eq!(
+            "PARSER",
+            identity.resolve("parser", Path::new("data/parser"))
+        );
+
+        // KAPE layout, the hive is in a sub folder next to an empty RegBack copy
+        let folder = format!("{TEMP_FOLDER}/system_hive");
+        let _ = fs::remove_dir_all(&folder);
+        let config = format!("{folder}/C/Windows/System32/config");
+        fs::create_dir_all(format!("{config}/RegBack")).unwrap();
+        fs::write(format!("{config}/RegBack/SYSTEM"), b"").unwrap();
+        fs::copy("data/parser/SYSTEM.hive", format!("{config}/SYSTEM")).unwrap();
+
+        let identity = MachineIdentity::new(&[IdentitySource::system_hive {
+            file_filter: Regex::new("^SYSTEM$").unwrap(),
+        }]);
+        assert_eq!("WKS-042", identity.resolve("archive", Path::new(&folder)));
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
@@ -11,12 +11,26 @@ use zerocopy::IntoBytes;
 
 use crate::{
     Error,
-    writer::{clickhouse_writer::ClickhouseWriter, file_writer::FileWriter, kafka::KafkaWriter},
+    configuration::{DataTopic, DataType},
+    provenance::PARSER_VERSION,
+    writer::{
+        clickhouse_config::ClickhouseMode,
+        clickhouse_typed_writer::ClickhouseTypedWriter,
+        clickhouse_writer::{ClickhouseOptions, ClickhouseWriter},
+        dead_letter::{ErrorPolicy, GuardedWriter, OnError},
+        file_writer::{FileOptions, FileWriter},
+        http::{HttpFormat, HttpOptions, HttpWriter},
+        kafka::KafkaWriter,
+        kafka_schema::{KafkaFormat, KafkaOptions},
+        opensearch::{OpenSearchOptions, OpenSearchWriter, install_templates},
+        parquet_writer::{DEFAULT_ROW_GROUP_SIZE, ParquetCompression, ParquetFiles},
+        s3::{S3Format, S3Options, S3Writer},
+    },
 };
 use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as enc64};
 
 use serde::{Deserialize, Serialize};
-use serde_json::{Number, Value, json};
+use serde_json::{Map, Number, Value, json};
 
 pub const OUTPUT_DATE_FORMAT_UTC: &str = "%Y-%m-%d %H:%M:%S.%3f";
 ///
@@ -32,6 +46,22 @@ pub trait OutputWriter {
     /// called at the end of a parsing to ensure that the last data is writen or sent
     ///
     fn flush(&mut self) -> Result<(), Error>;
+
+    ///
+    /// called when the parsing of a file did not complete
+    /// the data written so far is flushed, unless the writer is able to discard it
+    ///
+    fn abort(&mut self) -> Result<(), Error> {
+        self.flush()
+    }
+
+    ///
+    /// called after an error of write or flush, returns the rows of the batch that could not be written
+    /// the writers sending the rows in batches keep them to be written to the dead letters
+    ///
+    fn take_rejected(&mut self) -> Vec<Tuple> {
+        Vec::new()
+    }
 }
 
 ///
@@ -49,9 +79,23 @@ impl Output {
         context: &str,
         topic: &str,
     ) -> Result<Self, Error> {
-        let mut list = vec![];
+        let mut list: Vec<Box<dyn OutputWriter>> = vec![];
         for o in output_config {
-            list.push(o.build(archive_name, file_name, context, topic)?);
+            let writer = o.build(archive_name, file_name, context, topic);
+            let errors = o.error_policy();
+            if errors.on_error == OnError::fail {
+                list.push(writer?);
+            } else {
+                list.push(Box::new(GuardedWriter::new(
+                    writer,
+                    o.name(),
+                    errors,
+                    archive_name,
+                    file_name,
+                    context,
+                    topic,
+                )?));
+            }
         }
         Ok(Self { list, num_rows: 0 })
     }
@@ -92,6 +136,16 @@ impl Output {
         }
     }
 
+    ///
+    /// Called once the file is completely parsed: flush data and release the writers
+    /// An output dropped without being completed is aborted
+    ///
+    pub fn complete(&mut self) -> Result<(), Error> {
+        let result = self.flush();
+        self.list.clear();
+        result
+    }
+
     ///
     /// returns the number of rows written to one writer
     ///
@@ -101,9 +155,11 @@ impl Output {
 }
 impl Drop for Output {
     fn drop(&mut self) {
-        //flush data before dropping the object
-        if let Err(e) = self.flush() {
-            error!("{e}")
+        //the parsing did not complete, abort the writers before dropping the object
+        for writer in &mut self.list {
+            if let Err(e) = writer.abort() {
+                error!("Error while aborting output: {e}");
+            }
         }
     }
 }
@@ -117,14 +173,65 @@ impl Drop for Output {
 pub enum OutputConfig {
     file {
         folder: String,
+        #[serde(flatten)]
+        options: FileOptions,
+        #[serde(flatten)]
+        errors: ErrorPolicy,
     },
     clickhouse {
         server: String,
         login: Option<String>,
         password: Option<String>,
+        #[serde(flatten)]
+        options: ClickhouseOptions,
+        #[serde(flatten)]
+        errors: ErrorPolicy,
     },
     kafka {
         params: HashMap<String, String>,
+        #[serde(flatten)]
+        options: KafkaOptions,
+        #[serde(flatten)]
+        errors: ErrorPolicy,
+    },
+    http {
+        url: String,
+        #[serde(flatten)]
+        options: HttpOptions,
+        #[serde(flatten)]
+        errors: ErrorPolicy,
+    },
+    opensearch {
+        url: String,
+        #[serde(default)]
+        login: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        #[serde(flatten)]
+        options: OpenSearchOptions,
+        #[serde(flatten)]
+        errors: ErrorPolicy,
+    },
+    parquet {
+        folder: String,
+        #[serde(default)]
+        row_group_size: Option<usize>,
+        #[serde(default)]
+        compression: ParquetCompression,
+        // field definition of each topic, set by prepare() before the parsing
+        #[serde(skip)]
+        topics: HashMap<String, Vec<(String, DataType)>>,
+        #[serde(skip)]
+        files: ParquetFiles,
+        #[serde(flatten)]
+        errors: ErrorPolicy,
+    },
+    s3 {
+        bucket: String,
+        #[serde(flatten)]
+        options: S3Options,
+        #[serde(flatten)]
+        errors: ErrorPolicy,
     },
 }
 impl OutputConfig {
@@ -136,29 +243,246 @@ impl OutputConfig {
         topic: &str,
     ) -> Result<Box<dyn OutputWriter>, Error> {
         match self {
-            OutputConfig::file { folder } => {
+            OutputConfig::file {
+                folder, options, ..
+            } => {
                 let mut path: PathBuf = folder.into();
                 path.push(archive_name);
                 fs::create_dir_all(&path)?;
-                path.push(format!("{file_name}.jsonl"));
-                let writer = FileWriter::new(&path)?;
+                let writer = FileWriter::new(&path, file_name, options)?;
                 Ok(Box::new(writer))
             }
             OutputConfig::clickhouse {
                 server,
                 login,
                 password,
+                options,
+                ..
+            } => match options.mode {
+                ClickhouseMode::json => {
+                    let spool = options.spool(context, topic, archive_name, file_name, "jsonl");
+                    let writer = ClickhouseWriter::new(
+                        server,
+                        login,
+                        password,
+                        context,
+                        topic,
+                        options.batch_policy(),
+                        spool,
+                    )?;
+                    Ok(Box::new(writer))
+                }
+                ClickhouseMode::typed_columns => {
+                    let partial_field_def =
+                        options.topics.get(topic).map_or(&[][..], Vec::as_slice);
+                    let spool = options.spool(context, topic, archive_name, file_name, "rowbinary");
+                    let writer = ClickhouseTypedWriter::new(
+                        server,
+                        login,
+                        password,
+                        context,
+                        topic,
+                        partial_field_def,
+                        options.batch_policy(),
+                        spool,
+                    )?;
+                    Ok(Box::new(writer))
+                }
+            },
+            OutputConfig::kafka {
+                params, options, ..
             } => {
-                let writer = ClickhouseWriter::new(server, &login, &password, context, topic)?;
+                let topic_name = &full_topic_name(context, topic);
+                let schema = match options.format {
+                    KafkaFormat::json => None,
+                    _ => Some(options.schemas.get(topic).cloned().ok_or_else(|| {
+                        Error::SchemaRegistry(format!("no schema registered for topic '{topic}'"))
+                    })?),
+                };
+                // stable per file, a new attempt fences the producer of the previous one
+                let transactional_id = options
+                    .transactional
+                    .then(|| format!("{topic_name}-{archive_name}-{file_name}"));
+                let writer = KafkaWriter::new(
+                    topic_name,
+                    params,
+                    schema,
+                    transactional_id,
+                    options.transaction_timeout(),
+                )?;
                 Ok(Box::new(writer))
             }
-            OutputConfig::kafka { params } => {
-                let topic_name = &full_topic_name(context, topic);
-                let writer = KafkaWriter::new(topic_name, params)?;
+            OutputConfig::http { url, options, .. } => {
+                let writer = HttpWriter::new(url, options, topic)?;
+                Ok(Box::new(writer))
+            }
+            OutputConfig::opensearch {
+                url,
+                login,
+                password,
+                options,
+                ..
+            } => {
+                let writer = OpenSearchWriter::new(url, login, password, options, context, topic)?;
+                Ok(Box::new(writer))
+            }
+            OutputConfig::parquet {
+                folder,
+                row_group_size,
+                compression,
+                topics,
+                files,
+                ..
+            } => {
+                // unknown topics are written with the data column only
+                let partial_field_def = topics.get(topic).map_or(&[][..], Vec::as_slice);
+                let writer = files.writer(
+                    folder,
+                    archive_name,
+                    topic,
+                    partial_field_def,
+                    row_group_size.unwrap_or(DEFAULT_ROW_GROUP_SIZE),
+                    *compression,
+                )?;
+                Ok(Box::new(writer))
+            }
+            OutputConfig::s3 {
+                bucket, options, ..
+            } => {
+                let client = options.client(bucket)?;
+                let writer =
+                    S3Writer::new(&client, options, context, archive_name, file_name, topic)?;
                 Ok(Box::new(writer))
             }
         }
     }
+
+    ///
+    /// Name of the output, written in the dead letters
+    ///
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputConfig::file { .. } => "file",
+            OutputConfig::clickhouse { .. } => "clickhouse",
+            OutputConfig::kafka { .. } => "kafka",
+            OutputConfig::http { .. } => "http",
+            OutputConfig::opensearch { .. } => "opensearch",
+            OutputConfig::parquet { .. } => "parquet",
+            OutputConfig::s3 { .. } => "s3",
+        }
+    }
+
+    pub fn error_policy(&self) -> &ErrorPolicy {
+        match self {
+            OutputConfig::file { errors, .. }
+            | OutputConfig::clickhouse { errors, .. }
+            | OutputConfig::kafka { errors, .. }
+            | OutputConfig::http { errors, .. }
+            | OutputConfig::opensearch { errors, .. }
+            | OutputConfig::parquet { errors, .. }
+            | OutputConfig::s3 { errors, .. } => errors,
+        }
+    }
+
+    ///
+    /// Returns true if the output requires the definition of the topics before the parsing
+    ///
+    pub fn needs_topics(&self) -> bool {
+        match self {
+            OutputConfig::parquet { .. } => true,
+            OutputConfig::clickhouse { options, .. } => {
+                options.mode == ClickhouseMode::typed_columns
+            }
+            OutputConfig::kafka { options, .. } => options.format != KafkaFormat::json,
+            OutputConfig::http { options, .. } => options.format == HttpFormat::splunk_hec,
+            OutputConfig::opensearch { options, .. } => options.install_templates,
+            OutputConfig::s3 { options, .. } => options.format == S3Format::parquet,
+            _ => false,
+        }
+    }
+
+    ///
+    /// Prepare the output from the definition of the topics:
+    /// - parquet, s3 with the parquet format, clickhouse with typed columns: keep the field definition used to type the columns
+    /// - http: keep the sort fields giving the time of the splunk events
+    /// - opensearch: install the index templates
+    /// - kafka with the avro or protobuf format: register the schemas
+    ///
+    pub fn prepare(
+        &mut self,
+        client_context: &str,
+        data_topics: &[DataTopic],
+    ) -> Result<(), Error> {
+        match self {
+            OutputConfig::parquet { topics, .. } => {
+                for topic in data_topics {
+                    topics.insert(topic.table_name.clone(), topic.partial_field_def.clone());
+                }
+            }
+            OutputConfig::clickhouse { options, .. } => {
+                for topic in data_topics {
+                    options
+                        .topics
+                        .insert(topic.table_name.clone(), topic.partial_field_def.clone());
+                }
+            }
+            OutputConfig::http { options, .. } => {
+                for topic in data_topics {
+                    if !topic.sort_field.is_empty() {
+                        options
+                            .sort_fields
+                            .insert(topic.table_name.clone(), topic.sort_field.clone());
+                    }
+                }
+            }
+            OutputConfig::opensearch {
+                url,
+                login,
+                password,
+                options,
+                ..
+            } if options.install_templates => {
+                install_templates(url, login, password, options, client_context, data_topics)?;
+            }
+            OutputConfig::kafka { options, .. } if options.format != KafkaFormat::json => {
+                options.register_schemas(client_context, data_topics)?;
+            }
+            OutputConfig::s3 { options, .. } => {
+                for topic in data_topics {
+                    options
+                        .topics
+                        .insert(topic.table_name.clone(), topic.partial_field_def.clone());
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    ///
+    /// Called once every file of an archive is processed
+    /// - parquet: write the footer of the parquet files of the archive
+    /// - s3: write the manifest of the objects uploaded for the archive
+    ///
+    pub fn complete_archive(
+        &self,
+        client_context: &str,
+        archive_name: &str,
+        num_errors: usize,
+    ) -> Result<(), Error> {
+        match self {
+            OutputConfig::parquet { files, .. } => files.complete(archive_name),
+            OutputConfig::s3 {
+                bucket, options, ..
+            } => options.complete_archive(
+                &options.client(bucket)?,
+                client_context,
+                archive_name,
+                num_errors,
+            ),
+            _ => Ok(()),
+        }
+    }
 }
 
 ///
@@ -168,6 +492,59 @@ pub fn full_topic_name(client_context: &str, name: &str) -> String {
     format!("{client_context}_{name}")
 }
 
+///
+/// Value of a field of the field definition
+/// The dots of the name separate the keys of nested objects, like the json paths of clickhouse: System.EventID
+///
+pub fn get_field<'a>(data: &'a Map<String, Value>, name: &str) -> Option<&'a Value> {
+    if let Some(value) = data.get(name) {
+        return Some(value);
+    }
+    name.match_indices('.')
+        .find_map(|(pos, _)| match data.get(&name[..pos]) {
+            Some(Value::Object(object)) => get_field(object, &name[pos + 1..]),
+            _ => None,
+        })
+}
+
+///
+/// Remove a field of the field definition, the nested objects left empty are removed
+///
+pub fn remove_field(data: &mut Map<String, Value>, name: &str) -> Option<Value> {
+    if let Some(value) = data.shift_remove(name) {
+        return Some(value);
+    }
+    for (pos, _) in name.match_indices('.') {
+        let key = &name[..pos];
+        if let Some(Value::Object(object)) = data.get_mut(key)
+            && let Some(value) = remove_field(object, &name[pos + 1..])
+        {
+            if object.is_empty() {
+                data.shift_remove(key);
+            }
+            return Some(value);
+        }
+    }
+    None
+}
+
+///
+/// Algorithm used to compute the tuple ids
+/// - legacy: keys and values are concatenated, the default algorithm
+/// - canonical_v1: typed and length-prefixed values, object keys are sorted
+///
+/// With both algorithms, the id depends on the data, the computer and the original file of the tuple.
+/// The computer is resolved by the machine identity sources and the original file is rebuilt from the path layouts:
+/// the rows of an archive imported before these settings existed do not keep their id
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum IdHashing {
+    #[default]
+    legacy,
+    canonical_v1,
+}
+
 ///
 /// The metadata fields common to every tuples
 ///
@@ -177,6 +554,8 @@ pub const FIELD_COMPUTER: &str = "computer";
 pub const FIELD_ORIGINAL: &str = "original_file";
 pub const FIELD_ARCHIVE: &str = "archive_name";
 pub const FIELD_DATA: &str = "data";
+pub const FIELD_ARTIFACT_HASH: &str = "artifact_hash";
+pub const FIELD_PARSER_VERSION: &str = "parser_version";
 
 ///
 /// Fields common to every tuples
@@ -188,6 +567,9 @@ pub struct Fields {
     pub original_file: String,
     pub archive_name: String,
     pub archive_file: String,
+    pub id_hashing: IdHashing,
+    // SHA-256 of the parsed file, set once the file is hashed
+    pub artifact_hash: String,
 }
 impl Fields {
     pub fn new(
@@ -207,6 +589,8 @@ impl Fields {
             original_file: original_file.to_owned(),
             archive_name: archive_name.to_owned(),
             archive_file: archive_file.to_owned(),
+            id_hashing: IdHashing::default(),
+            artifact_hash: String::new(),
         }
     }
 }
@@ -225,6 +609,9 @@ pub struct Tuple {
     pub archive_file: String,
     pub data: Option<Value>,
     pub sort_data: Option<i64>,
+    pub id_hashing: IdHashing,
+    pub artifact_hash: String,
+    pub parser_version: &'static str,
 }
 impl Tuple {
     pub fn new(fields: &Fields) -> Self {
@@ -240,6 +627,9 @@ impl Tuple {
             archive_file: fields.archive_file.to_owned(),
             data: None,
             sort_data: None,
+            id_hashing: fields.id_hashing,
+            artifact_hash: fields.artifact_hash.to_owned(),
+            parser_version: PARSER_VERSION,
         }
     }
 
@@ -271,13 +661,25 @@ impl Tuple {
     fn compute_id(&self) -> String {
         let mut hasher = blake3::Hasher::new();
 
-        if let Some(data) = &self.data {
-            //the content is allready checked in the set_data()
-            let map = data.as_object().unwrap();
-            Self::hash_json_map(&mut hasher, map);
+        match self.id_hashing {
+            IdHashing::legacy => {
+                if let Some(data) = &self.data {
+                    //the content is allready checked in the set_data()
+                    let map = data.as_object().unwrap();
+                    Self::hash_json_map(&mut hasher, map);
+                }
+                hasher.update(self.computer.as_bytes());
+                hasher.update(self.original_file.as_bytes());
+            }
+            IdHashing::canonical_v1 => {
+                hasher.update(CANONICAL_V1_PREFIX);
+                if let Some(data) = &self.data {
+                    Self::hash_canonical_value(&mut hasher, data);
+                }
+                Self::hash_canonical_str(&mut hasher, &self.computer);
+                Self::hash_canonical_str(&mut hasher, &self.original_file);
+            }
         }
-        hasher.update(self.computer.as_bytes());
-        hasher.update(self.original_file.as_bytes());
         let hash = hasher.finalize();
 
         let mut uid = [0; 16];
@@ -318,6 +720,14 @@ impl Tuple {
         );
 
         tuple.insert(FIELD_ID.to_string(), json!(self.id.to_string()));
+        tuple.insert(
+            FIELD_ARTIFACT_HASH.to_string(),
+            Value::String(self.artifact_hash),
+        );
+        tuple.insert(
+            FIELD_PARSER_VERSION.to_string(),
+            Value::String(self.parser_version.to_owned()),
+        );
 
         if let Some(val) = self.data {
             tuple.insert(FIELD_DATA.to_string(), val);
@@ -372,11 +782,85 @@ impl Tuple {
             Self::hash_json_value(hasher, value);
         }
     }
+
+    ///
+    /// Every value is prefixed by a type tag, strings and containers by their length
+    /// so that different values cannot produce the same byte stream
+    ///
+    fn hash_canonical_value(hasher: &mut blake3::Hasher, value: &Value) {
+        match value {
+            Value::Null => {
+                hasher.update(&[TAG_NULL]);
+            }
+            Value::Bool(v) => {
+                hasher.update(&[TAG_BOOL, *v as u8]);
+            }
+            Value::Number(number) => Self::hash_canonical_number(hasher, number),
+            Value::String(v) => {
+                hasher.update(&[TAG_STRING]);
+                Self::hash_canonical_str(hasher, v);
+            }
+            Value::Array(values) => {
+                hasher.update(&[TAG_ARRAY]);
+                hasher.update(&(values.len() as u64).to_le_bytes());
+                for value in values {
+                    Self::hash_canonical_value(hasher, value);
+                }
+            }
+            Value::Object(map) => {
+                // the key order does not change the id
+                let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+                entries.sort_unstable_by_key(|(key, _)| *key);
+
+                hasher.update(&[TAG_OBJECT]);
+                hasher.update(&(entries.len() as u64).to_le_bytes());
+                for (key, value) in entries {
+                    Self::hash_canonical_str(hasher, key);
+                    Self::hash_canonical_value(hasher, value);
+                }
+            }
+        }
+    }
+
+    ///
+    /// Integers have the same representation whether they are parsed as signed or unsigned
+    ///
+    fn hash_canonical_number(hasher: &mut blake3::Hasher, num: &Number) {
+        if let Some(num) = num.as_i64() {
+            hasher.update(&[TAG_INTEGER]);
+            hasher.update(&num.to_le_bytes());
+        } else if let Some(num) = num.as_u64() {
+            hasher.update(&[TAG_UNSIGNED]);
+            hasher.update(&num.to_le_bytes());
+        } else if let Some(num) = num.as_f64() {
+            hasher.update(&[TAG_FLOAT]);
+            hasher.update(&num.to_le_bytes());
+        }
+    }
+
+    fn hash_canonical_str(hasher: &mut blake3::Hasher, value: &str) {
+        hasher.update(&(value.len() as u64).to_le_bytes());
+        hasher.update(value.as_bytes());
+    }
 }
 
+///
+/// Domain separation of the canonical hashing scheme, a new version must use a new prefix
+///
+const CANONICAL_V1_PREFIX: &[u8] = b"tuple-id-v1";
+
+const TAG_NULL: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_INTEGER: u8 = 2;
+const TAG_UNSIGNED: u8 = 3;
+const TAG_FLOAT: u8 = 4;
+const TAG_STRING: u8 = 5;
+const TAG_ARRAY: u8 = 6;
+const TAG_OBJECT: u8 = 7;
+
 #[cfg(test)]
 mod tests {
-    use std::collections::HashSet;
+    use std::{cell::RefCell, collections::HashSet, rc::Rc};
 
     use rand::{TryRngCore, rngs::OsRng};
     use serde_json::{Value, json};
@@ -385,7 +869,52 @@ mod tests {
         FIELD_ARCHIVE, FIELD_COMPUTER, FIELD_DATA, FIELD_ID, FIELD_IMPORT_DATE, FIELD_ORIGINAL,
     };
 
-    use super::{Fields, Tuple};
+    use super::{Fields, IdHashing, Output, OutputWriter, Tuple, get_field, remove_field};
+
+    ///
+    /// Record the calls of the output
+    ///
+    struct CallWriter(Rc<RefCell<Vec<&'static str>>>);
+    impl OutputWriter for CallWriter {
+        fn write(&mut self, _: Tuple) -> Result<(), crate::Error> {
+            self.0.borrow_mut().push("write");
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), crate::Error> {
+            self.0.borrow_mut().push("flush");
+            Ok(())
+        }
+
+        fn abort(&mut self) -> Result<(), crate::Error> {
+            self.0.borrow_mut().push("abort");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn complete_or_abort() {
+        let fields = Fields::new("machine_id", "original_file", "archive", "file");
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut output = Output {
+            list: vec![Box::new(CallWriter(calls.clone()))],
+            num_rows: 0,
+        };
+        output.write(Tuple::new(&fields)).unwrap();
+        output.complete().unwrap();
+        drop(output);
+        assert_eq!(vec!["write", "flush"], *calls.borrow());
+
+        // dropped before completion
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut output = Output {
+            list: vec![Box::new(CallWriter(calls.clone()))],
+            num_rows: 0,
+        };
+        output.write(Tuple::new(&fields)).unwrap();
+        drop(output);
+        assert_eq!(vec!["write", "abort"], *calls.borrow());
+    }
 
     #[test]
     fn unique_id() {
@@ -447,6 +976,36 @@ mod tests {
         assert_eq!("1N0BAAAAAAAb3tT1XT2sSw", tuple.id);
     }
 
+    #[test]
+    fn canonical_id() {
+        let mut fields = Fields::new("machine", "original", "archive_name", "archive_file");
+        fields.id_hashing = IdHashing::canonical_v1;
+        let id = |data: Value| {
+            let mut tuple = Tuple::new(&fields);
+            tuple.set_data(data, None).unwrap();
+            tuple.id
+        };
+
+        //keys and values cannot be confused
+        assert_ne!(id(json!({"a": "bc"})), id(json!({"ab": "c"})));
+        assert_ne!(id(json!({"a": ["b", "c"]})), id(json!({"a": ["bc"]})));
+        assert_ne!(id(json!({"a": "1"})), id(json!({"a": 1})));
+        assert_ne!(id(json!({"a": null})), id(json!({})));
+
+        //semantically equal objects have the same id
+        assert_eq!(id(json!({"a": 1, "b": 2})), id(json!({"b": 2, "a": 1})));
+        assert_eq!(id(json!({"a": 1u64})), id(json!({"a": 1i64})));
+
+        //the legacy scheme is the default one
+        let legacy = Fields::new("machine", "original", "archive_name", "archive_file");
+        assert_eq!(IdHashing::legacy, legacy.id_hashing);
+        let mut tuple = Tuple::new(&legacy);
+        tuple.set_data(json!({"a": "bc"}), None).unwrap();
+        let mut other = Tuple::new(&legacy);
+        other.set_data(json!({"ab": "c"}), None).unwrap();
+        assert_eq!(tuple.id, other.id);
+    }
+
     #[test]
     fn to_json_string() {
         let fields = Fields::new(
@@ -480,4 +1039,32 @@ mod tests {
         assert_eq!(1.87, data.get("i").unwrap().as_f64().unwrap());
         assert_eq!("test", data.get("rd").unwrap().as_str().unwrap());
     }
+
+    #[test]
+    fn nested_fields() {
+        let mut data = json!({
+            "System": {"EventID": 4624, "Provider": {"Name": "Security"}},
+            "System.Level": 4,
+            "User": "bob"
+        });
+        let data = data.as_object_mut().unwrap();
+        assert_eq!(Some(&json!(4624)), get_field(data, "System.EventID"));
+        assert_eq!(Some(&json!(4)), get_field(data, "System.Level"));
+        assert_eq!(
+            Some(&json!("Security")),
+            get_field(data, "System.Provider.Name")
+        );
+        assert_eq!(None, get_field(data, "System.Task"));
+        assert_eq!(None, get_field(data, "User.Name"));
+
+        assert_eq!(
+            Some(json!("Security")),
+            remove_field(data, "System.Provider.Name")
+        );
+        assert_eq!(Some(json!(4624)), remove_field(data, "System.EventID"));
+        assert_eq!(Some(json!(4)), remove_field(data, "System.Level"));
+        assert_eq!(None, remove_field(data, "System.EventID"));
+        // the empty objects are removed
+        assert_eq!(&json!({"User": "bob"}), &Value::Object(data.clone()));
+    }
 }
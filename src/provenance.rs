@@ -0,0 +1,307 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    configuration::{Configuration, DataType, ParserType},
+    errors::Error,
+    output::{Fields, OUTPUT_DATE_FORMAT_UTC, Output, OutputConfig, Tuple},
+};
+
+///
+/// Version of the parsers, stored in every rows
+///
+pub const PARSER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const EVIDENCE_MANIFEST_TABLE_NAME: &str = "evidence_manifest";
+pub const EVIDENCE_MANIFEST_SORT_FIELD: &str = "ProcessedAt";
+
+///
+/// Allocate 1 MB to the hashing buffer
+///
+const HASH_BUFFER_CAPACITY: usize = 1024 * 1024;
+
+pub fn evidence_manifest_fields() -> Vec<(String, DataType)> {
+    vec![
+        (EVIDENCE_MANIFEST_SORT_FIELD.to_owned(), DataType::Date),
+        ("Host".to_owned(), DataType::String),
+        ("ConfigurationHash".to_owned(), DataType::String),
+        ("Parser".to_owned(), DataType::String),
+        ("ParserVersion".to_owned(), DataType::String),
+        ("ArchivePath".to_owned(), DataType::String),
+        ("ArchiveSize".to_owned(), DataType::Uint64),
+        ("ArchiveSha256".to_owned(), DataType::String),
+        ("ArchiveBlake3".to_owned(), DataType::String),
+        ("ArtifactPath".to_owned(), DataType::String),
+        ("ArtifactSize".to_owned(), DataType::Uint64),
+        ("ArtifactSha256".to_owned(), DataType::String),
+        ("ArtifactBlake3".to_owned(), DataType::String),
+        ("NumRows".to_owned(), DataType::Int64),
+        ("Error".to_owned(), DataType::String),
+    ]
+}
+
+///
+/// Identify a source archive or a parsed artifact
+///
+#[derive(Clone, Debug)]
+pub struct FileEvidence {
+    // path of the archive, or path of the artifact in the archive
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    pub blake3: String,
+}
+impl FileEvidence {
+    ///
+    /// Hash the file, the SHA-256 and BLAKE3 hashes are computed in a single read
+    ///
+    pub fn from_file<P: AsRef<Path>>(path: P, name: &str) -> Result<Self, Error> {
+        let mut reader = BufReader::with_capacity(HASH_BUFFER_CAPACITY, File::open(path)?);
+        let mut sha256 = Sha256::new();
+        let mut blake3 = blake3::Hasher::new();
+        let mut buffer = vec![0; HASH_BUFFER_CAPACITY];
+        let mut size = 0;
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            sha256.update(&buffer[..read]);
+            blake3.update(&buffer[..read]);
+            size += read as u64;
+        }
+        Ok(Self {
+            path: name.to_owned(),
+            size,
+            sha256: format!("{:x}", sha256.finalize()),
+            blake3: blake3.finalize().to_hex().to_string(),
+        })
+    }
+}
+
+///
+/// Identify who processed the evidences and how
+///
+#[derive(Clone, Debug)]
+pub struct Provenance {
+    pub host: String,
+    pub configuration_hash: String,
+}
+impl Provenance {
+    pub fn new(configuration: &Configuration) -> Result<Self, Error> {
+        let configuration =
+            serde_yml::to_string(configuration).map_err(|e| Error::Configuration(e.to_string()))?;
+        Ok(Self {
+            host: gethostname::gethostname().to_string_lossy().to_string(),
+            configuration_hash: blake3::hash(configuration.as_bytes()).to_hex().to_string(),
+        })
+    }
+}
+
+///
+/// An entry of the evidence_manifest table: when, where and how an artifact was processed
+///
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ManifestEntry<'a> {
+    processed_at: String,
+    host: &'a str,
+    configuration_hash: &'a str,
+    parser: String,
+    parser_version: &'a str,
+    archive_path: Option<&'a str>,
+    archive_size: Option<u64>,
+    archive_sha256: Option<&'a str>,
+    archive_blake3: Option<&'a str>,
+    artifact_path: &'a str,
+    artifact_size: u64,
+    artifact_sha256: &'a str,
+    artifact_blake3: &'a str,
+    num_rows: usize,
+    error: Option<String>,
+}
+
+///
+/// Build the evidence_manifest entry of an artifact
+/// The entries are sent to the archive thread, which writes them in a single EvidenceManifest
+///
+#[allow(clippy::too_many_arguments)]
+pub fn manifest_tuple(
+    fields: &Fields,
+    provenance: &Provenance,
+    parser: &ParserType,
+    processed_at: DateTime<Utc>,
+    archive: Option<&FileEvidence>,
+    artifact: &FileEvidence,
+    num_rows: usize,
+    error: Option<&Error>,
+) -> Result<Tuple, Error> {
+    let entry = ManifestEntry {
+        processed_at: processed_at.format(OUTPUT_DATE_FORMAT_UTC).to_string(),
+        host: &provenance.host,
+        configuration_hash: &provenance.configuration_hash,
+        // the parser name, or its json configuration when it has parameters
+        parser: match serde_json::to_value(parser)? {
+            Value::String(name) => name,
+            value => value.to_string(),
+        },
+        parser_version: PARSER_VERSION,
+        archive_path: archive.map(|archive| archive.path.as_str()),
+        archive_size: archive.map(|archive| archive.size),
+        archive_sha256: archive.map(|archive| archive.sha256.as_str()),
+        archive_blake3: archive.map(|archive| archive.blake3.as_str()),
+        artifact_path: &artifact.path,
+        artifact_size: artifact.size,
+        artifact_sha256: &artifact.sha256,
+        artifact_blake3: &artifact.blake3,
+        num_rows,
+        error: error.map(|e| e.to_string()),
+    };
+    let mut tuple = Tuple::new(fields);
+    tuple.set_data(serde_json::to_value(entry)?, Some(processed_at.timestamp()))?;
+    Ok(tuple)
+}
+
+///
+/// The evidence_manifest output of an archive
+/// The outputs are created with the first entry and completed once every file of the archive is processed
+///
+pub struct EvidenceManifest<'a> {
+    output_config: &'a [OutputConfig],
+    client_context: &'a str,
+    archive_name: &'a str,
+    output: Option<Output>,
+}
+impl<'a> EvidenceManifest<'a> {
+    pub fn new(
+        output_config: &'a [OutputConfig],
+        client_context: &'a str,
+        archive_name: &'a str,
+    ) -> Self {
+        Self {
+            output_config,
+            client_context,
+            archive_name,
+            output: None,
+        }
+    }
+
+    pub fn write(&mut self, tuple: Tuple) -> Result<(), Error> {
+        let output = match &mut self.output {
+            Some(output) => output,
+            None => {
+                // the manifest file must not overwrite the data files
+                let file_name = format!("{}.{EVIDENCE_MANIFEST_TABLE_NAME}", self.archive_name);
+                self.output.insert(Output::new(
+                    self.output_config,
+                    self.archive_name,
+                    &file_name,
+                    self.client_context,
+                    EVIDENCE_MANIFEST_TABLE_NAME,
+                )?)
+            }
+        };
+        output.write(tuple)
+    }
+
+    pub fn complete(&mut self) -> Result<(), Error> {
+        match self.output.take() {
+            Some(mut output) => output.complete(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::writer::{dead_letter::ErrorPolicy, file_writer::FileOptions};
+
+    use super::*;
+
+    #[test]
+    fn hash_file() {
+        let evidence = FileEvidence::from_file("data/parser/app.log", "logs/app.log").unwrap();
+        assert_eq!("logs/app.log", evidence.path);
+        assert_eq!(
+            std::fs::metadata("data/parser/app.log").unwrap().len(),
+            evidence.size
+        );
+        assert_eq!(64, evidence.sha256.len());
+        assert_eq!(64, evidence.blake3.len());
+
+        let mut buffer = Vec::new();
+        File::open("data/parser/app.log")
+            .unwrap()
+            .read_to_end(&mut buffer)
+            .unwrap();
+        assert_eq!(blake3::hash(&buffer).to_hex().to_string(), evidence.blake3);
+        assert_eq!(format!("{:x}", Sha256::digest(&buffer)), evidence.sha256);
+    }
+
+    #[test]
+    fn empty_file_hash() {
+        let path = "data/temp/provenance_empty_file";
+        std::fs::create_dir_all("data/temp").unwrap();
+        File::create(path).unwrap();
+        let evidence = FileEvidence::from_file(path, "empty").unwrap();
+        let _ = std::fs::remove_file(path);
+        assert_eq!(0, evidence.size);
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            evidence.sha256
+        );
+    }
+
+    #[test]
+    fn one_manifest_per_archive() {
+        let folder = "data/temp/provenance_manifest";
+        let _ = std::fs::remove_dir_all(folder);
+        let output_config = vec![OutputConfig::file {
+            folder: folder.to_owned(),
+            options: FileOptions::default(),
+            errors: ErrorPolicy::default(),
+        }];
+        let provenance = Provenance {
+            host: "host".to_owned(),
+            configuration_hash: "hash".to_owned(),
+        };
+        let artifact = FileEvidence::from_file("data/parser/app.log", "logs/app.log").unwrap();
+
+        let mut manifest = EvidenceManifest::new(&output_config, "test", "archive");
+        for file in ["app.log", "app.1.log"] {
+            let fields = Fields::new("machine", file, "archive", file);
+            let tuple = manifest_tuple(
+                &fields,
+                &provenance,
+                &ParserType::evtx,
+                Utc::now(),
+                None,
+                &artifact,
+                10,
+                None,
+            )
+            .unwrap();
+            manifest.write(tuple).unwrap();
+        }
+        manifest.complete().unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(format!("{folder}/archive"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(vec!["archive.evidence_manifest.jsonl"], files);
+        let content =
+            std::fs::read_to_string(format!("{folder}/archive/archive.evidence_manifest.jsonl"))
+                .unwrap();
+        assert_eq!(2, content.lines().count());
+        let _ = std::fs::remove_dir_all(folder);
+    }
+}
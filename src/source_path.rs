@@ -0,0 +1,331 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error,
+    configuration::{regex_deserializer, regex_serializer},
+};
+
+///
+/// File listing the collected files of a DFIR ORC archive with their original path
+///
+const ORC_GET_THIS_FILE: &str = "getthis.csv";
+const ORC_FULL_NAME: &str = "FullName";
+const ORC_SAMPLE_NAME: &str = "SampleName";
+
+///
+/// KAPE copies the files under a folder named after the drive letter, optionally in a volume shadow copy folder
+///
+const KAPE_REGEX: &str = r"^(?:vss\d+/)?(?P<drive>[A-Za-z])/(?P<path>.+)$";
+
+///
+/// Velociraptor stores the uploads under the accessor name, with a percent encoded drive
+///
+const VELOCIRAPTOR_REGEX: &str =
+    r"^(?:.*/)?uploads/(?:auto|file|ntfs)/(?:%5C%5C\.%5C)?(?P<drive>[A-Za-z])%3A/(?P<path>.+)$";
+
+///
+/// Rules translating the layout of a collector back to the path on the source computer
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_camel_case_types)]
+pub enum PathLayout {
+    // the FullName of the file in the GetThis.csv file of the archive
+    orc,
+    // C/Users/bob/NTUSER.DAT
+    kape,
+    // uploads/auto/C%3A/Users/bob/NTUSER.DAT
+    velociraptor,
+    // the 'drive' and 'path' capture groups of a regex applied to the relative path
+    custom {
+        #[serde(deserialize_with = "regex_deserializer")]
+        #[serde(serialize_with = "regex_serializer")]
+        regex: Regex,
+    },
+}
+
+///
+/// A file found in a collection
+///
+#[derive(Debug)]
+pub struct CollectedFile {
+    pub path: PathBuf,
+    // path relative to the collection root, with '/' separators
+    pub relative_path: String,
+    pub file_name: String,
+}
+impl CollectedFile {
+    ///
+    /// A name unique in the collection, used to name the output files
+    /// The '/' separators are replaced by '_', the '%' and '_' of the path are percent encoded
+    /// so that two paths never share a name: a/b_c -> a_b%5Fc, a_b/c -> a%5Fb_c
+    ///
+    pub fn flat_name(&self) -> String {
+        self.relative_path
+            .replace('%', "%25")
+            .replace('_', "%5F")
+            .replace('/', "_")
+    }
+}
+
+///
+/// Folders of the registry backups, compared in lowercase
+///
+const BACKUP_FOLDERS: [&str; 1] = ["regback"];
+
+///
+/// True for the copies of a file kept in a registry backup or a volume shadow copy:
+/// Windows/System32/config/RegBack/SOFTWARE, vss1/C/Windows/System32/config/SOFTWARE
+///
+pub fn is_backup_copy(path: &str) -> bool {
+    path.split(['\\', '/']).any(|component| {
+        let component = component.to_lowercase();
+        BACKUP_FOLDERS.contains(&component.as_str())
+            || component.contains("shadowcopy")
+            || component
+                .strip_prefix("vss")
+                .is_some_and(|num| !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+///
+/// List the files of a collection, in its sub folders included
+///
+pub fn collect_files(root: &Path) -> Result<Vec<CollectedFile>, Error> {
+    let mut files = Vec::new();
+    let mut folders = vec![(root.to_path_buf(), String::new())];
+    while let Some((folder, prefix)) = folders.pop() {
+        for entry in fs::read_dir(&folder)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let relative_path = format!("{prefix}{file_name}");
+            if entry.file_type()?.is_dir() {
+                folders.push((entry.path(), format!("{relative_path}/")));
+            } else {
+                files.push(CollectedFile {
+                    path: entry.path(),
+                    relative_path,
+                    file_name,
+                });
+            }
+        }
+    }
+    files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+    Ok(files)
+}
+
+///
+/// Translate the path of the collected files to their original path
+/// The relative path is kept when no layout applies
+///
+#[derive(Default)]
+pub struct SourcePaths {
+    regexes: Vec<Regex>,
+    // lowercase relative path -> original path
+    orc_names: HashMap<String, String>,
+}
+impl SourcePaths {
+    pub fn new(layouts: &[PathLayout], root: &Path) -> Result<Self, Error> {
+        let mut orc_names = HashMap::new();
+        if layouts
+            .iter()
+            .any(|layout| matches!(layout, PathLayout::orc))
+        {
+            read_orc_names(root, &mut orc_names)?;
+        }
+        let regexes = layouts
+            .iter()
+            .filter_map(|layout| match layout {
+                PathLayout::orc => None,
+                PathLayout::kape => Some(Regex::new(KAPE_REGEX)),
+                PathLayout::velociraptor => Some(Regex::new(VELOCIRAPTOR_REGEX)),
+                PathLayout::custom { regex } => Some(Ok(regex.clone())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { regexes, orc_names })
+    }
+
+    pub fn original_path(&self, relative_path: &str) -> String {
+        if let Some(name) = self.orc_names.get(&relative_path.to_lowercase()) {
+            return name.to_owned();
+        }
+        for regex in &self.regexes {
+            if let Some(captures) = regex.captures(relative_path)
+                && let Some(path) = captures.name("path")
+            {
+                let path = percent_decode(path.as_str()).replace('/', "\\");
+                return match captures.name("drive") {
+                    Some(drive) => format!("{}:\\{path}", drive.as_str().to_uppercase()),
+                    None => path,
+                };
+            }
+        }
+        relative_path.to_owned()
+    }
+}
+
+///
+/// GetThis.csv files can be found at the root or in the sub folders of the archive
+/// The sample names are relative to the folder of the GetThis.csv file
+///
+fn read_orc_names(root: &Path, names: &mut HashMap<String, String>) -> Result<(), Error> {
+    for file in collect_files(root)? {
+        if file.file_name.to_lowercase() != ORC_GET_THIS_FILE {
+            continue;
+        }
+        let folder = &file.relative_path[..file.relative_path.len() - file.file_name.len()];
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_path(&file.path)?;
+        let headers = reader.headers()?.clone();
+        let position = |name: &str| headers.iter().position(|header| header == name);
+        let (Some(full_name), Some(sample_name)) =
+            (position(ORC_FULL_NAME), position(ORC_SAMPLE_NAME))
+        else {
+            warn!("'{}' is not a GetThis.csv file", file.relative_path);
+            continue;
+        };
+        for record in reader.records() {
+            let record = record?;
+            if let (Some(full_name), Some(sample_name)) =
+                (record.get(full_name), record.get(sample_name))
+                && !full_name.is_empty()
+                && !sample_name.is_empty()
+            {
+                let sample_name = format!("{folder}{}", sample_name.replace('\\', "/"));
+                names.insert(sample_name.to_lowercase(), full_name.to_owned());
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// Decode the %XX sequences, invalid sequences are kept as is
+///
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'%'
+            && let Some(hex) = value.get(pos + 1..pos + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            decoded.push(byte);
+            pos += 3;
+        } else {
+            decoded.push(bytes[pos]);
+            pos += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMP_FOLDER: &str = "data/temp/source_path";
+
+    #[test]
+    fn collector_layouts() {
+        let paths = SourcePaths::new(
+            &[
+                PathLayout::kape,
+                PathLayout::velociraptor,
+                PathLayout::custom {
+                    regex: Regex::new(r"^mount/(?P<path>.+)$").unwrap(),
+                },
+            ],
+            Path::new("data/archive/decompressed/machine1_2025"),
+        )
+        .unwrap();
+        assert_eq!(
+            "C:\\Users\\bob\\NTUSER.DAT",
+            paths.original_path("C/Users/bob/NTUSER.DAT")
+        );
+        assert_eq!(
+            "C:\\Windows\\System32\\winevt\\Logs\\Security.evtx",
+            paths.original_path("vss1/C/Windows/System32/winevt/Logs/Security.evtx")
+        );
+        assert_eq!(
+            "C:\\Users\\alice\\NTUSER.DAT",
+            paths.original_path("uploads/auto/C%3A/Users/alice/NTUSER.DAT")
+        );
+        assert_eq!(
+            "D:\\$MFT",
+            paths.original_path("collection/uploads/ntfs/%5C%5C.%5CD%3A/%24MFT")
+        );
+        assert_eq!("etc\\passwd", paths.original_path("mount/etc/passwd"));
+        assert_eq!("SRUDB.dat", paths.original_path("SRUDB.dat"));
+    }
+
+    #[test]
+    fn backup_copy() {
+        assert!(!is_backup_copy("C/Windows/System32/config/SOFTWARE"));
+        assert!(is_backup_copy("C/Windows/System32/config/RegBack/SOFTWARE"));
+        assert!(is_backup_copy("vss1/C/Windows/System32/config/SOFTWARE"));
+        assert!(is_backup_copy(
+            "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1\\Windows\\System32\\config\\SOFTWARE"
+        ));
+        assert!(!is_backup_copy("C/Users/vssadmin/NTUSER.DAT"));
+    }
+
+    #[test]
+    fn flat_name() {
+        let flat_name = |relative_path: &str| {
+            CollectedFile {
+                path: PathBuf::from(relative_path),
+                relative_path: relative_path.to_owned(),
+                file_name: String::new(),
+            }
+            .flat_name()
+        };
+        assert_eq!(
+            "C_Windows_Security.evtx",
+            flat_name("C/Windows/Security.evtx")
+        );
+        assert_ne!(flat_name("a/b_c"), flat_name("a_b/c"));
+        assert_ne!(flat_name("a_/b"), flat_name("a/_b"));
+        assert_ne!(flat_name("a%5F/b"), flat_name("a_/b"));
+    }
+
+    #[test]
+    fn orc_get_this() {
+        let root = format!("{TEMP_FOLDER}/orc_get_this");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(format!("{root}/Hives")).unwrap();
+        fs::write(
+            format!("{root}/Hives/GetThis.csv"),
+            "ComputerName,VolumeID,FullName,SampleName\r\n\
+             WKS,0x1,\\Users\\bob\\NTUSER.DAT,NTUSER_DAT_00010000_data\r\n\
+             WKS,0x1,\\Users\\alice\\NTUSER.DAT,NTUSER_DAT_00020000_data\r\n",
+        )
+        .unwrap();
+        fs::write(format!("{root}/Hives/NTUSER_DAT_00010000_data"), "").unwrap();
+
+        let files = collect_files(Path::new(&root)).unwrap();
+        assert_eq!(2, files.len());
+        assert_eq!("Hives/GetThis.csv", files[0].relative_path);
+        assert_eq!("Hives_NTUSER%5FDAT%5F00010000%5Fdata", files[1].flat_name());
+
+        let paths = SourcePaths::new(&[PathLayout::orc], Path::new(&root)).unwrap();
+        assert_eq!(
+            "\\Users\\alice\\NTUSER.DAT",
+            paths.original_path("Hives/NTUSER_DAT_00020000_data")
+        );
+        assert_eq!(
+            "NTUSER_DAT_00020000_data",
+            paths.original_path("NTUSER_DAT_00020000_data")
+        );
+        let _ = fs::remove_dir_all(&root);
+    }
+}
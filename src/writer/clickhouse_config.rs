@@ -1,5 +1,6 @@
 use clickhouse::Client;
 use log::info;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     Error,
@@ -7,10 +8,59 @@ use crate::{
     output::*,
 };
 
+///
+/// Layout of the tables
+/// - json: every field is stored in the data column, the fields of the definition are typed json paths
+/// - typed_columns: the fields of the definition are columns, the other fields are stored in the data column
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum ClickhouseMode {
+    #[default]
+    json,
+    typed_columns,
+}
+
+///
+/// The names of the metadata columns, the fields of the definition using one of them are kept in the data column
+///
+const RESERVED_COLUMNS: [&str; 8] = [
+    FIELD_ID,
+    FIELD_IMPORT_DATE,
+    FIELD_COMPUTER,
+    FIELD_ORIGINAL,
+    FIELD_ARCHIVE,
+    FIELD_ARTIFACT_HASH,
+    FIELD_PARSER_VERSION,
+    FIELD_DATA,
+];
+
+///
+/// The fields of the definition stored as columns in the typed_columns mode
+///
+pub fn typed_columns(partial_field_def: &[(String, DataType)]) -> Vec<(String, DataType)> {
+    partial_field_def
+        .iter()
+        .filter(|(name, _)| !RESERVED_COLUMNS.contains(&name.as_str()))
+        .cloned()
+        .collect()
+}
+
+///
+/// The column holding a field of the definition
+///
+fn field_column(field: &str, mode: ClickhouseMode) -> String {
+    match mode {
+        ClickhouseMode::typed_columns if !RESERVED_COLUMNS.contains(&field) => format!("`{field}`"),
+        _ => format!("{FIELD_DATA}.{field}"),
+    }
+}
+
 ///
 /// Create database and required tables for every topics..
 /// re-entrant: can be run several times without any risk of data deletion
 ///
+#[allow(clippy::too_many_arguments)]
 pub async fn create_database(
     client: &Client,
     db: &str,
@@ -19,7 +69,14 @@ pub async fn create_database(
     enable_kafka: bool,
     kafka_brokers: &str,
     consumers: usize,
+    mode: ClickhouseMode,
 ) -> Result<(), Error> {
+    //the kafka tables feed the data column only
+    if enable_kafka && mode == ClickhouseMode::typed_columns {
+        return Err(Error::Generic(
+            "the kafka tables cannot be created for tables with typed columns".to_owned(),
+        ));
+    }
     let database_query = &database_query(db, cluster);
     client.query(database_query).execute().await?;
 
@@ -29,18 +86,19 @@ pub async fn create_database(
 
     for topic in topics {
         info!("creating tables for '{}'", topic.table_name);
-        //create main table
-        if topic
-            .partial_field_def
-            .iter()
-            .find_map(|field| {
-                if field.0.eq(&topic.sort_field) {
-                    Some(field)
-                } else {
-                    None
-                }
-            })
-            .is_none()
+        //create main table, the sort field is optional
+        if !topic.sort_field.is_empty()
+            && topic
+                .partial_field_def
+                .iter()
+                .find_map(|field| {
+                    if field.0.eq(&topic.sort_field) {
+                        Some(field)
+                    } else {
+                        None
+                    }
+                })
+                .is_none()
         {
             return Err(Error::ClickhouseSortField(
                 topic.table_name.to_owned(),
@@ -54,10 +112,24 @@ pub async fn create_database(
             &topic.partial_field_def,
             &topic.sort_field,
             cluster,
+            mode,
         );
         client.query(&table_query).execute().await?;
 
+        //tables created by previous versions do not have the provenance columns
+        let provenance_query = provenance_columns_query(db, &topic.table_name, cluster);
+        client.query(&provenance_query).execute().await?;
+
         if enable_kafka {
+            //the kafka tables of previous versions cannot be altered, they are recreated
+            //the consumer offsets are kept by kafka, no message is lost
+            if kafka_table_is_outdated(client, db, &topic.table_name).await? {
+                info!("recreating the kafka tables of '{}'", topic.table_name);
+                for query in drop_kafka_tables_queries(db, &topic.table_name, cluster) {
+                    client.query(&query).execute().await?;
+                }
+            }
+
             //create kafka table
             let kafka_table_query = kafka_table_query(
                 db,
@@ -77,15 +149,20 @@ pub async fn create_database(
         for (field, data_type) in &topic.partial_field_def {
             if let DataType::Date = data_type {
                 //create materialized views that feeds the timeline table
-                let timeline_short_mat =
-                    timeline_short_materialized_view_query(db, &topic.table_name, field, cluster);
+                let timeline_short_mat = timeline_short_materialized_view_query(
+                    db,
+                    &topic.table_name,
+                    field,
+                    cluster,
+                    mode,
+                );
                 client.query(&timeline_short_mat).execute().await?;
             }
         }
     }
 
     //create timeline views
-    let timeline_data_view = timeline_data_view_query(db, topics, cluster);
+    let timeline_data_view = timeline_data_view_query(db, topics, cluster, mode);
     client.query(&timeline_data_view).execute().await?;
 
     let timeline_view = timeline_view_query(db, cluster);
@@ -118,6 +195,8 @@ fn kafka_table_query(
             {FIELD_COMPUTER} String,
             {FIELD_ORIGINAL} String,
             {FIELD_ARCHIVE} String,
+            {FIELD_ARTIFACT_HASH} String,
+            {FIELD_PARSER_VERSION} String,
             {FIELD_DATA} String,
 
         ) 
@@ -139,13 +218,19 @@ fn table_query(
     partial_field_def: &Vec<(String, DataType)>,
     sort_field: &str,
     cluster: &Option<String>,
+    mode: ClickhouseMode,
 ) -> String {
     let cluster = cluster_def(cluster);
-    let json_type = build_json_type(partial_field_def);
+    let (columns, json_type) = match mode {
+        ClickhouseMode::json => (String::new(), build_json_type(partial_field_def)),
+        ClickhouseMode::typed_columns => {
+            (build_typed_columns(partial_field_def), "JSON".to_owned())
+        }
+    };
     let sort_field = if sort_field.is_empty() {
         sort_field.to_owned()
     } else {
-        format!(", data.{sort_field}")
+        format!(", {}", field_column(sort_field, mode))
     };
 
     format!(
@@ -156,6 +241,8 @@ fn table_query(
             {FIELD_COMPUTER} LowCardinality(String),
             {FIELD_ORIGINAL} LowCardinality(String),
             {FIELD_ARCHIVE} LowCardinality(String),
+            {FIELD_ARTIFACT_HASH} LowCardinality(String),
+            {FIELD_PARSER_VERSION} LowCardinality(String),{columns}
             {FIELD_DATA} {json_type},
         ) 
         ENGINE = ReplacingMergeTree({FIELD_IMPORT_DATE})
@@ -164,6 +251,50 @@ fn table_query(
     )
 }
 
+///
+/// Add the provenance columns to an existing table
+///
+fn provenance_columns_query(db: &str, table_name: &str, cluster: &Option<String>) -> String {
+    let cluster = cluster_def(cluster);
+    format!(
+        "ALTER TABLE {db}.{table_name} {cluster}
+            ADD COLUMN IF NOT EXISTS {FIELD_ARTIFACT_HASH} LowCardinality(String) AFTER {FIELD_ARCHIVE},
+            ADD COLUMN IF NOT EXISTS {FIELD_PARSER_VERSION} LowCardinality(String) AFTER {FIELD_ARTIFACT_HASH}
+        ;"
+    )
+}
+
+///
+/// True when the kafka table exists without the provenance columns
+///
+async fn kafka_table_is_outdated(
+    client: &Client,
+    db: &str,
+    table_name: &str,
+) -> Result<bool, Error> {
+    let columns = client
+        .query("SELECT name FROM system.columns WHERE database = ? AND table = ?")
+        .bind(db)
+        .bind(format!("_{table_name}_kafka"))
+        .fetch_all::<String>()
+        .await?;
+    Ok(!columns.is_empty()
+        && [FIELD_ARTIFACT_HASH, FIELD_PARSER_VERSION]
+            .iter()
+            .any(|field| !columns.iter().any(|column| column == field)))
+}
+
+///
+/// Drop the materialized view reading the kafka table, then the kafka table
+///
+fn drop_kafka_tables_queries(db: &str, table_name: &str, cluster: &Option<String>) -> [String; 2] {
+    let cluster = cluster_def(cluster);
+    [
+        format!("DROP VIEW IF EXISTS {db}._{table_name}_consumer {cluster};"),
+        format!("DROP TABLE IF EXISTS {db}._{table_name}_kafka {cluster};"),
+    ]
+}
+
 fn build_json_type(partial_field_def: &Vec<(String, DataType)>) -> String {
     let mut json = "JSON".to_owned();
     let mut first = true;
@@ -179,16 +310,7 @@ fn build_json_type(partial_field_def: &Vec<(String, DataType)>) -> String {
             json.push_str(name);
             json.push_str(" ");
 
-            match ftype {
-                DataType::Boolean => json.push_str("Bool"),
-                DataType::String => json.push_str("String"),
-                DataType::Date => json.push_str("DateTime64(3,'UTC')"),
-                DataType::Float => json.push_str("Float64"),
-                DataType::Int32 => json.push_str("Int32"),
-                DataType::Int64 => json.push_str("Int64"),
-                DataType::Uint8 => json.push_str("UInt8"),
-                DataType::Uint16 => json.push_str("UInt16"),
-            };
+            json.push_str(&clickhouse_type(ftype));
         }
 
         json.push_str("\n\t)");
@@ -196,6 +318,41 @@ fn build_json_type(partial_field_def: &Vec<(String, DataType)>) -> String {
     json
 }
 
+///
+/// The columns of the fields of the definition, a missing field is stored with the default value of its type
+///
+fn build_typed_columns(partial_field_def: &[(String, DataType)]) -> String {
+    let mut columns = String::new();
+    for (name, ftype) in typed_columns(partial_field_def) {
+        columns.push_str(&format!(
+            "\n            `{name}` {},",
+            clickhouse_type(&ftype)
+        ));
+    }
+    columns
+}
+
+///
+/// The clickhouse type of a json path
+///
+fn clickhouse_type(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Boolean => "Bool".to_owned(),
+        DataType::String => "String".to_owned(),
+        DataType::Date => "DateTime64(3,'UTC')".to_owned(),
+        DataType::Float => "Float64".to_owned(),
+        DataType::Int32 => "Int32".to_owned(),
+        DataType::Int64 => "Int64".to_owned(),
+        DataType::Uint8 => "UInt8".to_owned(),
+        DataType::Uint16 => "UInt16".to_owned(),
+        DataType::Uint64 => "UInt64".to_owned(),
+        DataType::Uuid => "UUID".to_owned(),
+        DataType::Ipv4 => "IPv4".to_owned(),
+        DataType::Ipv6 => "IPv6".to_owned(),
+        DataType::Array(item) => format!("Array({})", clickhouse_type(item)),
+    }
+}
+
 ///
 ///  The materialized view creation query that feed the final table with kafka data
 ///
@@ -211,6 +368,8 @@ fn materialized_view_query(db: &str, table_name: &str, cluster: &Option<String>)
             {FIELD_COMPUTER},
             {FIELD_ORIGINAL},
             {FIELD_ARCHIVE},
+            {FIELD_ARTIFACT_HASH},
+            {FIELD_PARSER_VERSION},
             {FIELD_DATA}
         FROM {db}._{table_name}_kafka
         ;"
@@ -258,16 +417,18 @@ fn timeline_short_materialized_view_query(
     table_name: &str,
     date_field: &str,
     cluster: &Option<String>,
+    mode: ClickhouseMode,
 ) -> String {
     let cluster = cluster_def(cluster);
     let field_name = date_field.replace(".", "_");
+    let date_column = field_column(date_field, mode);
     format!(
         "CREATE MATERIALIZED VIEW IF NOT EXISTS {db}._{table_name}_tml_mat_{field_name} TO {db}.timeline_short {cluster} AS SELECT 
                     {FIELD_ID},
                     {FIELD_COMPUTER},
                     {FIELD_ORIGINAL},
                     {FIELD_ARCHIVE},
-                    data.{date_field} as event_date,
+                    {date_column} as event_date,
                     '{table_name}' as source
                 FROM {db}.{table_name};"
     )
@@ -276,11 +437,17 @@ fn timeline_short_materialized_view_query(
 //
 // View that is used to retrieve the data field from the timeline
 //
-fn timeline_data_view_query(db: &str, topics: &Vec<DataTopic>, cluster: &Option<String>) -> String {
+fn timeline_data_view_query(
+    db: &str,
+    topics: &Vec<DataTopic>,
+    cluster: &Option<String>,
+    mode: ClickhouseMode,
+) -> String {
     let mut query = String::new();
     for (topic_pos, data_topic) in topics.iter().enumerate() {
         let partial_field_def = &data_topic.partial_field_def;
         let table_name = &data_topic.table_name;
+        let data = timeline_data(partial_field_def, mode);
         for (_, data_type) in partial_field_def {
             if let DataType::Date = data_type {
                 if topic_pos != 0 {
@@ -294,7 +461,7 @@ fn timeline_data_view_query(db: &str, topics: &Vec<DataTopic>, cluster: &Option<
                         {FIELD_COMPUTER},
                         {FIELD_ORIGINAL},
                         '{table_name}' as source,
-                        toJSONString({FIELD_DATA}) as data
+                        {data} as data
                     FROM {db}.{table_name}"
                 );
                 query.push_str(&subquery);
@@ -308,6 +475,26 @@ fn timeline_data_view_query(db: &str, topics: &Vec<DataTopic>, cluster: &Option<
     format!("CREATE OR REPLACE VIEW {db}._timeline_data {cluster} AS \n {query}")
 }
 
+///
+/// The data of a row as a json string
+/// With typed columns, the columns are added to the data as strings, under their dotted names
+///
+fn timeline_data(partial_field_def: &[(String, DataType)], mode: ClickhouseMode) -> String {
+    let columns = match mode {
+        ClickhouseMode::json => Vec::new(),
+        ClickhouseMode::typed_columns => typed_columns(partial_field_def),
+    };
+    if columns.is_empty() {
+        return format!("toJSONString({FIELD_DATA})");
+    }
+    let map = columns
+        .iter()
+        .map(|(name, _)| format!("'{name}', toString(`{name}`)"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("JSONMergePatch(toJSONString({FIELD_DATA}), toJSONString(map({map})))")
+}
+
 fn timeline_view_query(db: &str, cluster: &Option<String>) -> String {
     let cluster = cluster_def(cluster);
     format!(
@@ -359,16 +546,31 @@ mod tests {
             ("TestFloat".to_owned(), DataType::Float),
             ("TestBool".to_owned(), DataType::Boolean),
         ];
-        let table = table_query(db, table_name, &fields, "TestDate", &cluster);
+        let table = table_query(
+            db,
+            table_name,
+            &fields,
+            "TestDate",
+            &cluster,
+            ClickhouseMode::json,
+        );
         println!("{table}");
 
+        let provenance = provenance_columns_query(db, table_name, &cluster);
+        println!("{provenance}");
+
         let timeline_short = timeline_short_query(db, &cluster);
         println!("{timeline_short}");
 
         for (field, data_type) in &fields {
             if let DataType::Date = data_type {
-                let time_line_mat =
-                    timeline_short_materialized_view_query(db, table_name, field, &cluster);
+                let time_line_mat = timeline_short_materialized_view_query(
+                    db,
+                    table_name,
+                    field,
+                    &cluster,
+                    ClickhouseMode::json,
+                );
                 println!("{time_line_mat}");
             }
         }
@@ -380,13 +582,71 @@ mod tests {
             "TestDate".to_owned(),
         );
 
-        let timeline_data = timeline_data_view_query(db, &vec![data_topic], &cluster);
+        let timeline_data =
+            timeline_data_view_query(db, &vec![data_topic], &cluster, ClickhouseMode::json);
         println!("{timeline_data}");
 
         let timeline = timeline_view_query(db, &cluster);
         println!("{timeline}");
     }
 
+    #[test]
+    fn json_types() {
+        let fields = vec![
+            ("Guid".to_owned(), DataType::Uuid),
+            ("Size".to_owned(), DataType::Uint64),
+            ("Ip".to_owned(), DataType::Ipv6),
+            (
+                "Tags".to_owned(),
+                DataType::Array(Box::new(DataType::String)),
+            ),
+        ];
+        assert_eq!(
+            "JSON(\n\tGuid UUID,\n\tSize UInt64,\n\tIp IPv6,\n\tTags Array(String)\n\t)",
+            build_json_type(&fields)
+        );
+    }
+
+    #[test]
+    fn typed_table() {
+        let fields = vec![
+            ("System.EventID".to_owned(), DataType::Uint16),
+            ("System.TimeCreated".to_owned(), DataType::Date),
+            ("id".to_owned(), DataType::String),
+        ];
+        let table = table_query(
+            "db",
+            "evtx",
+            &fields,
+            "System.TimeCreated",
+            &None,
+            ClickhouseMode::typed_columns,
+        );
+        assert!(table.contains("`System.EventID` UInt16,"));
+        assert!(table.contains("`System.TimeCreated` DateTime64(3,'UTC'),"));
+        assert!(table.contains(&format!("{FIELD_DATA} JSON,")));
+        assert!(table.contains("ORDER BY (computer,id, `System.TimeCreated`)"));
+        // the id field is kept in the data column
+        assert!(!table.contains("`id`"));
+
+        let view = timeline_short_materialized_view_query(
+            "db",
+            "evtx",
+            "System.TimeCreated",
+            &None,
+            ClickhouseMode::typed_columns,
+        );
+        assert!(view.contains("`System.TimeCreated` as event_date"));
+        assert_eq!(
+            "JSONMergePatch(toJSONString(data), toJSONString(map('System.EventID', toString(`System.EventID`), 'System.TimeCreated', toString(`System.TimeCreated`))))",
+            timeline_data(&fields, ClickhouseMode::typed_columns)
+        );
+        assert_eq!(
+            "toJSONString(data)",
+            timeline_data(&fields, ClickhouseMode::json)
+        );
+    }
+
     #[tokio::test]
     async fn test_connection() {
         let client = Client::default()
@@ -430,6 +690,7 @@ mod tests {
             false,
             "",
             0,
+            ClickhouseMode::json,
         )
         .await
         .expect_err("");
@@ -472,6 +733,7 @@ mod tests {
             false,
             "",
             0,
+            ClickhouseMode::json,
         )
         .await
         .unwrap();
@@ -488,4 +750,113 @@ mod tests {
         let expected = format!("{table_name}");
         assert!(res.iter().find(|e| e.eq(&&expected)).is_some());
     }
+
+    #[tokio::test]
+    async fn upgrade_database_test() {
+        let client = Client::default()
+            .with_user("default")
+            .with_url("http://localhost:8123")
+            .with_option("enable_json_type", "1");
+
+        let db_name = "test_clickhouse_config_upgrade";
+        let table_name = "test_table";
+        let topic_name = format!("{db_name}_{table_name}");
+        let fields = vec![("TestDate".to_owned(), DataType::Date)];
+        let data_topic = DataTopic::new(
+            topic_name.clone(),
+            table_name.to_owned(),
+            fields.clone(),
+            "TestDate".to_owned(),
+        );
+
+        //
+        //  The tables of a previous version, without the provenance columns
+        //
+        let without_provenance = |query: String| {
+            query
+                .replace(
+                    &format!("{FIELD_ARTIFACT_HASH} LowCardinality(String),"),
+                    "",
+                )
+                .replace(
+                    &format!("{FIELD_PARSER_VERSION} LowCardinality(String),"),
+                    "",
+                )
+                .replace(&format!("{FIELD_ARTIFACT_HASH} String,"), "")
+                .replace(&format!("{FIELD_PARSER_VERSION} String,"), "")
+                .replace(&format!("{FIELD_ARTIFACT_HASH},"), "")
+                .replace(&format!("{FIELD_PARSER_VERSION},"), "")
+        };
+        let old_queries = [
+            format!("DROP DATABASE IF EXISTS {db_name};"),
+            database_query(db_name, &None),
+            without_provenance(table_query(
+                db_name,
+                table_name,
+                &fields,
+                "TestDate",
+                &None,
+                ClickhouseMode::json,
+            )),
+            without_provenance(kafka_table_query(
+                db_name,
+                table_name,
+                "localhost:9092",
+                &topic_name,
+                1,
+                &None,
+            )),
+            without_provenance(materialized_view_query(db_name, table_name, &None)),
+        ];
+        for query in old_queries {
+            assert!(!query.contains(FIELD_PARSER_VERSION));
+            client.query(&query).execute().await.unwrap();
+        }
+        assert!(
+            kafka_table_is_outdated(&client, db_name, table_name)
+                .await
+                .unwrap()
+        );
+
+        create_database(
+            &client,
+            db_name,
+            &vec![data_topic],
+            &None,
+            true,
+            "localhost:9092",
+            1,
+            ClickhouseMode::json,
+        )
+        .await
+        .unwrap();
+
+        //
+        //  Every table has the provenance columns and the view reads them
+        //
+        assert!(
+            !kafka_table_is_outdated(&client, db_name, table_name)
+                .await
+                .unwrap()
+        );
+        for table in [table_name.to_owned(), format!("_{table_name}_kafka")] {
+            let columns = client
+                .query("SELECT name FROM system.columns WHERE database = ? AND table = ?")
+                .bind(db_name)
+                .bind(&table)
+                .fetch_all::<String>()
+                .await
+                .unwrap();
+            assert!(columns.iter().any(|c| c == FIELD_ARTIFACT_HASH));
+            assert!(columns.iter().any(|c| c == FIELD_PARSER_VERSION));
+        }
+        let view = client
+            .query("SELECT create_table_query FROM system.tables WHERE database = ? AND name = ?")
+            .bind(db_name)
+            .bind(format!("_{table_name}_consumer"))
+            .fetch_one::<String>()
+            .await
+            .unwrap();
+        assert!(view.contains(FIELD_PARSER_VERSION));
+    }
 }
@@ -0,0 +1,491 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    thread,
+    time::Instant,
+};
+
+use log::{error, warn};
+use reqwest::blocking::Client;
+use serde_json::{Map, Value};
+
+use crate::{
+    Error,
+    configuration::DataType,
+    output::{
+        FIELD_ARCHIVE, FIELD_ARTIFACT_HASH, FIELD_COMPUTER, FIELD_DATA, FIELD_ID,
+        FIELD_IMPORT_DATE, FIELD_ORIGINAL, FIELD_PARSER_VERSION, OutputWriter, Tuple, get_field,
+        remove_field,
+    },
+    writer::{
+        clickhouse_config::typed_columns,
+        clickhouse_writer::{BatchPolicy, INITIAL_BACKOFF, MAX_BACKOFF, Spool},
+        http::is_retryable,
+        parquet_writer::parse_date,
+    },
+};
+
+///
+/// Insert the fields of the topic definition in the typed columns created by clickhouse_config in the typed_columns mode
+/// The other fields are stored in the data column.
+///
+/// The rows are sent in the RowBinary format: the typed values are not parsed by clickhouse at insert time
+/// A value that cannot be converted to the column type is kept in the data column, the column gets the default value of the type
+///
+/// A batch is retried with an exponential backoff when the server cannot be reached.
+/// When every retry failed, the batch is written in the spool in the RowBinary format. It can be re-inserted with:
+///  clickhouse-client --database {database} --input_format_binary_read_json_as_string=1 --query "{insert query}" < spool_file
+/// The insert query is logged with the error. The next flush returns an error with the number of spooled rows
+///
+/// The inserts are asynchronous on the server side, the insert waits until the rows are written to the table
+///
+pub struct ClickhouseTypedWriter {
+    client: Client,
+    url: String,
+    login: String,
+    password: String,
+    database: String,
+    table: String,
+    query: String,
+    columns: Vec<(String, DataType)>,
+    policy: BatchPolicy,
+    spool: Spool,
+    buffer: Vec<u8>,
+    // the tuples of the batch, kept until it is inserted or spooled
+    tuples: Vec<Tuple>,
+    batch_start: Instant,
+    num_spooled: usize,
+    rejected: Vec<Tuple>,
+}
+impl ClickhouseTypedWriter {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        server: &str,
+        login: &Option<String>,
+        password: &Option<String>,
+        database: &str,
+        table: &str,
+        partial_field_def: &[(String, DataType)],
+        policy: BatchPolicy,
+        spool: Spool,
+    ) -> Result<Self, Error> {
+        let columns = typed_columns(partial_field_def);
+        let mut names = vec![
+            FIELD_ID.to_owned(),
+            FIELD_IMPORT_DATE.to_owned(),
+            FIELD_COMPUTER.to_owned(),
+            FIELD_ORIGINAL.to_owned(),
+            FIELD_ARCHIVE.to_owned(),
+            FIELD_ARTIFACT_HASH.to_owned(),
+            FIELD_PARSER_VERSION.to_owned(),
+        ];
+        for (name, _) in &columns {
+            names.push(format!("`{name}`"));
+        }
+        names.push(FIELD_DATA.to_owned());
+        let query = format!(
+            "INSERT INTO {table} ({}) FORMAT RowBinary",
+            names.join(", ")
+        );
+
+        Ok(Self {
+            client: Client::builder().build()?,
+            url: format!("http://{server}/"),
+            login: login.clone().unwrap_or("default".to_owned()),
+            password: password.clone().unwrap_or_default(),
+            database: database.to_owned(),
+            table: table.to_owned(),
+            query,
+            columns,
+            policy,
+            spool,
+            buffer: Vec::new(),
+            tuples: Vec::new(),
+            batch_start: Instant::now(),
+            num_spooled: 0,
+            rejected: Vec::new(),
+        })
+    }
+
+    ///
+    /// Send the batch, retrying with an exponential backoff when the server cannot be reached
+    /// The batch is written in the spool when the insert fails, it is reported by the next flush
+    /// Returns an error only if the spool cannot be written, the tuples of the batch are kept for take_rejected()
+    ///
+    fn send(&mut self) -> Result<(), Error> {
+        if self.tuples.is_empty() {
+            return Ok(());
+        }
+        let body = std::mem::take(&mut self.buffer);
+        let tuples = std::mem::take(&mut self.tuples);
+        let num_rows = tuples.len();
+
+        let mut retries = 0;
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let (reason, transient) = match self.insert(body.clone()) {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    let message = response.text().unwrap_or_default();
+                    (format!("status {status}: {message}"), is_retryable(status))
+                }
+                Err(e) => {
+                    let transient = e.is_connect() || e.is_timeout();
+                    (e.to_string(), transient)
+                }
+            };
+            if !transient || retries >= self.policy.max_retries {
+                error!(
+                    "{num_rows} rows could not be inserted in table '{}' after {retries} retries: {reason}, they are written in the spool: {}, insert query: {}",
+                    self.table,
+                    self.spool.path().display(),
+                    self.query
+                );
+                if let Err(e) = self.spool.write(&body) {
+                    self.rejected.extend(tuples);
+                    return Err(e);
+                }
+                self.num_spooled += num_rows;
+                return Ok(());
+            }
+            warn!(
+                "{num_rows} rows could not be inserted in table '{}': {reason}, retrying in {} ms",
+                self.table,
+                backoff.as_millis()
+            );
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            retries += 1;
+        }
+    }
+
+    fn insert(&self, body: Vec<u8>) -> Result<reqwest::blocking::Response, reqwest::Error> {
+        self.client
+            .post(&self.url)
+            .basic_auth(&self.login, Some(&self.password))
+            .query(&[
+                ("database", self.database.as_str()),
+                ("query", self.query.as_str()),
+                ("enable_json_type", "1"),
+                ("allow_experimental_json_type", "1"),
+                ("input_format_binary_read_json_as_string", "1"),
+                ("async_insert", "1"),
+                ("wait_for_async_insert", "1"),
+            ])
+            .body(body)
+            .send()
+    }
+}
+
+impl OutputWriter for ClickhouseTypedWriter {
+    fn write(&mut self, data: Tuple) -> Result<(), Error> {
+        if self.tuples.is_empty() {
+            self.batch_start = Instant::now();
+        }
+        let buffer = &mut self.buffer;
+        write_string(buffer, &data.id);
+        buffer.extend_from_slice(&data.import_date.timestamp_millis().to_le_bytes());
+        write_string(buffer, &data.computer);
+        write_string(buffer, &data.original_file);
+        write_string(buffer, &data.archive_name);
+        write_string(buffer, &data.artifact_hash);
+        write_string(buffer, data.parser_version);
+
+        let mut remaining = match &data.data {
+            Some(Value::Object(map)) => map.clone(),
+            _ => Map::new(),
+        };
+        for (name, data_type) in &self.columns {
+            if write_value(buffer, data_type, get_field(&remaining, name)) {
+                remove_field(&mut remaining, name);
+            }
+        }
+        write_string(buffer, &serde_json::to_string(&remaining)?);
+
+        self.tuples.push(data);
+        if self.tuples.len() >= self.policy.max_rows
+            || self.buffer.len() >= self.policy.max_bytes
+            || self.batch_start.elapsed() >= self.policy.max_period
+        {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.send()?;
+        match std::mem::take(&mut self.num_spooled) {
+            0 => Ok(()),
+            num_spooled => Err(Error::ClickhouseSpooled(
+                num_spooled,
+                self.table.clone(),
+                self.spool.path().display().to_string(),
+            )),
+        }
+    }
+
+    fn take_rejected(&mut self) -> Vec<Tuple> {
+        std::mem::take(&mut self.rejected)
+    }
+}
+
+///
+/// Write a value of the column, returns false if it cannot be converted to the column type
+/// The default value of the type is written in that case, a missing or null value is written as the default value
+///
+fn write_value(buffer: &mut Vec<u8>, data_type: &DataType, value: Option<&Value>) -> bool {
+    let Some(value) = value.filter(|value| !value.is_null()) else {
+        write_default(buffer, data_type);
+        return true;
+    };
+    let start = buffer.len();
+    let written = write_converted(buffer, data_type, value);
+    if !written {
+        buffer.truncate(start);
+        write_default(buffer, data_type);
+    }
+    written
+}
+
+fn write_converted(buffer: &mut Vec<u8>, data_type: &DataType, value: &Value) -> bool {
+    match data_type {
+        DataType::String => {
+            match value {
+                Value::String(value) => write_string(buffer, value),
+                value => write_string(buffer, &value.to_string()),
+            }
+            true
+        }
+        DataType::Date => write_bytes(
+            buffer,
+            value
+                .as_str()
+                .and_then(parse_date)
+                .map(|value| value.to_le_bytes()),
+        ),
+        DataType::Int32 => write_bytes(
+            buffer,
+            value
+                .as_i64()
+                .and_then(|value| i32::try_from(value).ok())
+                .map(|value| value.to_le_bytes()),
+        ),
+        DataType::Int64 => write_bytes(buffer, value.as_i64().map(|value| value.to_le_bytes())),
+        DataType::Uint8 => write_bytes(
+            buffer,
+            value
+                .as_u64()
+                .and_then(|value| u8::try_from(value).ok())
+                .map(|value| value.to_le_bytes()),
+        ),
+        DataType::Uint16 => write_bytes(
+            buffer,
+            value
+                .as_u64()
+                .and_then(|value| u16::try_from(value).ok())
+                .map(|value| value.to_le_bytes()),
+        ),
+        DataType::Uint64 => write_bytes(buffer, value.as_u64().map(|value| value.to_le_bytes())),
+        DataType::Float => write_bytes(buffer, value.as_f64().map(|value| value.to_le_bytes())),
+        DataType::Boolean => write_bytes(buffer, value.as_bool().map(|value| [value as u8])),
+        DataType::Uuid => write_bytes(buffer, value.as_str().and_then(parse_uuid)),
+        DataType::Ipv4 => write_bytes(
+            buffer,
+            value
+                .as_str()
+                .and_then(|value| value.parse::<Ipv4Addr>().ok())
+                .map(|value| u32::from(value).to_le_bytes()),
+        ),
+        DataType::Ipv6 => write_bytes(
+            buffer,
+            value
+                .as_str()
+                .and_then(|value| value.parse::<IpAddr>().ok())
+                .map(|value| match value {
+                    IpAddr::V4(value) => value.to_ipv6_mapped().octets(),
+                    IpAddr::V6(value) => value.octets(),
+                }),
+        ),
+        DataType::Array(item_type) => {
+            let Some(items) = value.as_array() else {
+                return false;
+            };
+            write_varint(buffer, items.len());
+            // null items cannot be stored in the non nullable arrays
+            items
+                .iter()
+                .all(|item| !item.is_null() && write_converted(buffer, item_type, item))
+        }
+    }
+}
+
+fn write_default(buffer: &mut Vec<u8>, data_type: &DataType) {
+    let size = match data_type {
+        DataType::String | DataType::Array(_) => 1,
+        DataType::Boolean | DataType::Uint8 => 1,
+        DataType::Uint16 => 2,
+        DataType::Int32 | DataType::Ipv4 => 4,
+        DataType::Date | DataType::Int64 | DataType::Uint64 | DataType::Float => 8,
+        DataType::Uuid | DataType::Ipv6 => 16,
+    };
+    // an empty string or array is a zero length
+    buffer.resize(buffer.len() + size, 0);
+}
+
+fn write_bytes<const N: usize>(buffer: &mut Vec<u8>, bytes: Option<[u8; N]>) -> bool {
+    match bytes {
+        Some(bytes) => {
+            buffer.extend_from_slice(&bytes);
+            true
+        }
+        None => false,
+    }
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    write_varint(buffer, value.len());
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+///
+/// LEB128 length of the strings and arrays
+///
+fn write_varint(buffer: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+///
+/// A clickhouse UUID is two little endian u64, the most significant half first
+///
+pub(crate) fn parse_uuid(value: &str) -> Option<[u8; 16]> {
+    let hex = value.replace('-', "");
+    if hex.len() != 32 {
+        return None;
+    }
+    let uuid = u128::from_str_radix(&hex, 16).ok()?;
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&((uuid >> 64) as u64).to_le_bytes());
+    bytes[8..].copy_from_slice(&(uuid as u64).to_le_bytes());
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{output::Fields, writer::clickhouse_writer::ClickhouseOptions};
+
+    use super::*;
+
+    #[test]
+    fn row_binary() {
+        let mut buffer = vec![];
+        write_varint(&mut buffer, 300);
+        assert_eq!(vec![0xAC, 0x02], buffer);
+
+        let mut buffer = vec![];
+        assert!(write_value(
+            &mut buffer,
+            &DataType::Date,
+            Some(&json!("1970-01-01 00:00:01.500"))
+        ));
+        assert_eq!(1500i64.to_le_bytes().to_vec(), buffer);
+
+        let mut buffer = vec![];
+        assert!(write_value(
+            &mut buffer,
+            &DataType::Uuid,
+            Some(&json!("61f0c404-5cb3-11e7-907b-a6006ad3dba0"))
+        ));
+        assert_eq!(
+            vec![
+                0xe7, 0x11, 0xb3, 0x5c, 0x04, 0xc4, 0xf0, 0x61, 0xa0, 0xdb, 0xd3, 0x6a, 0x00, 0xa6,
+                0x7b, 0x90
+            ],
+            buffer
+        );
+
+        let mut buffer = vec![];
+        assert!(write_value(
+            &mut buffer,
+            &DataType::Ipv6,
+            Some(&json!("127.0.0.1"))
+        ));
+        assert_eq!(
+            vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 127, 0, 0, 1],
+            buffer
+        );
+
+        let mut buffer = vec![];
+        assert!(write_value(
+            &mut buffer,
+            &DataType::Array(Box::new(DataType::Uint16)),
+            Some(&json!([1, 2]))
+        ));
+        assert_eq!(vec![2, 1, 0, 2, 0], buffer);
+
+        // unconvertible values are written as the default of the type
+        let mut buffer = vec![];
+        assert!(!write_value(
+            &mut buffer,
+            &DataType::Array(Box::new(DataType::Uint8)),
+            Some(&json!([1, 300]))
+        ));
+        assert_eq!(vec![0], buffer);
+
+        let mut buffer = vec![];
+        assert!(!write_value(
+            &mut buffer,
+            &DataType::Int32,
+            Some(&json!("text"))
+        ));
+        assert_eq!(vec![0; 4], buffer);
+
+        let mut buffer = vec![];
+        assert!(write_value(&mut buffer, &DataType::Uint64, None));
+        assert_eq!(vec![0; 8], buffer);
+    }
+
+    #[test]
+    fn spool() {
+        let options = ClickhouseOptions {
+            max_retries: Some(0),
+            spool_folder: Some("data/temp/clickhouse_typed_spool".to_owned()),
+            ..Default::default()
+        };
+        let spool = options.spool("database", "test", "archive_name", "file", "rowbinary");
+        let path = spool.path().to_owned();
+        let _ = std::fs::remove_file(&path);
+
+        // nothing listens on the port, the batch is spooled
+        let field_def = vec![("EventID".to_owned(), DataType::Uint16)];
+        let mut writer = ClickhouseTypedWriter::new(
+            "localhost:1",
+            &None,
+            &None,
+            "database",
+            "test",
+            &field_def,
+            options.batch_policy(),
+            spool,
+        )
+        .unwrap();
+        let fields = Fields::new("machine", "Security.evtx", "archive", "Security.evtx");
+        for i in 0..3 {
+            let mut tuple = Tuple::new(&fields);
+            tuple.set_data(json!({"EventID": i}), None).unwrap();
+            writer.write(tuple).unwrap();
+        }
+        // the spooled rows are reported once
+        assert!(matches!(
+            writer.flush(),
+            Err(Error::ClickhouseSpooled(3, _, _))
+        ));
+        writer.flush().unwrap();
+        assert!(!std::fs::read(&path).unwrap().is_empty());
+    }
+}
@@ -1,28 +1,172 @@
-use std::sync::{
-    Arc,
-    atomic::{AtomicBool, Ordering},
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
 };
 
 use crate::{
     Error,
-    output::{OutputWriter, Tuple},
+    configuration::DataType,
+    output::{
+        FIELD_ARCHIVE, FIELD_ARTIFACT_HASH, FIELD_COMPUTER, FIELD_DATA, FIELD_ID,
+        FIELD_IMPORT_DATE, FIELD_ORIGINAL, FIELD_PARSER_VERSION, OUTPUT_DATE_FORMAT_UTC,
+        OutputWriter, Tuple,
+    },
+    writer::clickhouse_config::ClickhouseMode,
 };
 use chrono::{DateTime, Utc};
-use clickhouse::{Client, inserter::Inserter};
+use clickhouse::Client;
 use clickhouse_derive::Row;
-use log::error;
+use log::{error, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 use tokio::{
     runtime::Runtime,
     sync::{mpsc, oneshot},
+    time::{Instant, sleep, timeout_at},
 };
 
+const DEFAULT_MAX_BATCH_ROWS: usize = 10_000;
+const DEFAULT_MAX_BATCH_BYTES: usize = 1024 * 1024 * 10;
+const DEFAULT_MAX_BATCH_PERIOD_MS: u64 = 5_000;
+const DEFAULT_MAX_RETRIES: u32 = 8;
+const DEFAULT_SPOOL_FOLDER: &str = "spool";
+pub(crate) const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+pub(crate) const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+///
+/// Options of the clickhouse output
+/// - mode: layout of the tables, must match the mode used by the clickhouse_initializer
+/// - max_batch_rows, max_batch_bytes: a batch is inserted when one of the limits is reached
+/// - max_batch_period_ms: a batch is inserted at the latest this number of milliseconds after its first row
+/// - max_retries: number of retries when the server cannot be reached
+/// - spool_folder: the batches that could not be inserted are written in this folder
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ClickhouseOptions {
+    #[serde(default)]
+    pub mode: ClickhouseMode,
+    #[serde(default)]
+    pub max_batch_rows: Option<usize>,
+    #[serde(default)]
+    pub max_batch_bytes: Option<usize>,
+    #[serde(default)]
+    pub max_batch_period_ms: Option<u64>,
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    #[serde(default)]
+    pub spool_folder: Option<String>,
+    // field definition of each topic, set by prepare() before the parsing
+    #[serde(skip)]
+    pub topics: HashMap<String, Vec<(String, DataType)>>,
+}
+impl ClickhouseOptions {
+    pub fn batch_policy(&self) -> BatchPolicy {
+        BatchPolicy {
+            // the rows are sent through a channel of this capacity, which cannot be 0
+            max_rows: self.max_batch_rows.unwrap_or(DEFAULT_MAX_BATCH_ROWS).max(1),
+            max_bytes: self.max_batch_bytes.unwrap_or(DEFAULT_MAX_BATCH_BYTES),
+            max_period: Duration::from_millis(
+                self.max_batch_period_ms
+                    .unwrap_or(DEFAULT_MAX_BATCH_PERIOD_MS),
+            ),
+            max_retries: self.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+        }
+    }
+
+    ///
+    /// The spool of a parsed file: {spool_folder}/{database}/{table}/{archive_name}/{file_name}.{extension}
+    ///
+    pub fn spool(
+        &self,
+        database: &str,
+        table: &str,
+        archive_name: &str,
+        file_name: &str,
+        extension: &str,
+    ) -> Spool {
+        let mut path: PathBuf = self
+            .spool_folder
+            .as_deref()
+            .unwrap_or(DEFAULT_SPOOL_FOLDER)
+            .into();
+        path.push(database);
+        path.push(table);
+        path.push(archive_name);
+        path.push(format!("{file_name}.{extension}"));
+        Spool::new(path)
+    }
+}
+
+///
+/// Limits of the batches and number of retries of a failed insert
+///
+#[derive(Clone, Copy, Debug)]
+pub struct BatchPolicy {
+    pub max_rows: usize,
+    pub max_bytes: usize,
+    pub max_period: Duration,
+    pub max_retries: u32,
+}
+
+///
+/// Local file receiving the batches that could not be inserted, created on the first write
+///
+pub struct Spool {
+    path: PathBuf,
+    file: Option<File>,
+}
+impl Spool {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, file: None }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                if let Some(parent) = self.path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?;
+                self.file.insert(file)
+            }
+        };
+        file.write_all(bytes)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
 pub enum Message {
     Flush(oneshot::Sender<Result<(), Error>>),
     Row(Row),
 }
 
+///
+/// Insert the tuples in batches, the data is stored in the json data column
+///
+/// A batch is retried with an exponential backoff when the server cannot be reached.
+/// When every retry failed, the batch is written in the spool in the JSONEachRow format. It can be re-inserted with:
+///  clickhouse-client --query "INSERT INTO {database}.{table} FORMAT JSONEachRow" < spool_file
+/// The next flush returns an error with the number of spooled rows
+///
+/// The inserts are asynchronous on the server side, the insert waits until the rows are written to the table
+///
 pub struct ClickhouseWriter {
     _runtime: Runtime,
     sender: mpsc::Sender<Message>,
@@ -36,10 +180,11 @@ impl ClickhouseWriter {
         password: &Option<String>,
         database: &str,
         table: &str,
+        policy: BatchPolicy,
+        spool: Spool,
     ) -> Result<Self, Error> {
         let runtime = Runtime::new()?;
-        let max_rows: u64 = 10_000;
-        let (sender, mut receiver) = mpsc::channel::<Message>(max_rows as usize);
+        let (sender, mut receiver) = mpsc::channel::<Message>(policy.max_rows);
         let default_login = "default".to_owned();
         let login = login.as_ref().unwrap_or(&default_login);
         let default_password = "".to_owned();
@@ -55,43 +200,57 @@ impl ClickhouseWriter {
             .with_option("output_format_binary_write_json_as_string", "1")
             .with_option("allow_experimental_json_type", "1")
             .with_option("async_insert", "1")
-            .with_option("wait_for_async_insert", "0");
-
-        let mut inserter: Inserter<Row> = client
-            .inserter::<Row>(&table)?
-            .with_max_rows(max_rows)
-            .with_max_bytes(1024 * 1024 * 10);
+            .with_option("wait_for_async_insert", "1");
 
         let has_error = Arc::new(AtomicBool::new(false));
 
+        let mut batch = Batch {
+            client,
+            table: table.to_owned(),
+            policy,
+            spool,
+            rows: Vec::new(),
+            bytes: 0,
+            deadline: Instant::now(),
+            num_spooled: 0,
+        };
         let report_error = has_error.clone();
         runtime.spawn(async move {
-            let mut flush = None;
-            while let Some(msg) = receiver.recv().await {
-                match msg {
-                    Message::Flush(reply) => {
-                        flush = Some(reply);
-                        break;
+            loop {
+                let msg = if batch.rows.is_empty() {
+                    receiver.recv().await
+                } else {
+                    match timeout_at(batch.deadline, receiver.recv()).await {
+                        Ok(msg) => msg,
+                        Err(_) => {
+                            // the batch is older than the maximum period
+                            if let Err(e) = batch.send().await {
+                                error!("Clickhouse error: {e}");
+                                report_error.store(true, Ordering::Relaxed);
+                                break;
+                            }
+                            continue;
+                        }
                     }
-                    Message::Row(row) => {
-                        if let Err(e) = Self::write(row, &mut inserter).await {
+                };
+                match msg {
+                    Some(Message::Row(row)) => {
+                        if let Err(e) = batch.push(row).await {
                             error!("Clickhouse error: {e}");
                             report_error.store(true, Ordering::Relaxed);
                             break;
                         }
                     }
-                }
-            }
-            let end = inserter.end().await;
-            if let Some(flush) = flush {
-                if let Err(e) = end {
-                    let _ = flush.send(Err(e.into()));
-                } else {
-                    let _ = flush.send(Ok(()));
-                }
-            } else {
-                if let Err(e) = end {
-                    error!("{e}")
+                    Some(Message::Flush(reply)) => {
+                        let result = batch.send().await;
+                        let _ = reply.send(result.and_then(|_| batch.take_spooled()));
+                    }
+                    None => {
+                        if let Err(e) = batch.send().await {
+                            error!("{e}")
+                        }
+                        break;
+                    }
                 }
             }
         });
@@ -103,12 +262,6 @@ impl ClickhouseWriter {
             table: table.to_owned(),
         })
     }
-
-    async fn write(row: Row, inserter: &mut Inserter<Row>) -> Result<(), Error> {
-        inserter.write(&row)?;
-        inserter.commit().await?;
-        Ok(())
-    }
 }
 
 impl OutputWriter for ClickhouseWriter {
@@ -139,6 +292,106 @@ impl OutputWriter for ClickhouseWriter {
     }
 }
 
+///
+/// The rows waiting to be inserted
+///
+struct Batch {
+    client: Client,
+    table: String,
+    policy: BatchPolicy,
+    spool: Spool,
+    rows: Vec<Row>,
+    bytes: usize,
+    deadline: Instant,
+    num_spooled: usize,
+}
+impl Batch {
+    async fn push(&mut self, row: Row) -> Result<(), Error> {
+        if self.rows.is_empty() {
+            self.deadline = Instant::now() + self.policy.max_period;
+        }
+        self.bytes += row.size();
+        self.rows.push(row);
+        if self.rows.len() >= self.policy.max_rows || self.bytes >= self.policy.max_bytes {
+            self.send().await?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Insert the rows, retrying with an exponential backoff when the server cannot be reached
+    /// The rows are written in the spool when the insert fails, they are reported by take_spooled()
+    /// Returns an error only if the spool cannot be written
+    ///
+    async fn send(&mut self) -> Result<(), Error> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let rows = std::mem::take(&mut self.rows);
+        self.bytes = 0;
+
+        let mut retries = 0;
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let e = match self.insert(&rows).await {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            let transient = matches!(
+                e,
+                clickhouse::error::Error::Network(_) | clickhouse::error::Error::TimedOut
+            );
+            if !transient || retries >= self.policy.max_retries {
+                error!(
+                    "{} rows could not be inserted in table '{}' after {retries} retries: {e}, they are written in the spool: {}",
+                    rows.len(),
+                    self.table,
+                    self.spool.path().display()
+                );
+                let mut lines = String::new();
+                for row in &rows {
+                    lines.push_str(&row.to_json_string()?);
+                    lines.push('\n');
+                }
+                self.spool.write(lines.as_bytes())?;
+                self.num_spooled += rows.len();
+                return Ok(());
+            }
+            warn!(
+                "{} rows could not be inserted in table '{}': {e}, retrying in {} ms",
+                rows.len(),
+                self.table,
+                backoff.as_millis()
+            );
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            retries += 1;
+        }
+    }
+
+    ///
+    /// Error reporting the rows written in the spool since the last call
+    ///
+    fn take_spooled(&mut self) -> Result<(), Error> {
+        match std::mem::take(&mut self.num_spooled) {
+            0 => Ok(()),
+            num_spooled => Err(Error::ClickhouseSpooled(
+                num_spooled,
+                self.table.clone(),
+                self.spool.path().display().to_string(),
+            )),
+        }
+    }
+
+    async fn insert(&self, rows: &[Row]) -> Result<(), clickhouse::error::Error> {
+        let mut insert = self.client.insert::<Row>(&self.table)?;
+        for row in rows {
+            insert.write(row).await?;
+        }
+        insert.end().await
+    }
+}
+
 #[derive(Debug, Row, Serialize, Deserialize)]
 pub struct Row {
     id: String,
@@ -147,6 +400,8 @@ pub struct Row {
     computer: String,
     original_file: String,
     archive_name: String,
+    artifact_hash: String,
+    parser_version: String,
     data: String,
 }
 impl Row {
@@ -162,10 +417,56 @@ impl Row {
             computer: tuple.computer,
             original_file: tuple.original_file,
             archive_name: tuple.archive_name,
+            artifact_hash: tuple.artifact_hash,
+            parser_version: tuple.parser_version.to_owned(),
             data,
         };
         Ok(row)
     }
+
+    fn size(&self) -> usize {
+        self.id.len()
+            + self.computer.len()
+            + self.original_file.len()
+            + self.archive_name.len()
+            + self.artifact_hash.len()
+            + self.parser_version.len()
+            + self.data.len()
+    }
+
+    ///
+    /// The row in the JSONEachRow format of the spool
+    ///
+    fn to_json_string(&self) -> Result<String, Error> {
+        let mut row = Map::new();
+        row.insert(FIELD_ID.to_owned(), Value::String(self.id.clone()));
+        row.insert(
+            FIELD_IMPORT_DATE.to_owned(),
+            Value::String(self.import_date.format(OUTPUT_DATE_FORMAT_UTC).to_string()),
+        );
+        row.insert(
+            FIELD_COMPUTER.to_owned(),
+            Value::String(self.computer.clone()),
+        );
+        row.insert(
+            FIELD_ORIGINAL.to_owned(),
+            Value::String(self.original_file.clone()),
+        );
+        row.insert(
+            FIELD_ARCHIVE.to_owned(),
+            Value::String(self.archive_name.clone()),
+        );
+        row.insert(
+            FIELD_ARTIFACT_HASH.to_owned(),
+            Value::String(self.artifact_hash.clone()),
+        );
+        row.insert(
+            FIELD_PARSER_VERSION.to_owned(),
+            Value::String(self.parser_version.clone()),
+        );
+        row.insert(FIELD_DATA.to_owned(), serde_json::from_str(&self.data)?);
+        Ok(serde_json::to_string(&row)?)
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -203,6 +504,8 @@ mod tests {
                     {FIELD_COMPUTER} LowCardinality(String),
                     {FIELD_ORIGINAL} LowCardinality(String),
                     {FIELD_ARCHIVE} LowCardinality(String),
+                    {FIELD_ARTIFACT_HASH} LowCardinality(String),
+                    {FIELD_PARSER_VERSION} LowCardinality(String),
                     {FIELD_DATA} json,
                 ) 
                 ENGINE = ReplacingMergeTree({FIELD_IMPORT_DATE})
@@ -223,7 +526,18 @@ mod tests {
 
         let mut tuple = Tuple::new(&fields);
 
-        let mut writer = ClickhouseWriter::new(server, &None, &None, database, table).unwrap();
+        let options = ClickhouseOptions::default();
+        let spool = options.spool(database, table, "archive_name", "file", "jsonl");
+        let mut writer = ClickhouseWriter::new(
+            server,
+            &None,
+            &None,
+            database,
+            table,
+            options.batch_policy(),
+            spool,
+        )
+        .unwrap();
         let mut data = serde_json::Map::new();
         data.insert("i".to_string(), json!(0));
         data.insert("rd".to_string(), json!("Some String"));
@@ -259,4 +573,69 @@ mod tests {
 
         assert_eq!("Some String", json_value["rd"]);
     }
+
+    #[test]
+    fn batch_policy() {
+        let options = ClickhouseOptions {
+            max_batch_rows: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(1, options.batch_policy().max_rows);
+        assert_eq!(
+            DEFAULT_MAX_BATCH_ROWS,
+            ClickhouseOptions::default().batch_policy().max_rows
+        );
+    }
+
+    #[test]
+    fn spool() {
+        init_log();
+        let options = ClickhouseOptions {
+            max_retries: Some(0),
+            spool_folder: Some("data/temp/clickhouse_spool".to_owned()),
+            ..Default::default()
+        };
+        let spool = options.spool("database", "test", "archive_name", "file", "jsonl");
+        let path = spool.path().to_owned();
+        let _ = std::fs::remove_file(&path);
+
+        // nothing listens on the port, the batch is spooled
+        let mut writer = ClickhouseWriter::new(
+            "localhost:1",
+            &None,
+            &None,
+            "database",
+            "test",
+            options.batch_policy(),
+            spool,
+        )
+        .unwrap();
+
+        let fields = Fields::new(
+            "machine_id",
+            "original_file",
+            "archive_name",
+            "archive_file",
+        );
+        for i in 0..2 {
+            let mut tuple = Tuple::new(&fields);
+            tuple.set_data(json!({"i": i}), None).unwrap();
+            writer.write(tuple).unwrap();
+        }
+        // the spooled rows are reported once
+        assert!(matches!(
+            writer.flush(),
+            Err(Error::ClickhouseSpooled(2, _, _))
+        ));
+        writer.flush().unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, lines.len());
+        assert_eq!(json!({"i": 1}), lines[1][FIELD_DATA]);
+        assert_eq!("machine_id", lines[0][FIELD_COMPUTER]);
+    }
 }
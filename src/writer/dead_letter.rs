@@ -0,0 +1,539 @@
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    Error,
+    configuration::DataType,
+    output::{Output, OutputConfig, OutputWriter, Tuple},
+};
+
+pub const DEAD_LETTERS_TABLE_NAME: &str = "dead_letters";
+
+///
+/// Field definition of the dead_letters topic
+/// The rejected tuple is stored as a json string, its metadata in the common metadata fields
+///
+pub fn dead_letters_fields() -> Vec<(String, DataType)> {
+    vec![
+        ("Writer".to_string(), DataType::String),
+        ("Topic".to_string(), DataType::String),
+        ("Error".to_string(), DataType::String),
+        ("Tuple".to_string(), DataType::String),
+    ]
+}
+
+///
+/// What to do with a row that an output fails to write
+/// - fail: the parsing of the file stops
+/// - skip: the row is dropped, the number of rejected rows is logged
+/// - dlq: the row is written to the dead_letter output with the output name and the error,
+///   the parsing of the file fails when a flush loses rows that cannot be written to the dead letters
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum OnError {
+    #[default]
+    fail,
+    skip,
+    dlq,
+}
+
+///
+/// Error handling of an output
+/// - on_error: what to do with the rejected rows
+/// - dead_letter: the output receiving the rejected rows with the dlq policy, it is not prepared and must write json (file, kafka, http...)
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ErrorPolicy {
+    #[serde(default)]
+    pub on_error: OnError,
+    #[serde(default)]
+    pub dead_letter: Option<Box<OutputConfig>>,
+}
+
+///
+/// Apply the error policy of an output to its writer
+/// An output that cannot be created rejects every row instead of stopping the parsing
+///
+pub struct GuardedWriter {
+    writer: Result<Box<dyn OutputWriter>, String>,
+    name: &'static str,
+    on_error: OnError,
+    topic: String,
+    dead_letters: Option<DeadLetters>,
+    num_rejected: usize,
+}
+impl GuardedWriter {
+    pub fn new(
+        writer: Result<Box<dyn OutputWriter>, Error>,
+        name: &'static str,
+        errors: &ErrorPolicy,
+        archive_name: &str,
+        file_name: &str,
+        context: &str,
+        topic: &str,
+    ) -> Result<Self, Error> {
+        let dead_letters = match (errors.on_error, &errors.dead_letter) {
+            (OnError::dlq, Some(config)) if config.needs_topics() => {
+                return Err(Error::Generic(format!(
+                    "the dead_letter output of the {name} output must not require the definition of the topics"
+                )));
+            }
+            (OnError::dlq, Some(config)) => Some(DeadLetters {
+                config: config.as_ref().clone(),
+                // one file per output, the dead letters of several outputs must not overwrite each other
+                file_name: format!("{file_name}.{name}.{DEAD_LETTERS_TABLE_NAME}"),
+                archive_name: archive_name.to_owned(),
+                context: context.to_owned(),
+                output: None,
+            }),
+            (OnError::dlq, None) => {
+                return Err(Error::Generic(format!(
+                    "the dlq policy of the {name} output requires a dead_letter output"
+                )));
+            }
+            _ => None,
+        };
+        let writer = writer.map_err(|e| {
+            error!(
+                "Output '{name}' cannot be created for topic '{topic}', its rows are rejected: {e}"
+            );
+            e.to_string()
+        });
+        Ok(Self {
+            writer,
+            name,
+            on_error: errors.on_error,
+            topic: topic.to_owned(),
+            dead_letters,
+            num_rejected: 0,
+        })
+    }
+
+    fn reject(&mut self, tuple: Option<Tuple>, error: &str) -> Result<(), Error> {
+        // only the first error is logged to avoid flooding the log file
+        if self.num_rejected == 0 {
+            error!(
+                "Output '{}' rejected a row of topic '{}': {error}",
+                self.name, self.topic
+            );
+        }
+        self.num_rejected += 1;
+        match (&mut self.dead_letters, tuple) {
+            (Some(dead_letters), Some(tuple)) => {
+                dead_letters.write(tuple, self.name, &self.topic, error)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn reject_all(&mut self, tuples: Vec<Tuple>, error: &str) -> Result<(), Error> {
+        for tuple in tuples {
+            self.reject(Some(tuple), error)?;
+        }
+        Ok(())
+    }
+
+    fn report(&mut self) {
+        if self.num_rejected > 0 {
+            warn!(
+                "{} rows of topic '{}' rejected by output '{}' with the {:?} policy",
+                self.num_rejected, self.topic, self.name, self.on_error
+            );
+            self.num_rejected = 0;
+        }
+    }
+
+    fn complete_dead_letters(&mut self) -> Result<(), Error> {
+        match &mut self.dead_letters {
+            Some(dead_letters) => dead_letters.complete(),
+            None => Ok(()),
+        }
+    }
+}
+impl OutputWriter for GuardedWriter {
+    fn write(&mut self, data: Tuple) -> Result<(), Error> {
+        let writer = match &mut self.writer {
+            Ok(writer) => writer,
+            Err(e) => {
+                let e = e.clone();
+                return self.reject(Some(data), &e);
+            }
+        };
+        // the writer consumes the tuple, a copy is kept for the dead letters
+        let copy = self.dead_letters.is_some().then(|| data.clone());
+        let Err(e) = writer.write(data) else {
+            return Ok(());
+        };
+        // a batching writer rejects every row of the failed batch, including this one
+        let rejected = writer.take_rejected();
+        if rejected.is_empty() {
+            return self.reject(copy, &e.to_string());
+        }
+        self.reject_all(rejected, &e.to_string())
+    }
+
+    ///
+    /// The rows of the failed batch are rejected
+    /// With the dlq policy, the error is returned when the writer could not return its rows
+    ///
+    fn flush(&mut self) -> Result<(), Error> {
+        let result = match &mut self.writer {
+            Ok(writer) => writer.flush().map_err(|e| (e, writer.take_rejected())),
+            Err(_) => Ok(()),
+        };
+        let result = match result {
+            Ok(()) => Ok(()),
+            Err((e, rejected)) => {
+                error!(
+                    "Output '{}' failed to flush the rows of topic '{}': {e}",
+                    self.name, self.topic
+                );
+                let lost = rejected.is_empty();
+                self.reject_all(rejected, &e.to_string())?;
+                match self.on_error {
+                    OnError::dlq if lost => Err(e),
+                    _ => Ok(()),
+                }
+            }
+        };
+        self.report();
+        self.complete_dead_letters()?;
+        result
+    }
+
+    ///
+    /// The dead letters are kept when the parsing fails
+    ///
+    fn abort(&mut self) -> Result<(), Error> {
+        if let Ok(writer) = &mut self.writer
+            && let Err(e) = writer.abort()
+        {
+            error!(
+                "Output '{}' failed to abort the rows of topic '{}': {e}",
+                self.name, self.topic
+            );
+        }
+        self.report();
+        self.complete_dead_letters()
+    }
+}
+
+///
+/// The output of the dead letters, created when the first row is rejected
+///
+struct DeadLetters {
+    config: OutputConfig,
+    archive_name: String,
+    file_name: String,
+    context: String,
+    output: Option<Output>,
+}
+impl DeadLetters {
+    fn write(&mut self, tuple: Tuple, writer: &str, topic: &str, error: &str) -> Result<(), Error> {
+        let output = match &mut self.output {
+            Some(output) => output,
+            None => self.output.insert(Output::new(
+                std::slice::from_ref(&self.config),
+                &self.archive_name,
+                &self.file_name,
+                &self.context,
+                DEAD_LETTERS_TABLE_NAME,
+            )?),
+        };
+        // the letter keeps the metadata of the rejected tuple
+        let mut letter = tuple.clone();
+        letter.data = None;
+        letter.set_data(
+            json!({
+                "Writer": writer,
+                "Topic": topic,
+                "Error": error,
+                "Tuple": tuple.to_json_string()?,
+            }),
+            None,
+        )?;
+        output.write(letter)
+    }
+
+    fn complete(&mut self) -> Result<(), Error> {
+        match self.output.take() {
+            Some(mut output) => output.complete(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::Value;
+
+    use crate::{
+        output::Fields,
+        writer::{
+            file_writer::{FileOptions, MemoryWriter},
+            http::{HttpOptions, HttpWriter},
+            mock_server::MockServer,
+        },
+    };
+
+    use super::*;
+
+    const TEMP_FOLDER: &str = "data/temp/dead_letter";
+
+    ///
+    /// A writer rejecting every row
+    ///
+    struct FailingWriter;
+    impl OutputWriter for FailingWriter {
+        fn write(&mut self, _: Tuple) -> Result<(), Error> {
+            Err(Error::Generic("server unavailable".to_owned()))
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    ///
+    /// A writer losing its rows when it is flushed
+    ///
+    struct UnflushableWriter;
+    impl OutputWriter for UnflushableWriter {
+        fn write(&mut self, _: Tuple) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Err(Error::Generic("connection reset".to_owned()))
+        }
+    }
+
+    fn dlq(folder: &str) -> ErrorPolicy {
+        ErrorPolicy {
+            on_error: OnError::dlq,
+            dead_letter: Some(Box::new(OutputConfig::file {
+                folder: folder.to_owned(),
+                options: FileOptions::default(),
+                errors: ErrorPolicy::default(),
+            })),
+        }
+    }
+
+    fn read_letters(path: &str) -> Vec<Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn tuple(fields: &Fields) -> Tuple {
+        let mut tuple = Tuple::new(fields);
+        tuple.set_data(json!({"EventID": 4624}), None).unwrap();
+        tuple
+    }
+
+    #[test]
+    fn dead_letters() {
+        let _ = fs::remove_dir_all(TEMP_FOLDER);
+        let fields = Fields::new("WKS-042", "Security.evtx", "archive", "Security.evtx");
+
+        let mut writer = GuardedWriter::new(
+            Ok(Box::new(FailingWriter)),
+            "clickhouse",
+            &dlq(TEMP_FOLDER),
+            "archive",
+            "Security.evtx",
+            "test",
+            "evtx",
+        )
+        .unwrap();
+        writer.write(tuple(&fields)).unwrap();
+        writer.write(tuple(&fields)).unwrap();
+        writer.flush().unwrap();
+
+        let letters = read_letters(&format!(
+            "{TEMP_FOLDER}/archive/Security.evtx.clickhouse.dead_letters.jsonl"
+        ));
+        assert_eq!(2, letters.len());
+        assert_eq!("WKS-042", letters[0]["computer"]);
+        let data = &letters[0]["data"];
+        assert_eq!("clickhouse", data["Writer"]);
+        assert_eq!("evtx", data["Topic"]);
+        assert_eq!("server unavailable", data["Error"]);
+        let rejected: Value = serde_json::from_str(data["Tuple"].as_str().unwrap()).unwrap();
+        assert_eq!(4624, rejected["data"]["EventID"]);
+    }
+
+    #[test]
+    fn rejected_batches() {
+        let folder = "data/temp/dead_letter_batches";
+        let _ = fs::remove_dir_all(folder);
+        let fields = Fields::new("WKS-042", "Security.evtx", "archive", "Security.evtx");
+
+        // the batch of 2 rows and the last row are rejected by the server
+        let server = MockServer::start(vec![400, 400], "{}");
+        let options = HttpOptions {
+            max_batch_rows: Some(2),
+            ..Default::default()
+        };
+        let writer: Box<dyn OutputWriter> =
+            Box::new(HttpWriter::new(&server.url, &options, "evtx").unwrap());
+        let mut writer = GuardedWriter::new(
+            Ok(writer),
+            "http",
+            &dlq(folder),
+            "archive",
+            "Security.evtx",
+            "test",
+            "evtx",
+        )
+        .unwrap();
+        for event_id in [4624, 4625, 4634] {
+            let mut tuple = Tuple::new(&fields);
+            tuple.set_data(json!({"EventID": event_id}), None).unwrap();
+            writer.write(tuple).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(2, server.requests().len());
+
+        let letters = read_letters(&format!(
+            "{folder}/archive/Security.evtx.http.dead_letters.jsonl"
+        ));
+        let event_ids: Vec<i64> = letters
+            .iter()
+            .map(|letter| {
+                let tuple: Value =
+                    serde_json::from_str(letter["data"]["Tuple"].as_str().unwrap()).unwrap();
+                tuple["data"]["EventID"].as_i64().unwrap()
+            })
+            .collect();
+        assert_eq!(vec![4624, 4625, 4634], event_ids);
+
+        // the rows lost by a flush fail the file with the dlq policy
+        let mut writer = GuardedWriter::new(
+            Ok(Box::new(UnflushableWriter)),
+            "s3",
+            &dlq(folder),
+            "archive",
+            "Security.evtx",
+            "test",
+            "evtx",
+        )
+        .unwrap();
+        writer.write(tuple(&fields)).unwrap();
+        assert!(writer.flush().is_err());
+
+        let errors = ErrorPolicy {
+            on_error: OnError::skip,
+            dead_letter: None,
+        };
+        let mut writer = GuardedWriter::new(
+            Ok(Box::new(UnflushableWriter)),
+            "s3",
+            &errors,
+            "archive",
+            "Security.evtx",
+            "test",
+            "evtx",
+        )
+        .unwrap();
+        writer.write(tuple(&fields)).unwrap();
+        writer.flush().unwrap();
+        let _ = fs::remove_dir_all(folder);
+    }
+
+    #[test]
+    fn skip() {
+        let fields = Fields::new("WKS-042", "Security.evtx", "archive", "Security.evtx");
+        let errors = ErrorPolicy {
+            on_error: OnError::skip,
+            dead_letter: None,
+        };
+        let mut writer = GuardedWriter::new(
+            Ok(Box::new(FailingWriter)),
+            "http",
+            &errors,
+            "archive",
+            "Security.evtx",
+            "test",
+            "evtx",
+        )
+        .unwrap();
+        writer.write(tuple(&fields)).unwrap();
+        writer.flush().unwrap();
+
+        // an output that cannot be created rejects the rows
+        let mut writer = GuardedWriter::new(
+            Err(Error::KafkaUnknownTopic("test_evtx".to_owned())),
+            "kafka",
+            &errors,
+            "archive",
+            "Security.evtx",
+            "test",
+            "evtx",
+        )
+        .unwrap();
+        writer.write(tuple(&fields)).unwrap();
+
+        // the rows accepted by a writer are unchanged
+        let memory = MemoryWriter::new(10);
+        let buffer = memory.get_buffer();
+        let mut writer = GuardedWriter::new(
+            Ok(Box::new(memory)),
+            "file",
+            &errors,
+            "archive",
+            "Security.evtx",
+            "test",
+            "evtx",
+        )
+        .unwrap();
+        writer.write(tuple(&fields)).unwrap();
+        assert_eq!(1, buffer.borrow().len());
+
+        // dlq requires a dead letter output
+        let errors = ErrorPolicy {
+            on_error: OnError::dlq,
+            dead_letter: None,
+        };
+        assert!(
+            GuardedWriter::new(
+                Ok(Box::new(FailingWriter)),
+                "http",
+                &errors,
+                "archive",
+                "Security.evtx",
+                "test",
+                "evtx",
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn config() {
+        let yaml = r#"
+type: clickhouse
+server: localhost:8123
+on_error: dlq
+dead_letter:
+  type: file
+  folder: dead_letters
+"#;
+        let config = serde_yml::from_str::<OutputConfig>(yaml).unwrap();
+        let errors = config.error_policy();
+        assert_eq!(OnError::dlq, errors.on_error);
+        match errors.dead_letter.as_deref() {
+            Some(OutputConfig::file { folder, errors, .. }) => {
+                assert_eq!("dead_letters", folder);
+                assert_eq!(OnError::fail, errors.on_error);
+            }
+            _ => panic!("file dead_letter expected"),
+        }
+    }
+}
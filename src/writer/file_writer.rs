@@ -1,49 +1,299 @@
+#[cfg(test)]
+use std::{cell::RefCell, rc::Rc};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error,
+    output::{OutputWriter, Tuple},
+};
+
+const FILE_BUFFER_OUTPUT_CAPACITY: usize = 1024 * 1024;
+
+///
+/// Suffix of the files being written, they are renamed when completed
+///
+const TEMP_SUFFIX: &str = ".tmp";
+
+///
+/// Compression of the jsonl files
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum FileCompression {
+    #[default]
+    none,
+    gzip,
+    zstd,
+}
+impl FileCompression {
+    fn extension(&self) -> &'static str {
+        match self {
+            FileCompression::none => "",
+            FileCompression::gzip => ".gz",
+            FileCompression::zstd => ".zst",
+        }
+    }
+}
+
+///
+/// What to do when the output file already exists
+/// - overwrite: the file is replaced, the parts left by a previous run are removed
+/// - append: the rows are added to the file, or to new parts when the files are rolled
+/// - fail: the file is not written and an error is returned
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum ExistingFile {
+    #[default]
+    overwrite,
+    append,
+    fail,
+}
+
+///
+/// Options of the file output
+/// A new file is started when max_file_size (uncompressed bytes) or max_file_rows is reached,
+/// the files are then numbered: file.0001.jsonl.zst
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FileOptions {
+    #[serde(default)]
+    pub compression: FileCompression,
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+    #[serde(default)]
+    pub max_file_rows: Option<usize>,
+    #[serde(default)]
+    pub if_exists: ExistingFile,
+}
+impl FileOptions {
+    fn is_rolling(&self) -> bool {
+        self.max_file_size.is_some() || self.max_file_rows.is_some()
+    }
+}
+
+///
+/// Compression stream of a file
+///
+enum Encoder {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+impl Encoder {
+    fn new(file: File, compression: FileCompression) -> Result<Self, Error> {
+        let writer = BufWriter::with_capacity(FILE_BUFFER_OUTPUT_CAPACITY, file);
+        Ok(match compression {
+            FileCompression::none => Encoder::Plain(writer),
+            FileCompression::gzip => {
+                Encoder::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            FileCompression::zstd => Encoder::Zstd(zstd::Encoder::new(writer, 0)?),
+        })
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            Encoder::Plain(writer) => writer.write_all(buf),
+            Encoder::Gzip(writer) => writer.write_all(buf),
+            Encoder::Zstd(writer) => writer.write_all(buf),
+        }
+    }
+
+    ///
+    /// Write the end of the compression stream
+    ///
+    fn finish(self) -> io::Result<()> {
+        let mut writer = match self {
+            Encoder::Plain(writer) => writer,
+            Encoder::Gzip(writer) => writer.finish()?,
+            Encoder::Zstd(writer) => writer.finish()?,
+        };
+        writer.flush()
+    }
+}
+
+///
+/// A file being written under a temporary name
 ///
-/// Write data to a file
+struct PartFile {
+    encoder: Encoder,
+    temp_path: PathBuf,
+    path: PathBuf,
+    size: u64,
+    rows: usize,
+}
+
+///
+/// Write data to jsonl files
+/// The files are written under a temporary name and renamed when the writer is flushed,
+/// a file is never seen partially written
 ///
 pub struct FileWriter {
-    writer: BufWriter<File>,
+    folder: PathBuf,
+    file_name: String,
+    options: FileOptions,
+    part: Option<PartFile>,
+    part_number: usize,
 }
 impl FileWriter {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        const FILE_BUFFER_OUTPUT_CAPACITY: usize = 1024 * 1024;
-
-        let outputfile = File::options()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)?;
-        let writer = BufWriter::with_capacity(FILE_BUFFER_OUTPUT_CAPACITY, outputfile);
-        Ok(Self { writer })
+    pub fn new<P: AsRef<Path>>(
+        folder: P,
+        file_name: &str,
+        options: &FileOptions,
+    ) -> Result<Self, Error> {
+        let mut writer = Self {
+            folder: folder.as_ref().to_path_buf(),
+            file_name: file_name.to_owned(),
+            options: options.clone(),
+            part: None,
+            part_number: 0,
+        };
+        // new parts are added after the parts of the previous runs
+        if options.is_rolling() && options.if_exists == ExistingFile::append {
+            while writer.part_path(writer.part_number + 1).exists() {
+                writer.part_number += 1;
+            }
+        }
+        writer.open_part()?;
+        Ok(writer)
+    }
+
+    fn part_path(&self, part_number: usize) -> PathBuf {
+        let extension = self.options.compression.extension();
+        let name = if self.options.is_rolling() {
+            format!("{}.{part_number:04}.jsonl{extension}", self.file_name)
+        } else {
+            format!("{}.jsonl{extension}", self.file_name)
+        };
+        self.folder.join(name)
+    }
+
+    fn open_part(&mut self) -> Result<(), Error> {
+        self.part_number += 1;
+        let path = self.part_path(self.part_number);
+        if self.options.if_exists == ExistingFile::fail && path.exists() {
+            return Err(Error::OutputFileExists(path.to_string_lossy().to_string()));
+        }
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(TEMP_SUFFIX);
+        let temp_path = PathBuf::from(temp_path);
+
+        let file = File::create(&temp_path)?;
+        self.part = Some(PartFile {
+            encoder: Encoder::new(file, self.options.compression)?,
+            temp_path,
+            path,
+            size: 0,
+            rows: 0,
+        });
+        Ok(())
+    }
+
+    ///
+    /// Complete the compression stream and move the file to its final name
+    ///
+    fn close_part(&self, part: PartFile) -> Result<(), Error> {
+        part.encoder.finish()?;
+        let exists = part.path.exists();
+        match self.options.if_exists {
+            ExistingFile::fail if exists => {
+                let _ = fs::remove_file(&part.temp_path);
+                return Err(Error::OutputFileExists(
+                    part.path.to_string_lossy().to_string(),
+                ));
+            }
+            // gzip and zstd streams can be concatenated
+            ExistingFile::append if exists => {
+                let mut file = File::options().append(true).open(&part.path)?;
+                io::copy(&mut File::open(&part.temp_path)?, &mut file)?;
+                file.flush()?;
+                fs::remove_file(&part.temp_path)?;
+            }
+            _ => fs::rename(&part.temp_path, &part.path)?,
+        }
+        Ok(())
+    }
+
+    fn is_part_full(&self, part: &PartFile) -> bool {
+        part.rows > 0
+            && (self
+                .options
+                .max_file_size
+                .is_some_and(|max_size| part.size >= max_size)
+                || self
+                    .options
+                    .max_file_rows
+                    .is_some_and(|max_rows| part.rows >= max_rows))
     }
 }
 impl OutputWriter for FileWriter {
     fn write(&mut self, data: Tuple) -> Result<(), Error> {
+        let mut part = self
+            .part
+            .take()
+            .ok_or_else(|| Error::Generic("the output file is closed".to_owned()))?;
+        if self.is_part_full(&part) {
+            self.close_part(part)?;
+            self.open_part()?;
+            part = self.part.take().ok_or(Error::OutputFlush())?;
+        }
+
         let line = data.to_json_string()?;
-        self.writer.write_all(line.as_bytes())?;
-        self.writer.write_all("\n".as_bytes())?;
+        let result = part
+            .encoder
+            .write_all(line.as_bytes())
+            .and_then(|_| part.encoder.write_all("\n".as_bytes()));
+        part.size += line.len() as u64 + 1;
+        part.rows += 1;
+        self.part = Some(part);
+        result?;
         Ok(())
     }
 
     fn flush(&mut self) -> Result<(), Error> {
-        self.writer.flush()?;
+        // the file is completed once, flushing again does nothing
+        let Some(part) = self.part.take() else {
+            return Ok(());
+        };
+        self.close_part(part)?;
+
+        // remove the parts of a previous run that produced more files
+        if self.options.is_rolling() && self.options.if_exists == ExistingFile::overwrite {
+            let mut part_number = self.part_number + 1;
+            loop {
+                let path = self.part_path(part_number);
+                if !path.exists() {
+                    break;
+                }
+                fs::remove_file(path)?;
+                part_number += 1;
+            }
+        }
         Ok(())
     }
-}
 
-#[cfg(test)]
-use std::{cell::RefCell, rc::Rc};
-use std::{
-    fs::File,
-    io::{BufWriter, Write},
-    path::Path,
-};
+    ///
+    /// The file being written is removed, the parts already completed are kept
+    ///
+    fn abort(&mut self) -> Result<(), Error> {
+        let Some(part) = self.part.take() else {
+            return Ok(());
+        };
+        // the file is closed before its removal
+        let _ = part.encoder.finish();
+        fs::remove_file(&part.temp_path)?;
+        Ok(())
+    }
+}
 
-use crate::{
-    Error,
-    output::{OutputWriter, Tuple},
-};
 ///
 /// Write a few lines in a vector
 ///
@@ -77,3 +327,191 @@ impl OutputWriter for MemoryWriter {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::MultiGzDecoder;
+    use serde_json::json;
+
+    use crate::output::Fields;
+
+    use super::*;
+
+    const TEMP_FOLDER: &str = "data/temp/file_writer";
+
+    fn write_rows(folder: &str, options: &FileOptions, num_rows: usize) -> Result<(), Error> {
+        let fields = Fields::new("machine", "Security.evtx", "archive", "Security.evtx");
+        let mut writer = FileWriter::new(folder, "Security.evtx", options)?;
+        for i in 0..num_rows {
+            let mut tuple = Tuple::new(&fields);
+            tuple.set_data(json!({"EventID": i}), None)?;
+            writer.write(tuple)?;
+        }
+        writer.flush()?;
+        writer.flush()
+    }
+
+    fn list_files(folder: &str) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn count_lines(content: &str) -> usize {
+        content.lines().count()
+    }
+
+    #[test]
+    fn compression() {
+        let folder = &format!("{TEMP_FOLDER}/compression");
+        let _ = fs::remove_dir_all(folder);
+        fs::create_dir_all(folder).unwrap();
+
+        let options = FileOptions {
+            compression: FileCompression::zstd,
+            ..Default::default()
+        };
+        write_rows(folder, &options, 10).unwrap();
+        let data = fs::read(format!("{folder}/Security.evtx.jsonl.zst")).unwrap();
+        let content = String::from_utf8(zstd::decode_all(data.as_slice()).unwrap()).unwrap();
+        assert_eq!(10, count_lines(&content));
+
+        let options = FileOptions {
+            compression: FileCompression::gzip,
+            if_exists: ExistingFile::append,
+            ..Default::default()
+        };
+        write_rows(folder, &options, 10).unwrap();
+        write_rows(folder, &options, 5).unwrap();
+        let mut content = String::new();
+        // the appended gzip members are read as a single stream
+        MultiGzDecoder::new(File::open(format!("{folder}/Security.evtx.jsonl.gz")).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(15, count_lines(&content));
+
+        assert_eq!(
+            vec!["Security.evtx.jsonl.gz", "Security.evtx.jsonl.zst"],
+            list_files(folder)
+        );
+        let _ = fs::remove_dir_all(folder);
+    }
+
+    #[test]
+    fn rolling() {
+        let folder = &format!("{TEMP_FOLDER}/rolling");
+        let _ = fs::remove_dir_all(folder);
+        fs::create_dir_all(folder).unwrap();
+
+        let options = FileOptions {
+            max_file_rows: Some(4),
+            ..Default::default()
+        };
+        write_rows(folder, &options, 10).unwrap();
+        assert_eq!(
+            vec![
+                "Security.evtx.0001.jsonl",
+                "Security.evtx.0002.jsonl",
+                "Security.evtx.0003.jsonl"
+            ],
+            list_files(folder)
+        );
+        let content = fs::read_to_string(format!("{folder}/Security.evtx.0003.jsonl")).unwrap();
+        assert_eq!(2, count_lines(&content));
+
+        // the parts of the previous run are removed
+        write_rows(folder, &options, 5).unwrap();
+        assert_eq!(
+            vec!["Security.evtx.0001.jsonl", "Security.evtx.0002.jsonl"],
+            list_files(folder)
+        );
+
+        // new parts are added
+        let options = FileOptions {
+            max_file_rows: Some(4),
+            if_exists: ExistingFile::append,
+            ..Default::default()
+        };
+        write_rows(folder, &options, 1).unwrap();
+        assert_eq!(3, list_files(folder).len());
+
+        // the lines have the same size, a file holds 4 of the 10 lines
+        let _ = fs::remove_dir_all(folder);
+        fs::create_dir_all(folder).unwrap();
+        write_rows(folder, &FileOptions::default(), 10).unwrap();
+        let size = fs::metadata(format!("{folder}/Security.evtx.jsonl"))
+            .unwrap()
+            .len();
+        fs::remove_file(format!("{folder}/Security.evtx.jsonl")).unwrap();
+
+        let options = FileOptions {
+            max_file_size: Some(size / 3),
+            ..Default::default()
+        };
+        write_rows(folder, &options, 10).unwrap();
+        assert_eq!(3, list_files(folder).len());
+        let content = fs::read_to_string(format!("{folder}/Security.evtx.0001.jsonl")).unwrap();
+        assert_eq!(4, count_lines(&content));
+        let _ = fs::remove_dir_all(folder);
+    }
+
+    #[test]
+    fn abort() {
+        let folder = &format!("{TEMP_FOLDER}/abort");
+        let _ = fs::remove_dir_all(folder);
+        fs::create_dir_all(folder).unwrap();
+
+        let fields = Fields::new("machine", "Security.evtx", "archive", "Security.evtx");
+        let options = FileOptions {
+            max_file_rows: Some(2),
+            ..Default::default()
+        };
+        let mut writer = FileWriter::new(folder, "Security.evtx", &options).unwrap();
+        for i in 0..3 {
+            let mut tuple = Tuple::new(&fields);
+            tuple.set_data(json!({"EventID": i}), None).unwrap();
+            writer.write(tuple).unwrap();
+        }
+        writer.abort().unwrap();
+        // the partial file is removed, the completed part is kept
+        assert_eq!(vec!["Security.evtx.0001.jsonl"], list_files(folder));
+
+        // an aborted file does not replace the file of a previous run
+        write_rows(folder, &FileOptions::default(), 3).unwrap();
+        let mut writer = FileWriter::new(folder, "Security.evtx", &FileOptions::default()).unwrap();
+        let mut tuple = Tuple::new(&fields);
+        tuple.set_data(json!({"EventID": 42}), None).unwrap();
+        writer.write(tuple).unwrap();
+        writer.abort().unwrap();
+        writer.abort().unwrap();
+        let content = fs::read_to_string(format!("{folder}/Security.evtx.jsonl")).unwrap();
+        assert_eq!(3, count_lines(&content));
+        let _ = fs::remove_dir_all(folder);
+    }
+
+    #[test]
+    fn fail_if_exists() {
+        let folder = &format!("{TEMP_FOLDER}/fail_if_exists");
+        let _ = fs::remove_dir_all(folder);
+        fs::create_dir_all(folder).unwrap();
+
+        let options = FileOptions {
+            if_exists: ExistingFile::fail,
+            ..Default::default()
+        };
+        write_rows(folder, &options, 3).unwrap();
+        let result = write_rows(folder, &options, 3);
+        assert!(matches!(result, Err(Error::OutputFileExists(_))));
+
+        // the existing file is kept, no temporary file is left
+        let content = fs::read_to_string(format!("{folder}/Security.evtx.jsonl")).unwrap();
+        assert_eq!(3, count_lines(&content));
+        assert_eq!(vec!["Security.evtx.jsonl"], list_files(folder));
+        let _ = fs::remove_dir_all(folder);
+    }
+}
@@ -0,0 +1,400 @@
+use std::{collections::HashMap, io::Write, thread, time::Duration};
+
+use chrono::NaiveDateTime;
+use flate2::write::GzEncoder;
+use log::warn;
+use reqwest::{
+    StatusCode,
+    blocking::Client,
+    header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{
+    Error,
+    output::{OUTPUT_DATE_FORMAT_UTC, OutputWriter, Tuple, get_field},
+};
+
+const DEFAULT_MAX_BATCH_ROWS: usize = 1_000;
+const DEFAULT_MAX_BATCH_BYTES: usize = 5 * 1024 * 1024;
+const DEFAULT_MAX_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+///
+/// Format of the request body
+/// - ndjson: one tuple per line
+/// - splunk_hec: Splunk HTTP Event Collector envelopes, the url is the event endpoint: https://splunk:8088/services/collector/event
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum HttpFormat {
+    #[default]
+    ndjson,
+    splunk_hec,
+}
+impl HttpFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            HttpFormat::ndjson => "application/x-ndjson",
+            HttpFormat::splunk_hec => "application/json",
+        }
+    }
+
+    fn authorization(&self, token: &str) -> String {
+        match self {
+            HttpFormat::ndjson => format!("Bearer {token}"),
+            HttpFormat::splunk_hec => format!("Splunk {token}"),
+        }
+    }
+}
+
+///
+/// Options of the http output
+/// - token: sent as 'Authorization: Bearer <token>', or 'Authorization: Splunk <token>' for splunk_hec
+/// - headers: added to every request
+/// - gzip: compress the request body
+/// - max_batch_rows, max_batch_bytes: a request is sent when one of the limits is reached
+/// - max_retries: number of retries when the server is unavailable or overloaded
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct HttpOptions {
+    #[serde(default)]
+    pub format: HttpFormat,
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub gzip: bool,
+    #[serde(default)]
+    pub max_batch_rows: Option<usize>,
+    #[serde(default)]
+    pub max_batch_bytes: Option<usize>,
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    // sort field of each topic, set by prepare() before the parsing
+    #[serde(skip)]
+    pub sort_fields: HashMap<String, String>,
+}
+
+///
+/// POST batches of tuples to an http endpoint
+///
+pub struct HttpWriter {
+    client: Client,
+    url: String,
+    headers: HeaderMap,
+    format: HttpFormat,
+    gzip: bool,
+    topic: String,
+    // field holding the event time for splunk
+    sort_field: Option<String>,
+    max_batch_rows: usize,
+    max_batch_bytes: usize,
+    max_retries: u32,
+    batch: String,
+    // the tuples of the batch, kept until it is sent
+    tuples: Vec<Tuple>,
+    rejected: Vec<Tuple>,
+}
+impl HttpWriter {
+    pub fn new(url: &str, options: &HttpOptions, topic: &str) -> Result<Self, Error> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(options.format.content_type()),
+        );
+        if let Some(token) = &options.token {
+            headers.insert(
+                AUTHORIZATION,
+                header_value(&options.format.authorization(token))?,
+            );
+        }
+        if options.gzip {
+            headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        }
+        for (name, value) in &options.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| Error::HttpOutput(format!("invalid header name '{name}': {e}")))?;
+            headers.insert(name, header_value(value)?);
+        }
+
+        Ok(Self {
+            client: Client::builder().build()?,
+            url: url.to_owned(),
+            headers,
+            format: options.format,
+            gzip: options.gzip,
+            topic: topic.to_owned(),
+            sort_field: options.sort_fields.get(topic).cloned(),
+            max_batch_rows: options.max_batch_rows.unwrap_or(DEFAULT_MAX_BATCH_ROWS),
+            max_batch_bytes: options.max_batch_bytes.unwrap_or(DEFAULT_MAX_BATCH_BYTES),
+            max_retries: options.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+            batch: String::new(),
+            tuples: Vec::new(),
+            rejected: Vec::new(),
+        })
+    }
+
+    ///
+    /// Wrap the tuple in a HEC envelope
+    /// The event time is the sort field of the topic, or the import date when it is not a date
+    ///
+    fn splunk_event(&self, tuple: &Tuple) -> Result<String, Error> {
+        let time = self
+            .sort_field
+            .as_ref()
+            .and_then(|field| get_field(tuple.data.as_ref()?.as_object()?, field)?.as_str())
+            .and_then(|value| NaiveDateTime::parse_from_str(value, OUTPUT_DATE_FORMAT_UTC).ok())
+            .map(|date| date.and_utc())
+            .unwrap_or(tuple.import_date);
+        let host = tuple.computer.clone();
+        let source = tuple.original_file.clone();
+        let event: Value = serde_json::from_str(&tuple.clone().to_json_string()?)?;
+
+        let envelope = json!({
+            "time": time.timestamp_millis() as f64 / 1000.0,
+            "host": host,
+            "source": source,
+            "sourcetype": self.topic,
+            "event": event,
+        });
+        Ok(envelope.to_string())
+    }
+
+    fn body(&self) -> Result<Vec<u8>, Error> {
+        if !self.gzip {
+            return Ok(self.batch.as_bytes().to_vec());
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(self.batch.as_bytes())?;
+        Ok(encoder.finish()?)
+    }
+
+    ///
+    /// Send the batch, the tuples of a batch that cannot be sent are kept for take_rejected()
+    ///
+    fn send_batch(&mut self) -> Result<(), Error> {
+        if self.tuples.is_empty() {
+            return Ok(());
+        }
+        let body = self.body();
+        let tuples = std::mem::take(&mut self.tuples);
+        self.batch.clear();
+
+        let result = body.and_then(|body| self.send(body, tuples.len()));
+        if result.is_err() {
+            self.rejected.extend(tuples);
+        }
+        result
+    }
+
+    ///
+    /// Send a body, retrying with an exponential backoff when the server is unavailable or overloaded
+    ///
+    fn send(&self, body: Vec<u8>, num_rows: usize) -> Result<(), Error> {
+        let mut retries = 0;
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let result = self
+                .client
+                .post(&self.url)
+                .headers(self.headers.clone())
+                .body(body.clone())
+                .send();
+            let reason = match result {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) if is_retryable(response.status()) => {
+                    format!("status {}", response.status())
+                }
+                Ok(response) => {
+                    return Err(Error::HttpOutput(format!(
+                        "'{}' answered with status {}: {}",
+                        self.url,
+                        response.status(),
+                        response.text().unwrap_or_default()
+                    )));
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => e.to_string(),
+                Err(e) => return Err(e.into()),
+            };
+
+            if retries >= self.max_retries {
+                return Err(Error::HttpOutput(format!(
+                    "{num_rows} rows could not be sent to '{}' after {retries} retries: {reason}",
+                    self.url
+                )));
+            }
+            warn!(
+                "{num_rows} rows could not be sent to '{}': {reason}, retrying in {} ms",
+                self.url,
+                backoff.as_millis()
+            );
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            retries += 1;
+        }
+    }
+}
+impl OutputWriter for HttpWriter {
+    fn write(&mut self, data: Tuple) -> Result<(), Error> {
+        let line = match self.format {
+            // the tuple is kept to be returned if the batch is rejected
+            HttpFormat::ndjson => data.clone().to_json_string()?,
+            HttpFormat::splunk_hec => self.splunk_event(&data)?,
+        };
+        self.batch.push_str(&line);
+        self.batch.push('\n');
+        self.tuples.push(data);
+
+        if self.tuples.len() >= self.max_batch_rows || self.batch.len() >= self.max_batch_bytes {
+            self.send_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.send_batch()
+    }
+
+    fn take_rejected(&mut self) -> Vec<Tuple> {
+        std::mem::take(&mut self.rejected)
+    }
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, Error> {
+    HeaderValue::from_str(value)
+        .map_err(|e| Error::HttpOutput(format!("invalid header value: {e}")))
+}
+
+pub(crate) fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::SERVICE_UNAVAILABLE
+        || status == StatusCode::BAD_GATEWAY
+        || status == StatusCode::GATEWAY_TIMEOUT
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::MultiGzDecoder;
+
+    use crate::{output::Fields, writer::mock_server::MockServer};
+
+    use super::*;
+
+    fn tuples(num_rows: usize) -> Vec<Tuple> {
+        let fields = Fields::new(
+            "WKS-042",
+            "C:\\Windows\\System32\\winevt\\Logs\\Security.evtx",
+            "archive",
+            "Security.evtx",
+        );
+        (0..num_rows)
+            .map(|i| {
+                let mut tuple = Tuple::new(&fields);
+                let data =
+                    json!({"EventID": 4624, "TimeCreated": format!("2024-01-02 10:12:1{i}.500")});
+                tuple.set_data(data, None).unwrap();
+                tuple
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ndjson() {
+        let server = MockServer::start(vec![503], "{}");
+        let options = HttpOptions {
+            gzip: true,
+            max_batch_rows: Some(2),
+            headers: HashMap::from([("X-Case".to_owned(), "case42".to_owned())]),
+            ..Default::default()
+        };
+        let mut writer = HttpWriter::new(&server.url, &options, "evtx").unwrap();
+        for tuple in tuples(3) {
+            writer.write(tuple).unwrap();
+        }
+        writer.flush().unwrap();
+
+        // the first request is rejected and sent again
+        let requests = server.requests();
+        assert_eq!(3, requests.len());
+        assert_eq!(requests[0].body, requests[1].body);
+
+        let headers = &requests[2].headers;
+        assert_eq!("gzip", headers["content-encoding"]);
+        assert_eq!("application/x-ndjson", headers["content-type"]);
+        assert_eq!("case42", headers["x-case"]);
+        let mut content = String::new();
+        MultiGzDecoder::new(requests[2].body.as_slice())
+            .read_to_string(&mut content)
+            .unwrap();
+        let lines: Vec<Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(1, lines.len());
+        assert_eq!("2024-01-02 10:12:12.500", lines[0]["data"]["TimeCreated"]);
+    }
+
+    #[test]
+    fn splunk_hec() {
+        let server = MockServer::start(vec![], "{}");
+        let mut options = HttpOptions {
+            format: HttpFormat::splunk_hec,
+            token: Some("00000000-0000-0000-0000-000000000000".to_owned()),
+            ..Default::default()
+        };
+        options
+            .sort_fields
+            .insert("evtx".to_owned(), "TimeCreated".to_owned());
+        let mut writer = HttpWriter::new(&server.url, &options, "evtx").unwrap();
+        for tuple in tuples(2) {
+            writer.write(tuple).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let requests = server.requests();
+        assert_eq!(1, requests.len());
+        assert_eq!(
+            "Splunk 00000000-0000-0000-0000-000000000000",
+            requests[0].headers["authorization"]
+        );
+        let events: Vec<Value> = String::from_utf8_lossy(&requests[0].body)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, events.len());
+        assert_eq!(1_704_190_331.5, events[1]["time"]);
+        assert_eq!("WKS-042", events[1]["host"]);
+        assert_eq!("evtx", events[1]["sourcetype"]);
+        assert_eq!(4624, events[1]["event"]["data"]["EventID"]);
+
+        // nested sort field
+        options
+            .sort_fields
+            .insert("evtx".to_owned(), "System.TimeCreated".to_owned());
+        let server = MockServer::start(vec![], "{}");
+        let mut writer = HttpWriter::new(&server.url, &options, "evtx").unwrap();
+        let fields = Fields::new("WKS-042", "Security.evtx", "archive", "Security.evtx");
+        let mut tuple = Tuple::new(&fields);
+        let data = json!({"System": {"TimeCreated": "2024-01-02 10:12:13.250"}});
+        tuple.set_data(data, None).unwrap();
+        writer.write(tuple).unwrap();
+        writer.flush().unwrap();
+        let event: Value = serde_json::from_slice(&server.requests()[0].body).unwrap();
+        assert_eq!(1_704_190_333.25, event["time"]);
+
+        // permanent errors are not retried
+        let server = MockServer::start(vec![400], "{}");
+        let mut writer = HttpWriter::new(&server.url, &options, "evtx").unwrap();
+        for tuple in tuples(1) {
+            writer.write(tuple).unwrap();
+        }
+        assert!(matches!(writer.flush(), Err(Error::HttpOutput(_))));
+        assert_eq!(1, server.requests().len());
+    }
+}
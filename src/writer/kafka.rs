@@ -24,18 +24,34 @@ use rdkafka::{
 use crate::{
     Error,
     output::{OutputWriter, Tuple},
+    writer::kafka_schema::TopicSchema,
 };
 
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
 ///
 /// Write output to a kafka topic
+/// The messages are json objects, or are encoded with the registered schema of the topic
+///
+/// With a transactional id, the messages of the file are produced in a transaction:
+/// it is committed by flush() and aborted by abort(), read_committed consumers see every message of the file or none
 ///
 pub struct KafkaWriter {
     producer: ThreadedProducer<InputDeliveryCallback>,
     topic: String,
+    schema: Option<TopicSchema>,
     has_error: Arc<AtomicBool>,
+    transactional: bool,
+    in_transaction: bool,
 }
 impl KafkaWriter {
-    pub fn new(topic: &str, params: &HashMap<String, String>) -> Result<KafkaWriter, Error> {
+    pub fn new(
+        topic: &str,
+        params: &HashMap<String, String>,
+        schema: Option<TopicSchema>,
+        transactional_id: Option<String>,
+        transaction_timeout: Duration,
+    ) -> Result<KafkaWriter, Error> {
         let mut config = params
             .iter()
             .fold(ClientConfig::new(), |mut config, (key, value)| {
@@ -44,6 +60,14 @@ impl KafkaWriter {
             });
         //forbid the creation of a topic if it does not exists
         config.set("allow.auto.create.topics".to_string(), "false".to_string());
+        if let Some(transactional_id) = &transactional_id {
+            config.set("transactional.id", transactional_id);
+            // the transaction lasts for the parsing of the file, the default of one minute is too short
+            config.set(
+                "transaction.timeout.ms",
+                transaction_timeout.as_millis().to_string(),
+            );
+        }
 
         let input_callback = InputDeliveryCallback::new();
         let has_error = input_callback.has_error.clone();
@@ -57,23 +81,55 @@ impl KafkaWriter {
         if metadata.topics().is_empty() || metadata.topics()[0].partitions().is_empty() {
             return Err(Error::KafkaUnknownTopic(topic.to_owned()));
         }
+        if transactional_id.is_some() {
+            // fences the producers of a previous attempt with the same id
+            producer.init_transactions(TRANSACTION_TIMEOUT)?;
+        }
 
         Ok(Self {
             producer,
             topic: topic.to_owned(),
+            schema,
             has_error,
+            transactional: transactional_id.is_some(),
+            in_transaction: false,
         })
     }
+
+    fn commit(&mut self) -> Result<(), Error> {
+        self.in_transaction = false;
+        if self.has_error.load(Ordering::Relaxed) {
+            self.producer.abort_transaction(TRANSACTION_TIMEOUT)?;
+            return Err(Error::KafkaProducer());
+        }
+        if let Err(e) = self.producer.commit_transaction(TRANSACTION_TIMEOUT) {
+            if let Err(abort_error) = self.producer.abort_transaction(TRANSACTION_TIMEOUT) {
+                error!(
+                    "Cannot abort the transaction of topic '{}': {abort_error}",
+                    self.topic
+                );
+            }
+            return Err(e.into());
+        }
+        Ok(())
+    }
 }
 impl OutputWriter for KafkaWriter {
     fn write(&mut self, data: Tuple) -> Result<(), Error> {
         if self.has_error.load(Ordering::Relaxed) {
             return Err(Error::KafkaProducer());
         }
+        if self.transactional && !self.in_transaction {
+            self.producer.begin_transaction()?;
+            self.in_transaction = true;
+        }
         let key = data.key;
-        let line = data.to_json_string()?;
+        let payload = match &self.schema {
+            Some(schema) => schema.encode(data)?,
+            None => data.to_json_string()?.into_bytes(),
+        };
         loop {
-            let record = BaseRecord::to(&self.topic).payload(&line).key(&key);
+            let record = BaseRecord::to(&self.topic).payload(&payload).key(&key);
             match self.producer.send(record) {
                 Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => {
                     self.producer.poll(Duration::from_millis(10));
@@ -90,10 +146,23 @@ impl OutputWriter for KafkaWriter {
     }
 
     fn flush(&mut self) -> Result<(), Error> {
+        if self.in_transaction {
+            // the pending messages are flushed by the commit
+            return self.commit();
+        }
         const FLUSH_TIMEOUT: u64 = 30;
         self.producer.flush(Duration::from_secs(FLUSH_TIMEOUT))?;
         Ok(())
     }
+
+    fn abort(&mut self) -> Result<(), Error> {
+        if self.in_transaction {
+            self.in_transaction = false;
+            self.producer.abort_transaction(TRANSACTION_TIMEOUT)?;
+            return Ok(());
+        }
+        self.flush()
+    }
 }
 
 ///
@@ -246,12 +315,17 @@ pub async fn list_topics(
 #[cfg(test)]
 mod tests {
 
-    use rdkafka::config::FromClientConfig;
+    use rdkafka::{
+        config::FromClientConfig,
+        consumer::{BaseConsumer, Consumer},
+    };
 
     use crate::{init_log, output::Fields};
 
     use super::*;
 
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
     #[tokio::test]
     async fn send_data() {
         init_log();
@@ -277,12 +351,13 @@ mod tests {
         delete_topics(vec![topic_name.to_string()], &adminclient).await;
 
         // topic does not exists yet
-        let writer = KafkaWriter::new("testtopic", &params.clone());
+        let writer = KafkaWriter::new("testtopic", &params.clone(), None, None, TIMEOUT);
         assert!(writer.is_err());
 
         let partitions = 3;
         create_topics(vec![topic_name.to_string()], partitions, 1, &adminclient).await;
-        let mut writer = KafkaWriter::new("testtopic", &params.clone()).unwrap();
+        let mut writer =
+            KafkaWriter::new("testtopic", &params.clone(), None, None, TIMEOUT).unwrap();
 
         let fields = Fields::new(
             "mymachine",
@@ -322,4 +397,65 @@ mod tests {
         }
         assert!(!found);
     }
+
+    #[tokio::test]
+    async fn transaction() {
+        init_log();
+
+        let mut params = HashMap::new();
+        params.insert(
+            "bootstrap.servers".to_string(),
+            "localhost:9092".to_string(),
+        );
+
+        let adminclient = AdminClient::from_config(
+            ClientConfig::new().set("bootstrap.servers", "localhost:9092".to_string()),
+        )
+        .unwrap();
+        let topic_name = "testtransaction";
+        delete_topics(vec![topic_name.to_string()], &adminclient).await;
+        create_topics(vec![topic_name.to_string()], 1, 1, &adminclient).await;
+
+        let fields = Fields::new(
+            "mymachine",
+            "c:\\system32\\SRUDB.dat",
+            "mymachine_ORC.7z",
+            "SRUDB.dat",
+        );
+        let transactional_id = Some(format!("{topic_name}-mymachine_ORC.7z-SRUDB.dat"));
+
+        // a failed file is aborted
+        let mut writer =
+            KafkaWriter::new(topic_name, &params, None, transactional_id.clone(), TIMEOUT).unwrap();
+        for _ in 0..3 {
+            writer.write(Tuple::new(&fields)).unwrap();
+        }
+        writer.abort().unwrap();
+        drop(writer);
+
+        // the new attempt is committed
+        let mut writer =
+            KafkaWriter::new(topic_name, &params, None, transactional_id, TIMEOUT).unwrap();
+        for _ in 0..2 {
+            writer.write(Tuple::new(&fields)).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:9092")
+            .set("group.id", "testtransaction")
+            .set("auto.offset.reset", "earliest")
+            .set("isolation.level", "read_committed")
+            .create()
+            .unwrap();
+        consumer.subscribe(&[topic_name]).unwrap();
+        let mut received = 0;
+        while let Some(message) = consumer.poll(Duration::from_secs(5)) {
+            message.unwrap();
+            received += 1;
+        }
+        assert_eq!(2, received);
+
+        delete_topics(vec![topic_name.to_string()], &adminclient).await;
+    }
 }
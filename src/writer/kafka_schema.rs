@@ -0,0 +1,761 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use crate::{
+    Error,
+    configuration::{DataTopic, DataType},
+    output::{
+        FIELD_ARCHIVE, FIELD_ARTIFACT_HASH, FIELD_COMPUTER, FIELD_DATA, FIELD_ID,
+        FIELD_IMPORT_DATE, FIELD_ORIGINAL, FIELD_PARSER_VERSION, Tuple, full_topic_name, get_field,
+        remove_field,
+    },
+    writer::{
+        clickhouse_config::typed_columns, clickhouse_typed_writer::parse_uuid,
+        parquet_writer::parse_date,
+    },
+};
+
+///
+/// Default transaction.max.timeout.ms of the brokers
+///
+const DEFAULT_TRANSACTION_TIMEOUT_MS: u64 = 15 * 60 * 1000;
+
+///
+/// Encoding of the kafka messages
+/// - json: the tuple as a json object
+/// - avro, protobuf: the Confluent wire format, with a schema registered in the schema registry
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum KafkaFormat {
+    #[default]
+    json,
+    avro,
+    protobuf,
+}
+impl KafkaFormat {
+    fn schema_type(&self) -> &'static str {
+        match self {
+            KafkaFormat::json => "JSON",
+            KafkaFormat::avro => "AVRO",
+            KafkaFormat::protobuf => "PROTOBUF",
+        }
+    }
+}
+
+///
+/// Options of the kafka output
+/// - format: encoding of the messages
+/// - schema_registry: url of a Confluent compatible schema registry, required by avro and protobuf
+/// - registry_login, registry_password: basic authentication of the schema registry
+/// - transactional: produce every parsed file in a transaction, committed when the file is completely parsed
+/// - transaction_timeout_ms: maximum duration of a transaction, it must cover the parsing of the largest file
+///   and not exceed the transaction.max.timeout.ms of the brokers (15 minutes by default)
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct KafkaOptions {
+    #[serde(default)]
+    pub format: KafkaFormat,
+    #[serde(default)]
+    pub transactional: bool,
+    #[serde(default)]
+    pub transaction_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub schema_registry: Option<String>,
+    #[serde(default)]
+    pub registry_login: Option<String>,
+    #[serde(default)]
+    pub registry_password: Option<String>,
+    // registered schema of each topic, set by prepare() before the parsing
+    #[serde(skip)]
+    pub schemas: HashMap<String, TopicSchema>,
+}
+impl KafkaOptions {
+    pub fn transaction_timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.transaction_timeout_ms
+                .unwrap_or(DEFAULT_TRANSACTION_TIMEOUT_MS),
+        )
+    }
+
+    ///
+    /// Register the schema of every topic with the topic name strategy: the subject is '{kafka topic}-value'
+    /// Registering a schema that already exists returns its id
+    ///
+    pub fn register_schemas(
+        &mut self,
+        client_context: &str,
+        topics: &[DataTopic],
+    ) -> Result<(), Error> {
+        let url = self.schema_registry.as_deref().ok_or_else(|| {
+            Error::SchemaRegistry(format!(
+                "the {:?} format requires a schema_registry",
+                self.format
+            ))
+        })?;
+        let url = url.trim_end_matches('/');
+        let client = Client::builder().build()?;
+        for topic in topics {
+            let mut schema = TopicSchema::new(self.format, topic);
+            let subject = format!(
+                "{}-value",
+                full_topic_name(client_context, &topic.table_name)
+            );
+            let mut request = client
+                .post(format!("{url}/subjects/{subject}/versions"))
+                .header("Content-Type", "application/vnd.schemaregistry.v1+json")
+                .json(&json!({
+                    "schemaType": self.format.schema_type(),
+                    "schema": schema.definition(client_context, &topic.table_name),
+                }));
+            if let Some(login) = &self.registry_login {
+                request = request.basic_auth(login, self.registry_password.as_ref());
+            }
+            let response = request.send()?;
+            let status = response.status();
+            if !status.is_success() {
+                return Err(Error::SchemaRegistry(format!(
+                    "cannot register the schema of subject '{subject}', status {status}: {}",
+                    response.text().unwrap_or_default()
+                )));
+            }
+            let body: Value = response.json()?;
+            schema.id = body["id"]
+                .as_u64()
+                .and_then(|id| u32::try_from(id).ok())
+                .ok_or_else(|| {
+                    Error::SchemaRegistry(format!(
+                        "invalid answer for the subject '{subject}': {body}"
+                    ))
+                })?;
+            self.schemas.insert(topic.table_name.clone(), schema);
+        }
+        Ok(())
+    }
+}
+
+///
+/// A schema field: the dotted path of the field in the data and its name in the schema
+///
+#[derive(Clone, Debug)]
+struct SchemaField {
+    path: String,
+    name: String,
+    data_type: DataType,
+}
+
+///
+/// Schema generated from the topic definition
+/// The metadata fields, a nullable field for every field of the definition, the other fields in the json data field
+/// A value that cannot be converted to the field type is null and kept in the data field
+///
+#[derive(Clone, Debug)]
+pub struct TopicSchema {
+    format: KafkaFormat,
+    id: u32,
+    fields: Vec<SchemaField>,
+}
+impl TopicSchema {
+    fn new(format: KafkaFormat, topic: &DataTopic) -> Self {
+        let mut names: HashSet<String> = [
+            FIELD_ID,
+            FIELD_IMPORT_DATE,
+            FIELD_COMPUTER,
+            FIELD_ORIGINAL,
+            FIELD_ARCHIVE,
+            FIELD_ARTIFACT_HASH,
+            FIELD_PARSER_VERSION,
+            FIELD_DATA,
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+        let mut fields = Vec::new();
+        for (path, data_type) in typed_columns(&topic.partial_field_def) {
+            // two paths giving the same name: the second one is kept in the data field
+            let name = schema_name(&path);
+            if names.insert(name.clone()) {
+                fields.push(SchemaField {
+                    path,
+                    name,
+                    data_type,
+                });
+            }
+        }
+        Self {
+            format,
+            id: 0,
+            fields,
+        }
+    }
+
+    ///
+    /// The schema text sent to the schema registry
+    ///
+    fn definition(&self, client_context: &str, table_name: &str) -> String {
+        match self.format {
+            KafkaFormat::json => String::new(),
+            KafkaFormat::avro => self.avro_schema(client_context, table_name).to_string(),
+            KafkaFormat::protobuf => self.protobuf_schema(client_context, table_name),
+        }
+    }
+
+    fn avro_schema(&self, client_context: &str, table_name: &str) -> Value {
+        let mut fields = vec![
+            json!({"name": FIELD_ID, "type": "string"}),
+            json!({"name": FIELD_IMPORT_DATE, "type": avro_type(&DataType::Date)}),
+            json!({"name": FIELD_COMPUTER, "type": "string"}),
+            json!({"name": FIELD_ORIGINAL, "type": "string"}),
+            json!({"name": FIELD_ARCHIVE, "type": "string"}),
+            json!({"name": FIELD_ARTIFACT_HASH, "type": "string"}),
+            json!({"name": FIELD_PARSER_VERSION, "type": "string"}),
+        ];
+        for field in &self.fields {
+            fields.push(json!({
+                "name": field.name,
+                "type": ["null", avro_type(&field.data_type)],
+                "default": null,
+            }));
+        }
+        fields.push(json!({"name": FIELD_DATA, "type": "string"}));
+        json!({
+            "type": "record",
+            "name": schema_name(table_name),
+            "namespace": schema_name(client_context),
+            "fields": fields,
+        })
+    }
+
+    fn protobuf_schema(&self, client_context: &str, table_name: &str) -> String {
+        let mut schema = format!(
+            "syntax = \"proto3\";\n\npackage {};\n\nmessage {} {{\n",
+            schema_name(client_context),
+            schema_name(table_name)
+        );
+        let metadata = [
+            (FIELD_ID, "string"),
+            (FIELD_IMPORT_DATE, "int64"),
+            (FIELD_COMPUTER, "string"),
+            (FIELD_ORIGINAL, "string"),
+            (FIELD_ARCHIVE, "string"),
+            (FIELD_ARTIFACT_HASH, "string"),
+            (FIELD_PARSER_VERSION, "string"),
+        ];
+        let mut number = 1;
+        for (name, proto_type) in metadata {
+            schema.push_str(&format!("  {proto_type} {name} = {number};\n"));
+            number += 1;
+        }
+        for field in &self.fields {
+            schema.push_str(&format!(
+                "  {} {} = {number};\n",
+                protobuf_field(&field.data_type),
+                field.name
+            ));
+            number += 1;
+        }
+        schema.push_str(&format!("  string {FIELD_DATA} = {number};\n}}\n"));
+        schema
+    }
+
+    ///
+    /// Encode the tuple in the Confluent wire format: a zero byte, the schema id, the message
+    ///
+    pub fn encode(&self, tuple: Tuple) -> Result<Vec<u8>, Error> {
+        let mut buffer = vec![0];
+        buffer.extend_from_slice(&self.id.to_be_bytes());
+        if self.format == KafkaFormat::protobuf {
+            // index of the message in the schema: the first one
+            buffer.push(0);
+        }
+
+        let import_date = tuple.import_date.timestamp_millis();
+        let metadata = [
+            tuple.id.as_str(),
+            tuple.computer.as_str(),
+            tuple.original_file.as_str(),
+            tuple.archive_name.as_str(),
+            tuple.artifact_hash.as_str(),
+            tuple.parser_version,
+        ];
+        let mut remaining = match tuple.data {
+            Some(Value::Object(map)) => map,
+            _ => Map::new(),
+        };
+
+        match self.format {
+            KafkaFormat::json => {}
+            KafkaFormat::avro => {
+                write_avro_string(&mut buffer, metadata[0]);
+                write_avro_long(&mut buffer, import_date);
+                for value in &metadata[1..] {
+                    write_avro_string(&mut buffer, value);
+                }
+                for field in &self.fields {
+                    if write_avro_field(&mut buffer, field, get_field(&remaining, &field.path)) {
+                        remove_field(&mut remaining, &field.path);
+                    }
+                }
+                write_avro_string(&mut buffer, &serde_json::to_string(&remaining)?);
+            }
+            KafkaFormat::protobuf => {
+                write_proto_string(&mut buffer, 1, metadata[0]);
+                write_proto_varint(&mut buffer, 2, import_date as u64);
+                for (number, value) in (3..).zip(&metadata[1..]) {
+                    write_proto_string(&mut buffer, number, value);
+                }
+                let mut number = 8;
+                for field in &self.fields {
+                    if write_proto_field(
+                        &mut buffer,
+                        number,
+                        field,
+                        get_field(&remaining, &field.path),
+                    ) {
+                        remove_field(&mut remaining, &field.path);
+                    }
+                    number += 1;
+                }
+                write_proto_string(&mut buffer, number, &serde_json::to_string(&remaining)?);
+            }
+        }
+        Ok(buffer)
+    }
+}
+
+///
+/// Avro and protobuf names: letters, digits and underscores, not starting with a digit
+///
+fn schema_name(name: &str) -> String {
+    let mut schema_name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !schema_name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        schema_name.insert(0, '_');
+    }
+    schema_name
+}
+
+fn avro_type(data_type: &DataType) -> Value {
+    match data_type {
+        DataType::Boolean => json!("boolean"),
+        DataType::String | DataType::Ipv4 | DataType::Ipv6 => json!("string"),
+        DataType::Uuid => json!({"type": "string", "logicalType": "uuid"}),
+        DataType::Date => json!({"type": "long", "logicalType": "timestamp-millis"}),
+        DataType::Float => json!("double"),
+        DataType::Int32 | DataType::Uint8 | DataType::Uint16 => json!("int"),
+        DataType::Int64 | DataType::Uint64 => json!("long"),
+        DataType::Array(item) => json!({"type": "array", "items": avro_type(item)}),
+    }
+}
+
+fn protobuf_type(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::Boolean => "bool",
+        DataType::Date | DataType::Int64 => "int64",
+        DataType::Float => "double",
+        DataType::Int32 => "int32",
+        DataType::Uint8 | DataType::Uint16 => "uint32",
+        DataType::Uint64 => "uint64",
+        DataType::String
+        | DataType::Uuid
+        | DataType::Ipv4
+        | DataType::Ipv6
+        | DataType::Array(_) => "string",
+    }
+}
+
+///
+/// Arrays are repeated fields, arrays of arrays cannot be repeated: they are stored as json strings
+///
+fn protobuf_field(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Array(item) if matches!(**item, DataType::Array(_)) => "string".to_owned(),
+        DataType::Array(item) => format!("repeated {}", protobuf_type(item)),
+        data_type => format!("optional {}", protobuf_type(data_type)),
+    }
+}
+
+///
+/// A json value converted to the type of the field
+///
+enum Converted<'a> {
+    String(Cow<'a, str>),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Converted<'a>>),
+}
+
+fn convert<'a>(data_type: &DataType, value: &'a Value) -> Option<Converted<'a>> {
+    let valid_str = |valid: fn(&str) -> bool| {
+        value
+            .as_str()
+            .filter(|value| valid(value))
+            .map(|value| Converted::String(Cow::Borrowed(value)))
+    };
+    match data_type {
+        DataType::String => Some(Converted::String(match value {
+            Value::String(value) => Cow::Borrowed(value),
+            value => Cow::Owned(value.to_string()),
+        })),
+        DataType::Date => value.as_str().and_then(parse_date).map(Converted::Int),
+        DataType::Float => value.as_f64().map(Converted::Float),
+        DataType::Int32 => value
+            .as_i64()
+            .filter(|value| i32::try_from(*value).is_ok())
+            .map(Converted::Int),
+        DataType::Int64 => value.as_i64().map(Converted::Int),
+        DataType::Uint8 => value
+            .as_u64()
+            .filter(|value| u8::try_from(*value).is_ok())
+            .map(Converted::Uint),
+        DataType::Uint16 => value
+            .as_u64()
+            .filter(|value| u16::try_from(*value).is_ok())
+            .map(Converted::Uint),
+        DataType::Uint64 => value.as_u64().map(Converted::Uint),
+        DataType::Boolean => value.as_bool().map(Converted::Bool),
+        DataType::Uuid => valid_str(|value| parse_uuid(value).is_some()),
+        DataType::Ipv4 => valid_str(|value| value.parse::<Ipv4Addr>().is_ok()),
+        DataType::Ipv6 => valid_str(|value| value.parse::<IpAddr>().is_ok()),
+        DataType::Array(item_type) => value
+            .as_array()?
+            .iter()
+            .map(|item| convert(item_type, item))
+            .collect::<Option<Vec<_>>>()
+            .map(Converted::Array),
+    }
+}
+
+///
+/// Write a nullable field, returns false if the value cannot be converted to the field type
+/// A missing or null value is written as null
+///
+fn write_avro_field(buffer: &mut Vec<u8>, field: &SchemaField, value: Option<&Value>) -> bool {
+    let Some(value) = value.filter(|value| !value.is_null()) else {
+        write_avro_long(buffer, 0);
+        return true;
+    };
+    let start = buffer.len();
+    // index of the type in the ["null", type] union
+    write_avro_long(buffer, 1);
+    let written = convert(&field.data_type, value)
+        .is_some_and(|converted| write_avro_value(buffer, &converted));
+    if !written {
+        buffer.truncate(start);
+        write_avro_long(buffer, 0);
+    }
+    written
+}
+
+fn write_avro_value(buffer: &mut Vec<u8>, value: &Converted) -> bool {
+    match value {
+        Converted::String(value) => write_avro_string(buffer, value),
+        Converted::Int(value) => write_avro_long(buffer, *value),
+        // the avro long is signed
+        Converted::Uint(value) => match i64::try_from(*value) {
+            Ok(value) => write_avro_long(buffer, value),
+            Err(_) => return false,
+        },
+        Converted::Float(value) => buffer.extend_from_slice(&value.to_le_bytes()),
+        Converted::Bool(value) => buffer.push(*value as u8),
+        Converted::Array(items) => {
+            // a single block followed by the empty block
+            if !items.is_empty() {
+                write_avro_long(buffer, items.len() as i64);
+                for item in items {
+                    if !write_avro_value(buffer, item) {
+                        return false;
+                    }
+                }
+            }
+            write_avro_long(buffer, 0);
+        }
+    }
+    true
+}
+
+///
+/// Zigzag encoded variable length integer, used for the avro int and long
+///
+fn write_avro_long(buffer: &mut Vec<u8>, value: i64) {
+    write_varint(buffer, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_avro_string(buffer: &mut Vec<u8>, value: &str) {
+    write_avro_long(buffer, value.len() as i64);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+///
+/// Write an optional or repeated field, returns false if the value cannot be converted to the field type
+/// A missing or null value is not written
+///
+fn write_proto_field(
+    buffer: &mut Vec<u8>,
+    number: u32,
+    field: &SchemaField,
+    value: Option<&Value>,
+) -> bool {
+    let Some(value) = value.filter(|value| !value.is_null()) else {
+        return true;
+    };
+    let Some(converted) = convert(&field.data_type, value) else {
+        return false;
+    };
+    match (&field.data_type, converted) {
+        (DataType::Array(item), _) if matches!(**item, DataType::Array(_)) => {
+            write_proto_string(buffer, number, &value.to_string());
+        }
+        (_, Converted::Array(items)) => {
+            let mut packed = Vec::new();
+            for item in items {
+                match item {
+                    Converted::String(item) => write_proto_string(buffer, number, &item),
+                    Converted::Int(item) => write_varint(&mut packed, item as u64),
+                    Converted::Uint(item) => write_varint(&mut packed, item),
+                    Converted::Bool(item) => write_varint(&mut packed, item as u64),
+                    Converted::Float(item) => packed.extend_from_slice(&item.to_le_bytes()),
+                    Converted::Array(_) => {}
+                }
+            }
+            if !packed.is_empty() {
+                write_proto_bytes(buffer, number, &packed);
+            }
+        }
+        (_, Converted::String(value)) => write_proto_string(buffer, number, &value),
+        (_, Converted::Int(value)) => write_proto_varint(buffer, number, value as u64),
+        (_, Converted::Uint(value)) => write_proto_varint(buffer, number, value),
+        (_, Converted::Bool(value)) => write_proto_varint(buffer, number, value as u64),
+        (_, Converted::Float(value)) => {
+            write_varint(buffer, ((number as u64) << 3) | 1);
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    true
+}
+
+fn write_proto_varint(buffer: &mut Vec<u8>, number: u32, value: u64) {
+    write_varint(buffer, (number as u64) << 3);
+    write_varint(buffer, value);
+}
+
+fn write_proto_string(buffer: &mut Vec<u8>, number: u32, value: &str) {
+    write_proto_bytes(buffer, number, value.as_bytes());
+}
+
+fn write_proto_bytes(buffer: &mut Vec<u8>, number: u32, value: &[u8]) {
+    write_varint(buffer, ((number as u64) << 3) | 2);
+    write_varint(buffer, value.len() as u64);
+    buffer.extend_from_slice(value);
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{output::Fields, writer::mock_server::MockServer};
+
+    use super::*;
+
+    fn topic() -> DataTopic {
+        DataTopic::new(
+            "test_evtx".to_owned(),
+            "evtx".to_owned(),
+            vec![
+                ("System.EventID".to_owned(), DataType::Uint16),
+                ("System.Channel".to_owned(), DataType::String),
+                ("System.TimeCreated".to_owned(), DataType::Date),
+                (
+                    "Keywords".to_owned(),
+                    DataType::Array(Box::new(DataType::Int64)),
+                ),
+                ("id".to_owned(), DataType::String),
+            ],
+            "System.TimeCreated".to_owned(),
+        )
+    }
+
+    fn tuple() -> Tuple {
+        let fields = Fields::new("WKS-042", "Security.evtx", "archive", "Security.evtx");
+        let mut tuple = Tuple::new(&fields);
+        let data = json!({
+            "System": {"EventID": 4624, "Channel": "Security", "TimeCreated": "not a date"},
+            "Keywords": [1, -2],
+            "id": "kept",
+        });
+        tuple.set_data(data, None).unwrap();
+        tuple
+    }
+
+    struct Reader<'a>(&'a [u8]);
+    impl Reader<'_> {
+        fn varint(&mut self) -> u64 {
+            let mut value = 0;
+            let mut shift = 0;
+            loop {
+                let byte = self.0[0];
+                self.0 = &self.0[1..];
+                value |= ((byte & 0x7f) as u64) << shift;
+                if byte < 0x80 {
+                    return value;
+                }
+                shift += 7;
+            }
+        }
+
+        fn long(&mut self) -> i64 {
+            let value = self.varint();
+            (value >> 1) as i64 ^ -((value & 1) as i64)
+        }
+
+        fn bytes(&mut self, len: usize) -> &[u8] {
+            let (bytes, rest) = self.0.split_at(len);
+            self.0 = rest;
+            bytes
+        }
+
+        fn string(&mut self) -> String {
+            let len = self.long() as usize;
+            String::from_utf8(self.bytes(len).to_vec()).unwrap()
+        }
+    }
+
+    #[test]
+    fn avro() {
+        let mut schema = TopicSchema::new(KafkaFormat::avro, &topic());
+        schema.id = 42;
+
+        let definition = schema.avro_schema("test", "evtx");
+        let fields = definition["fields"].as_array().unwrap();
+        assert_eq!(12, fields.len());
+        assert_eq!("System_EventID", fields[7]["name"]);
+        assert_eq!(json!(["null", "int"]), fields[7]["type"]);
+        assert_eq!(
+            json!(["null", {"type": "array", "items": "long"}]),
+            fields[10]["type"]
+        );
+
+        let tuple = tuple();
+        let id = tuple.id.clone();
+        let encoded = schema.encode(tuple).unwrap();
+        assert_eq!([0, 0, 0, 0, 42], encoded[..5]);
+
+        let mut reader = Reader(&encoded[5..]);
+        assert_eq!(id, reader.string());
+        reader.long();
+        assert_eq!("WKS-042", reader.string());
+        for _ in 0..4 {
+            reader.string();
+        }
+        assert_eq!((1, 4624), (reader.long(), reader.long()));
+        assert_eq!((1, "Security".to_owned()), (reader.long(), reader.string()));
+        // the invalid date is null
+        assert_eq!(0, reader.long());
+        assert_eq!(
+            (1, 2, 1, -2, 0),
+            (
+                reader.long(),
+                reader.long(),
+                reader.long(),
+                reader.long(),
+                reader.long()
+            )
+        );
+        assert_eq!(
+            json!({"System": {"TimeCreated": "not a date"}, "id": "kept"}),
+            serde_json::from_str::<Value>(&reader.string()).unwrap()
+        );
+        assert!(reader.0.is_empty());
+    }
+
+    #[test]
+    fn protobuf() {
+        let mut schema = TopicSchema::new(KafkaFormat::protobuf, &topic());
+        schema.id = 7;
+
+        let definition = schema.protobuf_schema("test", "evtx");
+        assert!(definition.contains("message evtx {"));
+        assert!(definition.contains("  int64 import_date = 2;\n"));
+        assert!(definition.contains("  optional uint32 System_EventID = 8;\n"));
+        assert!(definition.contains("  repeated int64 Keywords = 11;\n"));
+        assert!(definition.contains("  string data = 12;\n"));
+
+        let encoded = schema.encode(tuple()).unwrap();
+        assert_eq!([0, 0, 0, 0, 7, 0], encoded[..6]);
+
+        let mut reader = Reader(&encoded[6..]);
+        let mut fields = HashMap::new();
+        while !reader.0.is_empty() {
+            let tag = reader.varint();
+            let value = match tag & 7 {
+                0 => reader.varint().to_le_bytes().to_vec(),
+                _ => {
+                    let len = reader.varint() as usize;
+                    reader.bytes(len).to_vec()
+                }
+            };
+            fields.insert(tag >> 3, value);
+        }
+        assert_eq!(b"WKS-042".to_vec(), fields[&3]);
+        assert_eq!(4624u64.to_le_bytes().to_vec(), fields[&8]);
+        assert_eq!(b"Security".to_vec(), fields[&9]);
+        // the invalid date is not written
+        assert!(!fields.contains_key(&10));
+        let mut packed = vec![1];
+        packed.extend_from_slice(&[0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+        assert_eq!(packed, fields[&11]);
+        assert_eq!(
+            json!({"System": {"TimeCreated": "not a date"}, "id": "kept"}),
+            serde_json::from_slice::<Value>(&fields[&12]).unwrap()
+        );
+    }
+
+    #[test]
+    fn register() {
+        let server = MockServer::start(vec![], "{\"id\":42}");
+        let mut options = KafkaOptions {
+            format: KafkaFormat::avro,
+            schema_registry: Some(format!("{}/", server.url)),
+            ..Default::default()
+        };
+        options.register_schemas("test", &[topic()]).unwrap();
+        assert_eq!(42, options.schemas["evtx"].id);
+
+        let requests = server.requests();
+        assert!(
+            requests[0]
+                .line
+                .starts_with("POST /subjects/test_evtx-value/versions ")
+        );
+        let body: Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!("AVRO", body["schemaType"]);
+        let schema: Value = serde_json::from_str(body["schema"].as_str().unwrap()).unwrap();
+        assert_eq!("evtx", schema["name"]);
+        assert_eq!("test", schema["namespace"]);
+
+        // avro requires a registry
+        let mut options = KafkaOptions {
+            format: KafkaFormat::avro,
+            ..Default::default()
+        };
+        assert!(options.register_schemas("test", &[topic()]).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+///
+/// A received request: request line, lowercase headers and body
+///
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub line: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+///
+/// Minimal http server used by the tests of the http clients
+/// It answers with the given statuses, then with 200, the body of every answer is the given json
+///
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+impl MockServer {
+    pub fn start(statuses: Vec<u16>, answer: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let statuses = Arc::new(Mutex::new(statuses.into_iter()));
+        let answer = answer.to_owned();
+
+        let received = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let received = received.clone();
+                let statuses = statuses.clone();
+                let answer = answer.clone();
+                thread::spawn(move || Self::serve(stream.unwrap(), received, statuses, &answer));
+            }
+        });
+        Self { url, requests }
+    }
+
+    fn serve(
+        stream: TcpStream,
+        received: Arc<Mutex<Vec<Request>>>,
+        statuses: Arc<Mutex<std::vec::IntoIter<u16>>>,
+        answer: &str,
+    ) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        loop {
+            let mut request_line = String::new();
+            // the connection is closed when the request line is empty
+            if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                return;
+            }
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let Some((name, value)) = line.trim_end().split_once(':') else {
+                    break;
+                };
+                headers.insert(name.to_lowercase(), value.trim().to_owned());
+            }
+            let length = headers
+                .get("content-length")
+                .map_or(0, |length| length.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            received.lock().unwrap().push(Request {
+                line: request_line.trim_end().to_owned(),
+                headers,
+                body,
+            });
+
+            let status = statuses.lock().unwrap().next().unwrap_or(200);
+            let response = format!(
+                "HTTP/1.1 {status} Status\r\nContent-Length: {}\r\n\r\n{answer}",
+                answer.len()
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
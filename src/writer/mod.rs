@@ -1,4 +1,13 @@
 pub mod clickhouse_config;
+pub mod clickhouse_typed_writer;
 pub mod clickhouse_writer;
+pub mod dead_letter;
 pub mod file_writer;
+pub mod http;
 pub mod kafka;
+pub mod kafka_schema;
+#[cfg(test)]
+pub mod mock_server;
+pub mod opensearch;
+pub mod parquet_writer;
+pub mod s3;
@@ -0,0 +1,430 @@
+use std::{thread, time::Duration};
+
+use log::warn;
+use reqwest::{
+    StatusCode,
+    blocking::{Client, RequestBuilder},
+    header::CONTENT_TYPE,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use crate::{
+    Error,
+    configuration::{DataTopic, DataType},
+    output::{
+        FIELD_ARCHIVE, FIELD_ARTIFACT_HASH, FIELD_COMPUTER, FIELD_DATA, FIELD_ID,
+        FIELD_IMPORT_DATE, FIELD_ORIGINAL, FIELD_PARSER_VERSION, OutputWriter, Tuple,
+    },
+};
+
+///
+/// Placeholders of the index name
+///
+const CONTEXT_PLACEHOLDER: &str = "{client_context}";
+const TOPIC_PLACEHOLDER: &str = "{topic}";
+const DEFAULT_INDEX: &str = "{client_context}_{topic}";
+
+const DEFAULT_MAX_BATCH_ROWS: usize = 5_000;
+const DEFAULT_MAX_BATCH_BYTES: usize = 10 * 1024 * 1024;
+const DEFAULT_MAX_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+///
+/// Format of the dates written by the parsers, see OUTPUT_DATE_FORMAT_UTC
+///
+const DATE_FORMAT: &str = "yyyy-MM-dd HH:mm:ss.SSS||strict_date_optional_time||epoch_millis";
+
+///
+/// Options of the OpenSearch/Elasticsearch output
+/// - index: name of the index, {client_context} and {topic} are replaced, the name is lowercased
+/// - max_batch_rows, max_batch_bytes: a bulk request is sent when one of the limits is reached
+/// - max_retries: number of retries when the cluster rejects documents (HTTP 429)
+/// - install_templates: create an index template from the topic definition before the parsing
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct OpenSearchOptions {
+    #[serde(default)]
+    pub index: Option<String>,
+    #[serde(default)]
+    pub max_batch_rows: Option<usize>,
+    #[serde(default)]
+    pub max_batch_bytes: Option<usize>,
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    #[serde(default)]
+    pub install_templates: bool,
+}
+impl OpenSearchOptions {
+    pub fn index_name(&self, client_context: &str, topic: &str) -> String {
+        self.index
+            .as_deref()
+            .unwrap_or(DEFAULT_INDEX)
+            .replace(CONTEXT_PLACEHOLDER, client_context)
+            .replace(TOPIC_PLACEHOLDER, topic)
+            .to_lowercase()
+    }
+}
+
+///
+/// Credentials and address of the cluster
+///
+struct Server {
+    client: Client,
+    url: String,
+    login: Option<String>,
+    password: Option<String>,
+}
+impl Server {
+    fn new(url: &str, login: &Option<String>, password: &Option<String>) -> Result<Self, Error> {
+        Ok(Self {
+            client: Client::builder().build()?,
+            url: url.trim_end_matches('/').to_owned(),
+            login: login.clone(),
+            password: password.clone(),
+        })
+    }
+
+    fn request(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.login {
+            Some(login) => request.basic_auth(login, self.password.as_ref()),
+            None => request,
+        }
+    }
+}
+
+///
+/// Write the documents of a topic to an index with the _bulk API
+/// The tuple id is used as the document _id, re-importing an archive replaces its documents
+///
+pub struct OpenSearchWriter {
+    server: Server,
+    index: String,
+    max_batch_rows: usize,
+    max_batch_bytes: usize,
+    max_retries: u32,
+    // action and document lines of each document, with its tuple
+    batch: Vec<(String, Tuple)>,
+    batch_bytes: usize,
+    rejected: Vec<Tuple>,
+}
+impl OpenSearchWriter {
+    pub fn new(
+        url: &str,
+        login: &Option<String>,
+        password: &Option<String>,
+        options: &OpenSearchOptions,
+        client_context: &str,
+        topic: &str,
+    ) -> Result<Self, Error> {
+        let max_batch_rows = options.max_batch_rows.unwrap_or(DEFAULT_MAX_BATCH_ROWS);
+        Ok(Self {
+            server: Server::new(url, login, password)?,
+            index: options.index_name(client_context, topic),
+            max_batch_rows,
+            max_batch_bytes: options.max_batch_bytes.unwrap_or(DEFAULT_MAX_BATCH_BYTES),
+            max_retries: options.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+            batch: Vec::with_capacity(max_batch_rows),
+            batch_bytes: 0,
+            rejected: Vec::new(),
+        })
+    }
+
+    ///
+    /// Send the batch, the tuples of the documents that cannot be indexed are kept for take_rejected()
+    ///
+    fn send_batch(&mut self) -> Result<(), Error> {
+        let mut pending = std::mem::take(&mut self.batch);
+        self.batch_bytes = 0;
+        let result = self.send(&mut pending);
+        if result.is_err() {
+            self.rejected
+                .extend(pending.into_iter().map(|(_, tuple)| tuple));
+        }
+        result
+    }
+
+    ///
+    /// Send the documents, the rejected documents are sent again with an exponential backoff
+    /// The documents that are not indexed are left in pending
+    ///
+    fn send(&self, pending: &mut Vec<(String, Tuple)>) -> Result<(), Error> {
+        let url = format!("{}/_bulk", self.server.url);
+
+        let mut retries = 0;
+        let mut backoff = INITIAL_BACKOFF;
+        while !pending.is_empty() {
+            let request = self
+                .server
+                .client
+                .post(&url)
+                .header(CONTENT_TYPE, "application/x-ndjson")
+                .body(
+                    pending
+                        .iter()
+                        .map(|(document, _)| document.as_str())
+                        .collect::<String>(),
+                );
+            let response = self.server.request(request).send()?;
+            let status = response.status();
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                // the whole request is rejected
+            } else if status.is_success() {
+                let response: BulkResponse = response.json()?;
+                response.rejected(pending)?;
+                if pending.is_empty() {
+                    break;
+                }
+            } else {
+                return Err(Error::OpenSearch(format!(
+                    "bulk request to index '{}' failed with status {status}: {}",
+                    self.index,
+                    response.text().unwrap_or_default()
+                )));
+            }
+
+            if retries >= self.max_retries {
+                return Err(Error::OpenSearch(format!(
+                    "{} documents rejected by index '{}' after {retries} retries",
+                    pending.len(),
+                    self.index
+                )));
+            }
+            warn!(
+                "{} documents rejected by index '{}', retrying in {} ms",
+                pending.len(),
+                self.index,
+                backoff.as_millis()
+            );
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            retries += 1;
+        }
+        Ok(())
+    }
+}
+impl OutputWriter for OpenSearchWriter {
+    fn write(&mut self, data: Tuple) -> Result<(), Error> {
+        let action = json!({"index": {"_index": self.index, "_id": data.id}});
+        // the tuple is kept to be returned if the batch is rejected
+        let document = format!("{action}\n{}\n", data.clone().to_json_string()?);
+        self.batch_bytes += document.len();
+        self.batch.push((document, data));
+
+        if self.batch.len() >= self.max_batch_rows || self.batch_bytes >= self.max_batch_bytes {
+            self.send_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.send_batch()
+    }
+
+    fn take_rejected(&mut self) -> Vec<Tuple> {
+        std::mem::take(&mut self.rejected)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct BulkResponse {
+    errors: bool,
+    #[serde(default)]
+    items: Vec<Map<String, Value>>,
+}
+impl BulkResponse {
+    ///
+    /// Keep the documents that are not indexed
+    /// The documents rejected because the cluster is overloaded can be sent again, fails if a document is invalid
+    ///
+    fn rejected<T>(self, documents: &mut Vec<T>) -> Result<(), Error> {
+        if !self.errors {
+            documents.clear();
+            return Ok(());
+        }
+        let mut items = self.items.into_iter();
+        let mut num_failed = 0;
+        let mut first_error = None;
+        documents.retain(|_| {
+            // the item is keyed by the action: {"index":{"status":201,...}}
+            let Some(result) = items
+                .next()
+                .and_then(|item| item.into_iter().next())
+                .map(|(_, result)| result)
+            else {
+                return false;
+            };
+            let status = result.get("status").and_then(Value::as_u64).unwrap_or(0);
+            if status == u64::from(StatusCode::TOO_MANY_REQUESTS.as_u16()) {
+                true
+            } else if !(200..300).contains(&status) {
+                num_failed += 1;
+                if first_error.is_none() {
+                    first_error = result.get("error").map(Value::to_string);
+                }
+                true
+            } else {
+                false
+            }
+        });
+        if num_failed > 0 {
+            return Err(Error::OpenSearch(format!(
+                "{num_failed} documents failed, first error: {}",
+                first_error.unwrap_or_default()
+            )));
+        }
+        Ok(())
+    }
+}
+
+///
+/// Create or replace an index template for every topic
+/// The metadata and the fields of the topic definition are mapped, the other fields are dynamically mapped
+///
+pub fn install_templates(
+    url: &str,
+    login: &Option<String>,
+    password: &Option<String>,
+    options: &OpenSearchOptions,
+    client_context: &str,
+    topics: &[DataTopic],
+) -> Result<(), Error> {
+    let server = Server::new(url, login, password)?;
+    for topic in topics {
+        let index = options.index_name(client_context, &topic.table_name);
+        let request = server
+            .client
+            .put(format!("{}/_index_template/{index}", server.url))
+            .json(&index_template(&index, &topic.partial_field_def));
+        let response = server.request(request).send()?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::OpenSearch(format!(
+                "cannot install the template of index '{index}', status {status}: {}",
+                response.text().unwrap_or_default()
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn index_template(index: &str, partial_field_def: &[(String, DataType)]) -> Value {
+    let mut data = Map::new();
+    for (name, data_type) in partial_field_def {
+        data.insert(name.to_owned(), field_mapping(data_type));
+    }
+    let keyword = json!({"type": "keyword"});
+    json!({
+        "index_patterns": [index],
+        "template": {
+            "mappings": {
+                "properties": {
+                    FIELD_ID: keyword,
+                    FIELD_IMPORT_DATE: field_mapping(&DataType::Date),
+                    FIELD_COMPUTER: keyword,
+                    FIELD_ORIGINAL: keyword,
+                    FIELD_ARCHIVE: keyword,
+                    FIELD_ARTIFACT_HASH: keyword,
+                    FIELD_PARSER_VERSION: keyword,
+                    FIELD_DATA: {"properties": data},
+                }
+            }
+        }
+    })
+}
+
+///
+/// Arrays do not exist in the mappings, any field can hold several values
+///
+fn field_mapping(data_type: &DataType) -> Value {
+    match data_type {
+        DataType::String | DataType::Uuid => json!({"type": "keyword"}),
+        DataType::Date => json!({"type": "date", "format": DATE_FORMAT}),
+        DataType::Int32 | DataType::Uint16 => json!({"type": "integer"}),
+        DataType::Int64 => json!({"type": "long"}),
+        DataType::Uint8 => json!({"type": "short"}),
+        DataType::Uint64 => json!({"type": "unsigned_long"}),
+        DataType::Float => json!({"type": "double"}),
+        DataType::Boolean => json!({"type": "boolean"}),
+        DataType::Ipv4 | DataType::Ipv6 => json!({"type": "ip"}),
+        DataType::Array(data_type) => field_mapping(data_type),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_name() {
+        let options = OpenSearchOptions::default();
+        assert_eq!("dfir_evtx", options.index_name("DFIR", "evtx"));
+
+        let options = OpenSearchOptions {
+            index: Some("parser-{topic}-{client_context}".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            "parser-srum_network-case42",
+            options.index_name("case42", "SRUM_Network")
+        );
+    }
+
+    #[test]
+    fn template() {
+        let template = index_template(
+            "case_evtx",
+            &[
+                ("EventID".to_owned(), DataType::Uint16),
+                ("TimeCreated".to_owned(), DataType::Date),
+                (
+                    "Addresses".to_owned(),
+                    DataType::Array(Box::new(DataType::Ipv4)),
+                ),
+            ],
+        );
+        assert_eq!("case_evtx", template["index_patterns"][0]);
+        let properties = &template["template"]["mappings"]["properties"];
+        assert_eq!("keyword", properties[FIELD_ID]["type"]);
+        assert_eq!("date", properties[FIELD_IMPORT_DATE]["type"]);
+        let data = &properties[FIELD_DATA]["properties"];
+        assert_eq!("integer", data["EventID"]["type"]);
+        assert_eq!("date", data["TimeCreated"]["type"]);
+        assert_eq!("ip", data["Addresses"]["type"]);
+    }
+
+    #[test]
+    fn bulk_response() {
+        let documents = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let response: BulkResponse = serde_json::from_str(
+            r#"{"took":3,"errors":true,"items":[
+                {"index":{"_id":"1","status":201}},
+                {"index":{"_id":"2","status":429,"error":{"type":"es_rejected_execution_exception"}}},
+                {"index":{"_id":"3","status":200}}]}"#,
+        )
+        .unwrap();
+        let mut pending = documents.clone();
+        response.rejected(&mut pending).unwrap();
+        assert_eq!(vec!["b"], pending);
+
+        let response: BulkResponse = serde_json::from_str(
+            r#"{"took":3,"errors":true,"items":[
+                {"index":{"_id":"1","status":400,"error":{"type":"mapper_parsing_exception"}}},
+                {"index":{"_id":"2","status":201}},
+                {"index":{"_id":"3","status":201}}]}"#,
+        )
+        .unwrap();
+        let mut pending = documents.clone();
+        let error = response.rejected(&mut pending).unwrap_err();
+        assert!(error.to_string().contains("mapper_parsing_exception"));
+        // the invalid document is kept for the dead letters
+        assert_eq!(vec!["a"], pending);
+
+        let response: BulkResponse =
+            serde_json::from_str(r#"{"took":3,"errors":false,"items":[]}"#).unwrap();
+        let mut pending = documents;
+        response.rejected(&mut pending).unwrap();
+        assert!(pending.is_empty());
+    }
+}
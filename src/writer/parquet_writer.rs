@@ -0,0 +1,686 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use arrow_array::{
+    ArrayRef, RecordBatch,
+    builder::{
+        BooleanBuilder, Float64Builder, Int32Builder, Int64Builder, StringBuilder,
+        TimestampMillisecondBuilder, UInt8Builder, UInt16Builder, UInt64Builder,
+    },
+};
+use arrow_schema::{DataType as ArrowType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::NaiveDateTime;
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, GzipLevel, ZstdLevel},
+    file::properties::WriterProperties,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    Error,
+    configuration::DataType,
+    output::{
+        FIELD_ARCHIVE, FIELD_ARTIFACT_HASH, FIELD_COMPUTER, FIELD_DATA, FIELD_ID,
+        FIELD_IMPORT_DATE, FIELD_ORIGINAL, FIELD_PARSER_VERSION, OUTPUT_DATE_FORMAT_UTC,
+        OutputWriter, Tuple, get_field, remove_field,
+    },
+};
+
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 100_000;
+
+const TIMEZONE: &str = "UTC";
+
+///
+/// Compression codecs of the parquet files
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[allow(non_camel_case_types)]
+pub enum ParquetCompression {
+    uncompressed,
+    #[default]
+    snappy,
+    gzip,
+    lz4,
+    zstd,
+}
+impl ParquetCompression {
+    fn codec(&self) -> Compression {
+        match self {
+            ParquetCompression::uncompressed => Compression::UNCOMPRESSED,
+            ParquetCompression::snappy => Compression::SNAPPY,
+            ParquetCompression::gzip => Compression::GZIP(GzipLevel::default()),
+            ParquetCompression::lz4 => Compression::LZ4_RAW,
+            ParquetCompression::zstd => Compression::ZSTD(ZstdLevel::default()),
+        }
+    }
+}
+
+///
+/// Builds a typed column
+/// Arrays are stored as json strings
+///
+enum ColumnBuilder {
+    String(StringBuilder),
+    Date(TimestampMillisecondBuilder),
+    Int32(Int32Builder),
+    Int64(Int64Builder),
+    Uint8(UInt8Builder),
+    Uint16(UInt16Builder),
+    Uint64(UInt64Builder),
+    Float(Float64Builder),
+    Boolean(BooleanBuilder),
+}
+impl ColumnBuilder {
+    fn new(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::String
+            | DataType::Uuid
+            | DataType::Ipv4
+            | DataType::Ipv6
+            | DataType::Array(_) => ColumnBuilder::String(StringBuilder::new()),
+            DataType::Date => ColumnBuilder::Date(date_builder()),
+            DataType::Int32 => ColumnBuilder::Int32(Int32Builder::new()),
+            DataType::Int64 => ColumnBuilder::Int64(Int64Builder::new()),
+            DataType::Uint8 => ColumnBuilder::Uint8(UInt8Builder::new()),
+            DataType::Uint16 => ColumnBuilder::Uint16(UInt16Builder::new()),
+            DataType::Uint64 => ColumnBuilder::Uint64(UInt64Builder::new()),
+            DataType::Float => ColumnBuilder::Float(Float64Builder::new()),
+            DataType::Boolean => ColumnBuilder::Boolean(BooleanBuilder::new()),
+        }
+    }
+
+    fn arrow_type(&self) -> ArrowType {
+        match self {
+            ColumnBuilder::String(_) => ArrowType::Utf8,
+            ColumnBuilder::Date(_) => date_type(),
+            ColumnBuilder::Int32(_) => ArrowType::Int32,
+            ColumnBuilder::Int64(_) => ArrowType::Int64,
+            ColumnBuilder::Uint8(_) => ArrowType::UInt8,
+            ColumnBuilder::Uint16(_) => ArrowType::UInt16,
+            ColumnBuilder::Uint64(_) => ArrowType::UInt64,
+            ColumnBuilder::Float(_) => ArrowType::Float64,
+            ColumnBuilder::Boolean(_) => ArrowType::Boolean,
+        }
+    }
+
+    ///
+    /// Append a value, returns false if it cannot be converted to the column type
+    /// A null is appended in that case
+    ///
+    fn append(&mut self, value: Option<&Value>) -> bool {
+        let Some(value) = value.filter(|value| !value.is_null()) else {
+            self.append_null();
+            return true;
+        };
+        let appended = match self {
+            ColumnBuilder::String(builder) => {
+                match value {
+                    Value::String(value) => builder.append_value(value),
+                    value => builder.append_value(value.to_string()),
+                }
+                true
+            }
+            ColumnBuilder::Date(builder) => {
+                append_converted(value.as_str().and_then(parse_date), |value| {
+                    builder.append_value(value)
+                })
+            }
+            ColumnBuilder::Int32(builder) => append_converted(
+                value.as_i64().and_then(|value| i32::try_from(value).ok()),
+                |value| builder.append_value(value),
+            ),
+            ColumnBuilder::Int64(builder) => {
+                append_converted(value.as_i64(), |value| builder.append_value(value))
+            }
+            ColumnBuilder::Uint8(builder) => append_converted(
+                value.as_u64().and_then(|value| u8::try_from(value).ok()),
+                |value| builder.append_value(value),
+            ),
+            ColumnBuilder::Uint16(builder) => append_converted(
+                value.as_u64().and_then(|value| u16::try_from(value).ok()),
+                |value| builder.append_value(value),
+            ),
+            ColumnBuilder::Uint64(builder) => {
+                append_converted(value.as_u64(), |value| builder.append_value(value))
+            }
+            ColumnBuilder::Float(builder) => {
+                append_converted(value.as_f64(), |value| builder.append_value(value))
+            }
+            ColumnBuilder::Boolean(builder) => {
+                append_converted(value.as_bool(), |value| builder.append_value(value))
+            }
+        };
+        if !appended {
+            self.append_null();
+        }
+        appended
+    }
+
+    fn append_null(&mut self) {
+        match self {
+            ColumnBuilder::String(builder) => builder.append_null(),
+            ColumnBuilder::Date(builder) => builder.append_null(),
+            ColumnBuilder::Int32(builder) => builder.append_null(),
+            ColumnBuilder::Int64(builder) => builder.append_null(),
+            ColumnBuilder::Uint8(builder) => builder.append_null(),
+            ColumnBuilder::Uint16(builder) => builder.append_null(),
+            ColumnBuilder::Uint64(builder) => builder.append_null(),
+            ColumnBuilder::Float(builder) => builder.append_null(),
+            ColumnBuilder::Boolean(builder) => builder.append_null(),
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            ColumnBuilder::String(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Date(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Int32(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Int64(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Uint8(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Uint16(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Uint64(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Float(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Boolean(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+///
+/// Append the converted value, returns false when the conversion failed
+///
+fn append_converted<T>(value: Option<T>, mut append: impl FnMut(T)) -> bool {
+    match value {
+        Some(value) => {
+            append(value);
+            true
+        }
+        None => false,
+    }
+}
+
+fn date_builder() -> TimestampMillisecondBuilder {
+    TimestampMillisecondBuilder::new().with_timezone(TIMEZONE)
+}
+
+fn date_type() -> ArrowType {
+    ArrowType::Timestamp(TimeUnit::Millisecond, Some(TIMEZONE.into()))
+}
+
+///
+/// The dates are converted by the parsers to the output format
+///
+pub(crate) fn parse_date(value: &str) -> Option<i64> {
+    NaiveDateTime::parse_from_str(value, OUTPUT_DATE_FORMAT_UTC)
+        .ok()
+        .map(|date| date.and_utc().timestamp_millis())
+}
+
+///
+/// Metadata columns common to every topics
+///
+struct MetadataBuilders {
+    id: StringBuilder,
+    import_date: TimestampMillisecondBuilder,
+    computer: StringBuilder,
+    original_file: StringBuilder,
+    archive_name: StringBuilder,
+    artifact_hash: StringBuilder,
+    parser_version: StringBuilder,
+}
+impl MetadataBuilders {
+    fn new() -> Self {
+        Self {
+            id: StringBuilder::new(),
+            import_date: date_builder(),
+            computer: StringBuilder::new(),
+            original_file: StringBuilder::new(),
+            archive_name: StringBuilder::new(),
+            artifact_hash: StringBuilder::new(),
+            parser_version: StringBuilder::new(),
+        }
+    }
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new(FIELD_ID, ArrowType::Utf8, false),
+            Field::new(FIELD_IMPORT_DATE, date_type(), false),
+            Field::new(FIELD_COMPUTER, ArrowType::Utf8, false),
+            Field::new(FIELD_ORIGINAL, ArrowType::Utf8, false),
+            Field::new(FIELD_ARCHIVE, ArrowType::Utf8, false),
+            Field::new(FIELD_ARTIFACT_HASH, ArrowType::Utf8, false),
+            Field::new(FIELD_PARSER_VERSION, ArrowType::Utf8, false),
+        ]
+    }
+
+    fn append(&mut self, tuple: &Tuple) {
+        self.id.append_value(&tuple.id);
+        self.import_date
+            .append_value(tuple.import_date.timestamp_millis());
+        self.computer.append_value(&tuple.computer);
+        self.original_file.append_value(&tuple.original_file);
+        self.archive_name.append_value(&tuple.archive_name);
+        self.artifact_hash.append_value(&tuple.artifact_hash);
+        self.parser_version.append_value(tuple.parser_version);
+    }
+
+    fn finish(&mut self) -> Vec<ArrayRef> {
+        vec![
+            Arc::new(self.id.finish()),
+            Arc::new(self.import_date.finish()),
+            Arc::new(self.computer.finish()),
+            Arc::new(self.original_file.finish()),
+            Arc::new(self.archive_name.finish()),
+            Arc::new(self.artifact_hash.finish()),
+            Arc::new(self.parser_version.finish()),
+        ]
+    }
+}
+
+///
+/// Write a topic to a parquet file:
+/// - the metadata fields are typed columns
+/// - the fields of the topic definition are promoted to typed columns
+/// - the remaining data, and the values that cannot be converted, are stored in the json 'data' column
+///
+/// The file is completed when the writer is flushed
+///
+pub struct ParquetWriter<W: Write + Send = File> {
+    writer: Option<ArrowWriter<W>>,
+    schema: SchemaRef,
+    metadata: MetadataBuilders,
+    columns: Vec<(String, ColumnBuilder)>,
+    data: StringBuilder,
+    // the tuples of the row group, kept until it is written
+    tuples: Vec<Tuple>,
+    row_group_size: usize,
+    rejected: Vec<Tuple>,
+}
+impl ParquetWriter {
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        partial_field_def: &[(String, DataType)],
+        row_group_size: usize,
+        compression: ParquetCompression,
+    ) -> Result<Self, Error> {
+        Self::from_writer(
+            File::create(path)?,
+            partial_field_def,
+            row_group_size,
+            compression,
+        )
+    }
+}
+impl<W: Write + Send> ParquetWriter<W> {
+    pub fn from_writer(
+        output: W,
+        partial_field_def: &[(String, DataType)],
+        row_group_size: usize,
+        compression: ParquetCompression,
+    ) -> Result<Self, Error> {
+        let mut fields = MetadataBuilders::fields();
+        let mut columns = Vec::with_capacity(partial_field_def.len());
+        for (name, data_type) in partial_field_def {
+            // the names of the metadata columns are reserved
+            if fields.iter().any(|field| field.name() == name) || name == FIELD_DATA {
+                continue;
+            }
+            let column = ColumnBuilder::new(data_type);
+            fields.push(Field::new(name, column.arrow_type(), true));
+            columns.push((name.to_owned(), column));
+        }
+        fields.push(Field::new(FIELD_DATA, ArrowType::Utf8, false));
+        let schema = Arc::new(Schema::new(fields));
+
+        let row_group_size = row_group_size.max(1);
+        let properties = WriterProperties::builder()
+            .set_compression(compression.codec())
+            .set_max_row_group_size(row_group_size)
+            .build();
+        let writer = ArrowWriter::try_new(output, schema.clone(), Some(properties))?;
+
+        Ok(Self {
+            writer: Some(writer),
+            schema,
+            metadata: MetadataBuilders::new(),
+            columns,
+            data: StringBuilder::new(),
+            tuples: Vec::new(),
+            row_group_size,
+            rejected: Vec::new(),
+        })
+    }
+
+    ///
+    /// Write the row group, its tuples are kept for take_rejected() when it cannot be written
+    ///
+    fn write_batch(&mut self) -> Result<(), Error> {
+        if self.tuples.is_empty() {
+            return Ok(());
+        }
+        let tuples = std::mem::take(&mut self.tuples);
+        let result = self.write_arrays();
+        if result.is_err() {
+            self.rejected.extend(tuples);
+        }
+        result
+    }
+
+    fn write_arrays(&mut self) -> Result<(), Error> {
+        let mut arrays = self.metadata.finish();
+        for (_, column) in &mut self.columns {
+            arrays.push(column.finish());
+        }
+        arrays.push(Arc::new(self.data.finish()));
+
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+        let writer = self.writer.as_mut().ok_or(Error::OutputFlush())?;
+        writer.write(&batch)?;
+        Ok(())
+    }
+
+    ///
+    /// The underlying writer, until the file is closed
+    ///
+    pub fn inner_mut(&mut self) -> Option<&mut W> {
+        self.writer.as_mut().map(ArrowWriter::inner_mut)
+    }
+
+    ///
+    /// Write the pending rows and the footer, returns the underlying writer the first time
+    ///
+    pub fn close(&mut self) -> Result<Option<W>, Error> {
+        self.write_batch()?;
+        match self.writer.take() {
+            Some(writer) => Ok(Some(writer.into_inner()?)),
+            None => Ok(None),
+        }
+    }
+}
+impl<W: Write + Send> OutputWriter for ParquetWriter<W> {
+    fn write(&mut self, data: Tuple) -> Result<(), Error> {
+        if self.writer.is_none() {
+            return Err(Error::Generic("the parquet file is closed".to_owned()));
+        }
+        self.metadata.append(&data);
+
+        let mut remaining = match &data.data {
+            Some(Value::Object(map)) => map.clone(),
+            _ => Map::new(),
+        };
+        for (name, column) in &mut self.columns {
+            if column.append(get_field(&remaining, name)) {
+                remove_field(&mut remaining, name);
+            }
+        }
+        self.data.append_value(serde_json::to_string(&remaining)?);
+
+        self.tuples.push(data);
+        if self.tuples.len() >= self.row_group_size {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        // the footer is written once, the file cannot be appended afterwards
+        self.close()?;
+        Ok(())
+    }
+
+    fn take_rejected(&mut self) -> Vec<Tuple> {
+        std::mem::take(&mut self.rejected)
+    }
+}
+
+///
+/// Parquet files of the archives being parsed, one per archive and topic
+/// The files of an archive are parsed by several threads that share the writer of each topic
+///
+#[derive(Clone, Default)]
+pub struct ParquetFiles(Arc<Mutex<HashMap<ArchiveTopic, Arc<Mutex<ParquetWriter>>>>>);
+
+///
+/// The archive name and the topic of a parquet file
+///
+type ArchiveTopic = (String, String);
+impl ParquetFiles {
+    ///
+    /// Writer of a topic for an archive, the first parsed file creates {folder}/{topic}/{archive}.parquet
+    ///
+    pub fn writer(
+        &self,
+        folder: &str,
+        archive_name: &str,
+        topic: &str,
+        partial_field_def: &[(String, DataType)],
+        row_group_size: usize,
+        compression: ParquetCompression,
+    ) -> Result<ArchiveParquetWriter, Error> {
+        let mut files = self.0.lock().map_err(|_| poisoned())?;
+        let key = (archive_name.to_owned(), topic.to_owned());
+        if let Some(writer) = files.get(&key) {
+            return Ok(ArchiveParquetWriter(writer.clone()));
+        }
+        let mut path = Path::new(folder).join(topic);
+        fs::create_dir_all(&path)?;
+        path.push(format!("{archive_name}.parquet"));
+        let writer = ParquetWriter::new(&path, partial_field_def, row_group_size, compression)?;
+        let writer = Arc::new(Mutex::new(writer));
+        files.insert(key, writer.clone());
+        Ok(ArchiveParquetWriter(writer))
+    }
+
+    ///
+    /// Write the footer of the parquet files of an archive, returns the first error
+    ///
+    pub fn complete(&self, archive_name: &str) -> Result<(), Error> {
+        let mut writers = Vec::new();
+        self.0
+            .lock()
+            .map_err(|_| poisoned())?
+            .retain(|(archive, _), writer| {
+                let keep = archive != archive_name;
+                if !keep {
+                    writers.push(writer.clone());
+                }
+                keep
+            });
+        let mut result = Ok(());
+        for writer in writers {
+            let closed = match writer.lock() {
+                Ok(mut writer) => writer.close().map(|_| ()),
+                Err(_) => Err(poisoned()),
+            };
+            if result.is_ok() {
+                result = closed;
+            }
+        }
+        result
+    }
+}
+impl fmt::Debug for ParquetFiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ParquetFiles")
+    }
+}
+
+fn poisoned() -> Error {
+    Error::Generic("a parquet writer panicked".to_owned())
+}
+
+///
+/// Write the rows of a parsed file to the parquet file of its archive
+/// The rows stay buffered until a row group is full or the archive is completed
+///
+pub struct ArchiveParquetWriter(Arc<Mutex<ParquetWriter>>);
+impl OutputWriter for ArchiveParquetWriter {
+    fn write(&mut self, data: Tuple) -> Result<(), Error> {
+        self.0.lock().map_err(|_| poisoned())?.write(data)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    ///
+    /// The row group can hold the rows of the other files of the archive
+    ///
+    fn take_rejected(&mut self) -> Vec<Tuple> {
+        self.0
+            .lock()
+            .map(|mut writer| writer.take_rejected())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{
+        Array,
+        cast::AsArray,
+        types::{Int64Type, TimestampMillisecondType},
+    };
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+
+    use crate::output::Fields;
+
+    use super::*;
+
+    #[test]
+    fn typed_columns() {
+        let folder = "data/temp/parquet_writer";
+        let _ = fs::remove_dir_all(folder);
+        fs::create_dir_all(folder).unwrap();
+        let path = format!("{folder}/evtx.parquet");
+
+        let field_def = vec![
+            ("EventID".to_owned(), DataType::Int64),
+            ("TimeCreated".to_owned(), DataType::Date),
+            ("System.Channel".to_owned(), DataType::String),
+            ("id".to_owned(), DataType::String),
+        ];
+        let mut writer =
+            ParquetWriter::new(&path, &field_def, 2, ParquetCompression::zstd).unwrap();
+
+        let fields = Fields::new("machine", "Security.evtx", "archive", "Security.evtx");
+        let rows = [
+            json!({"EventID": 4624, "TimeCreated": "2024-01-02 10:12:13.000", "System": {"Channel": "Security"}, "User": "bob"}),
+            json!({"EventID": "not a number", "System": {"Channel": "Security", "Level": 4}}),
+            json!({"EventID": 4625, "TimeCreated": "2024-01-02 10:12:14.500", "id": "kept in data"}),
+        ];
+        for row in rows {
+            let mut tuple = Tuple::new(&fields);
+            tuple.set_data(row, None).unwrap();
+            writer.write(tuple).unwrap();
+        }
+        writer.flush().unwrap();
+        // a second flush does nothing
+        writer.flush().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        // two row groups of 2 and 1 rows
+        assert_eq!(2, reader.metadata().num_row_groups());
+        let batches: Vec<RecordBatch> = reader
+            .with_batch_size(2)
+            .build()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(2, batches.len());
+        let batch = &batches[0];
+        let names: Vec<&str> = batch
+            .schema_ref()
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(
+            vec![
+                "id",
+                "import_date",
+                "computer",
+                "original_file",
+                "archive_name",
+                "artifact_hash",
+                "parser_version",
+                "EventID",
+                "TimeCreated",
+                "System.Channel",
+                "data"
+            ],
+            names
+        );
+
+        let event_id = batch.column(7).as_primitive::<Int64Type>();
+        assert_eq!(4624, event_id.value(0));
+        assert!(event_id.is_null(1));
+        let time = batch.column(8).as_primitive::<TimestampMillisecondType>();
+        assert_eq!(1_704_190_333_000, time.value(0));
+        assert_eq!("Security", batch.column(9).as_string::<i32>().value(1));
+
+        let data = batch.column(10).as_string::<i32>();
+        assert_eq!(r#"{"User":"bob"}"#, data.value(0));
+        // the value that cannot be converted is kept in the data column
+        assert_eq!(
+            r#"{"EventID":"not a number","System":{"Level":4}}"#,
+            data.value(1)
+        );
+
+        let data = batches[1].column(10).as_string::<i32>();
+        assert_eq!(r#"{"id":"kept in data"}"#, data.value(0));
+        assert_eq!("computer", batches[1].schema_ref().field(2).name());
+        assert_eq!("machine", batches[1].column(2).as_string::<i32>().value(0));
+        let _ = fs::remove_dir_all(folder);
+    }
+
+    #[test]
+    fn one_file_per_archive() {
+        let folder = "data/temp/parquet_archive";
+        let _ = fs::remove_dir_all(folder);
+        let files = ParquetFiles::default();
+        let field_def = vec![("EventID".to_owned(), DataType::Int64)];
+
+        for (archive, file) in [
+            ("archive1", "Security.evtx"),
+            ("archive1", "System.evtx"),
+            ("archive2", "Security.evtx"),
+        ] {
+            let fields = Fields::new("machine", file, archive, file);
+            let mut writer = files
+                .writer(
+                    folder,
+                    archive,
+                    "evtx",
+                    &field_def,
+                    100,
+                    ParquetCompression::snappy,
+                )
+                .unwrap();
+            let mut tuple = Tuple::new(&fields);
+            tuple.set_data(json!({"EventID": 4624}), None).unwrap();
+            writer.write(tuple).unwrap();
+            writer.flush().unwrap();
+        }
+        files.complete("archive1").unwrap();
+
+        let path = format!("{folder}/evtx/archive1.parquet");
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(2, reader.metadata().file_metadata().num_rows());
+        // the file of the other archive is completed with its archive
+        let path = format!("{folder}/evtx/archive2.parquet");
+        assert!(ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).is_err());
+        files.complete("archive2").unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(1, reader.metadata().file_metadata().num_rows());
+        let _ = fs::remove_dir_all(folder);
+    }
+}
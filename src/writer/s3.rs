@@ -0,0 +1,552 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufWriter, Write},
+    sync::{Arc, Mutex},
+};
+
+use chrono::Utc;
+use object_store::{ObjectStore, PutPayload, WriteMultipart, aws::AmazonS3Builder, path::Path};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    Error,
+    configuration::DataType,
+    output::{OUTPUT_DATE_FORMAT_UTC, OutputWriter, Tuple},
+    writer::parquet_writer::{DEFAULT_ROW_GROUP_SIZE, ParquetCompression, ParquetWriter},
+};
+
+///
+/// S3 requires at least 5 MiB for every part but the last one
+///
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+const DEFAULT_PART_SIZE: usize = 10 * 1024 * 1024;
+
+///
+/// Number of parts uploaded concurrently by a writer
+///
+const MAX_CONCURRENT_PARTS: usize = 4;
+
+const BUFFER_CAPACITY: usize = 1024 * 1024;
+
+///
+/// Object written when every file of an archive is processed
+///
+const MANIFEST_NAME: &str = "_manifest.json";
+
+///
+/// Format of the uploaded objects
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum S3Format {
+    #[default]
+    jsonl,
+    parquet,
+}
+impl S3Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            S3Format::jsonl => "jsonl",
+            S3Format::parquet => "parquet",
+        }
+    }
+}
+
+///
+/// An uploaded object, listed in the manifest of its archive
+///
+#[derive(Serialize, Clone, Debug)]
+pub struct S3Object {
+    key: String,
+    topic: String,
+    rows: usize,
+    bytes: u64,
+}
+
+///
+/// Objects uploaded for each archive, shared by the writers of every thread
+///
+#[derive(Clone, Debug, Default)]
+pub struct S3Uploads(Arc<Mutex<HashMap<String, Vec<S3Object>>>>);
+impl S3Uploads {
+    fn add(&self, archive_name: &str, object: S3Object) {
+        if let Ok(mut uploads) = self.0.lock() {
+            uploads
+                .entry(archive_name.to_owned())
+                .or_default()
+                .push(object);
+        }
+    }
+
+    fn take(&self, archive_name: &str) -> Vec<S3Object> {
+        self.0
+            .lock()
+            .ok()
+            .and_then(|mut uploads| uploads.remove(archive_name))
+            .unwrap_or_default()
+    }
+}
+
+///
+/// Options of the S3 output
+/// - endpoint: url of an S3 compatible storage, like http://localhost:9000 for MinIO
+/// - region, access_key_id, secret_access_key: read from the AWS_* environment variables when not set
+/// - prefix: added before the keys: {prefix}/{client_context}/{archive}/{topic}/{file}.jsonl
+/// - part_size: size of the multipart upload parts, 5 MiB minimum
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct S3Options {
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub format: S3Format,
+    #[serde(default)]
+    pub part_size: Option<usize>,
+    // field definition of each topic, set by prepare() before the parsing
+    #[serde(skip)]
+    pub topics: HashMap<String, Vec<(String, DataType)>>,
+    #[serde(skip)]
+    pub uploads: S3Uploads,
+    #[serde(skip)]
+    pub connection: S3Connection,
+}
+impl S3Options {
+    ///
+    /// Client of the bucket, built by the first writer and shared by the clones of the options
+    ///
+    pub fn client(&self, bucket: &str) -> Result<S3Client, Error> {
+        let mut connection = self
+            .connection
+            .0
+            .lock()
+            .map_err(|_| Error::Generic("the S3 connection is poisoned".to_owned()))?;
+        if let Some(client) = connection.as_ref() {
+            return Ok(client.clone());
+        }
+        let client = S3Client::new(self.store(bucket)?)?;
+        Ok(connection.insert(client).clone())
+    }
+
+    fn store(&self, bucket: &str) -> Result<Arc<dyn ObjectStore>, Error> {
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+        if let Some(endpoint) = &self.endpoint {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        if let Some(region) = &self.region {
+            builder = builder.with_region(region);
+        }
+        if let Some(access_key_id) = &self.access_key_id {
+            builder = builder.with_access_key_id(access_key_id);
+        }
+        if let Some(secret_access_key) = &self.secret_access_key {
+            builder = builder.with_secret_access_key(secret_access_key);
+        }
+        Ok(Arc::new(builder.build()?))
+    }
+
+    ///
+    /// Folder of the objects of an archive: {prefix}/{client_context}/{archive}
+    ///
+    fn archive_key(&self, client_context: &str, archive_name: &str) -> String {
+        match self
+            .prefix
+            .as_deref()
+            .map(|prefix| prefix.trim_matches('/'))
+        {
+            Some(prefix) if !prefix.is_empty() => {
+                format!("{prefix}/{client_context}/{archive_name}")
+            }
+            _ => format!("{client_context}/{archive_name}"),
+        }
+    }
+
+    pub fn object_key(
+        &self,
+        client_context: &str,
+        archive_name: &str,
+        topic: &str,
+        file_name: &str,
+    ) -> String {
+        format!(
+            "{}/{topic}/{file_name}.{}",
+            self.archive_key(client_context, archive_name),
+            self.format.extension()
+        )
+    }
+
+    ///
+    /// Write the manifest listing the objects uploaded for the archive
+    /// Downstream jobs can consider the archive complete once the manifest exists
+    ///
+    pub fn complete_archive(
+        &self,
+        client: &S3Client,
+        client_context: &str,
+        archive_name: &str,
+        num_errors: usize,
+    ) -> Result<(), Error> {
+        let mut objects = self.uploads.take(archive_name);
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        let num_rows: usize = objects.iter().map(|object| object.rows).sum();
+        let manifest = json!({
+            "client_context": client_context,
+            "archive_name": archive_name,
+            "completed_at": Utc::now().format(OUTPUT_DATE_FORMAT_UTC).to_string(),
+            "num_errors": num_errors,
+            "num_rows": num_rows,
+            "objects": objects,
+        });
+        let key = format!(
+            "{}/{MANIFEST_NAME}",
+            self.archive_key(client_context, archive_name)
+        );
+        let payload = PutPayload::from(serde_json::to_vec_pretty(&manifest)?);
+        client
+            .runtime
+            .block_on(client.store.put(&Path::from(key.as_str()), payload))?;
+        Ok(())
+    }
+}
+
+///
+/// Store and runtime used by the writers of an output
+///
+#[derive(Clone)]
+pub struct S3Client {
+    store: Arc<dyn ObjectStore>,
+    runtime: Arc<Runtime>,
+}
+impl S3Client {
+    pub fn new(store: Arc<dyn ObjectStore>) -> Result<Self, Error> {
+        Ok(Self {
+            store,
+            runtime: Arc::new(runtime()?),
+        })
+    }
+}
+
+///
+/// Client of an output, shared by the writers of every thread
+///
+#[derive(Clone, Default)]
+pub struct S3Connection(Arc<Mutex<Option<S3Client>>>);
+impl fmt::Debug for S3Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("S3Connection")
+    }
+}
+
+fn runtime() -> Result<Runtime, Error> {
+    Ok(Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()?)
+}
+
+///
+/// Blocking adapter streaming the bytes to a multipart upload
+///
+pub struct S3Upload {
+    runtime: Arc<Runtime>,
+    upload: Option<WriteMultipart>,
+    bytes: u64,
+}
+impl S3Upload {
+    fn new(client: &S3Client, key: &str, part_size: usize) -> Result<Self, Error> {
+        let upload = client
+            .runtime
+            .block_on(client.store.put_multipart(&Path::from(key)))?;
+        Ok(Self {
+            runtime: client.runtime.clone(),
+            upload: Some(WriteMultipart::new_with_chunk_size(
+                upload,
+                part_size.max(MIN_PART_SIZE),
+            )),
+            bytes: 0,
+        })
+    }
+
+    ///
+    /// Upload the last part and complete the object, returns its size
+    ///
+    fn finish(&mut self) -> Result<u64, Error> {
+        if let Some(upload) = self.upload.take() {
+            self.runtime.block_on(upload.finish())?;
+        }
+        Ok(self.bytes)
+    }
+
+    ///
+    /// Cancel the upload, the parts already uploaded are deleted
+    ///
+    fn abort(&mut self) -> Result<(), Error> {
+        if let Some(upload) = self.upload.take() {
+            self.runtime.block_on(upload.abort())?;
+        }
+        Ok(())
+    }
+}
+impl Write for S3Upload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let upload = self
+            .upload
+            .as_mut()
+            .ok_or_else(|| io::Error::other("the upload is completed"))?;
+        // the parts are uploaded by tasks of the runtime, limiting the memory used
+        self.runtime
+            .block_on(async {
+                upload.wait_for_capacity(MAX_CONCURRENT_PARTS).await?;
+                upload.write(buf);
+                Ok::<(), object_store::Error>(())
+            })
+            .map_err(io::Error::other)?;
+        self.bytes += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum S3Body {
+    Jsonl(BufWriter<S3Upload>),
+    Parquet(Box<ParquetWriter<S3Upload>>),
+}
+
+///
+/// Upload the output of a file to an S3 compatible storage
+/// The object is completed when the writer is flushed and recorded for the manifest of the archive
+///
+pub struct S3Writer {
+    body: Option<S3Body>,
+    key: String,
+    topic: String,
+    archive_name: String,
+    rows: usize,
+    uploads: S3Uploads,
+}
+impl S3Writer {
+    pub fn new(
+        client: &S3Client,
+        options: &S3Options,
+        client_context: &str,
+        archive_name: &str,
+        file_name: &str,
+        topic: &str,
+    ) -> Result<Self, Error> {
+        let key = options.object_key(client_context, archive_name, topic, file_name);
+        let upload = S3Upload::new(client, &key, options.part_size.unwrap_or(DEFAULT_PART_SIZE))?;
+        let body = match options.format {
+            S3Format::jsonl => S3Body::Jsonl(BufWriter::with_capacity(BUFFER_CAPACITY, upload)),
+            S3Format::parquet => {
+                // unknown topics are written with the data column only
+                let partial_field_def = options.topics.get(topic).map_or(&[][..], Vec::as_slice);
+                S3Body::Parquet(Box::new(ParquetWriter::from_writer(
+                    upload,
+                    partial_field_def,
+                    DEFAULT_ROW_GROUP_SIZE,
+                    ParquetCompression::default(),
+                )?))
+            }
+        };
+        Ok(Self {
+            body: Some(body),
+            key,
+            topic: topic.to_owned(),
+            archive_name: archive_name.to_owned(),
+            rows: 0,
+            uploads: options.uploads.clone(),
+        })
+    }
+}
+impl OutputWriter for S3Writer {
+    fn write(&mut self, data: Tuple) -> Result<(), Error> {
+        match &mut self.body {
+            Some(S3Body::Jsonl(writer)) => {
+                let line = data.to_json_string()?;
+                writer.write_all(line.as_bytes())?;
+                writer.write_all("\n".as_bytes())?;
+            }
+            Some(S3Body::Parquet(writer)) => writer.write(data)?,
+            None => return Err(Error::Generic(format!("'{}' is uploaded", self.key))),
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        // the object is completed once, flushing again does nothing
+        let mut upload = match self.body.take() {
+            Some(S3Body::Jsonl(writer)) => writer.into_inner().map_err(|e| e.into_error())?,
+            Some(S3Body::Parquet(mut writer)) => writer.close()?.ok_or(Error::OutputFlush())?,
+            None => return Ok(()),
+        };
+        let bytes = upload.finish()?;
+        self.uploads.add(
+            &self.archive_name,
+            S3Object {
+                key: self.key.clone(),
+                topic: self.topic.clone(),
+                rows: self.rows,
+                bytes,
+            },
+        );
+        Ok(())
+    }
+
+    ///
+    /// The object is not completed and not listed in the manifest
+    ///
+    fn abort(&mut self) -> Result<(), Error> {
+        match self.body.take() {
+            Some(S3Body::Jsonl(writer)) => {
+                // the buffered bytes are dropped without being uploaded
+                let (mut upload, _) = writer.into_parts();
+                upload.abort()
+            }
+            Some(S3Body::Parquet(mut writer)) => writer.inner_mut().map_or(Ok(()), S3Upload::abort),
+            None => Ok(()),
+        }
+    }
+
+    ///
+    /// The rows of a parquet row group that could not be written
+    /// The rows already uploaded are lost with the object, the failed flush reports it
+    ///
+    fn take_rejected(&mut self) -> Vec<Tuple> {
+        match &mut self.body {
+            Some(S3Body::Parquet(writer)) => writer.take_rejected(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+    use serde_json::{Value, json};
+
+    use crate::output::Fields;
+
+    use super::*;
+
+    fn read(client: &S3Client, key: &str) -> Option<Vec<u8>> {
+        client.runtime.block_on(async {
+            let object = client.store.get(&Path::from(key)).await.ok()?;
+            Some(object.bytes().await.unwrap().to_vec())
+        })
+    }
+
+    #[test]
+    fn upload() {
+        let client = S3Client::new(Arc::new(InMemory::new())).unwrap();
+        let options = S3Options {
+            prefix: Some("/handoff/".to_owned()),
+            ..Default::default()
+        };
+        let fields = Fields::new("machine", "Security.evtx", "archive", "Security.evtx");
+        let mut writer = S3Writer::new(
+            &client,
+            &options,
+            "case42",
+            "archive",
+            "Security.evtx",
+            "evtx",
+        )
+        .unwrap();
+        for i in 0..3 {
+            let mut tuple = Tuple::new(&fields);
+            tuple.set_data(json!({"EventID": i}), None).unwrap();
+            writer.write(tuple).unwrap();
+        }
+        writer.flush().unwrap();
+        writer.flush().unwrap();
+
+        let key = "handoff/case42/archive/evtx/Security.evtx.jsonl";
+        let content = String::from_utf8(read(&client, key).unwrap()).unwrap();
+        assert_eq!(3, content.lines().count());
+
+        // the uploads are shared by the clones of the options
+        let parquet_options = S3Options {
+            format: S3Format::parquet,
+            ..options.clone()
+        };
+        let mut writer = S3Writer::new(
+            &client,
+            &parquet_options,
+            "case42",
+            "archive",
+            "Security.evtx.parse_errors",
+            "parse_errors",
+        )
+        .unwrap();
+        writer.flush().unwrap();
+        let parquet = read(
+            &client,
+            "handoff/case42/archive/parse_errors/Security.evtx.parse_errors.parquet",
+        )
+        .unwrap();
+        assert_eq!(b"PAR1", &parquet[parquet.len() - 4..]);
+
+        // an aborted upload is not completed nor listed in the manifest
+        for options in [&options, &parquet_options] {
+            let mut writer =
+                S3Writer::new(&client, options, "case42", "archive", "System.evtx", "evtx")
+                    .unwrap();
+            let mut tuple = Tuple::new(&fields);
+            tuple.set_data(json!({"EventID": 7036}), None).unwrap();
+            writer.write(tuple).unwrap();
+            writer.abort().unwrap();
+            writer.abort().unwrap();
+        }
+        assert!(read(&client, "handoff/case42/archive/evtx/System.evtx.jsonl").is_none());
+        assert!(read(&client, "handoff/case42/archive/evtx/System.evtx.parquet").is_none());
+
+        options
+            .complete_archive(&client, "case42", "archive", 0)
+            .unwrap();
+        let manifest: Value = serde_json::from_slice(
+            &read(&client, "handoff/case42/archive/_manifest.json").unwrap(),
+        )
+        .unwrap();
+        assert_eq!("archive", manifest["archive_name"]);
+        assert_eq!(3, manifest["num_rows"]);
+        let objects = manifest["objects"].as_array().unwrap();
+        assert_eq!(2, objects.len());
+        assert_eq!(key, objects[0]["key"]);
+        assert_eq!(3, objects[0]["rows"]);
+        assert_eq!(content.len() as u64, objects[0]["bytes"]);
+        assert_eq!(0, objects[1]["rows"]);
+
+        // the uploads of the archive are listed once
+        assert!(options.uploads.take("archive").is_empty());
+    }
+
+    #[test]
+    fn shared_client() {
+        let options = S3Options {
+            endpoint: Some("http://localhost:9000".to_owned()),
+            region: Some("us-east-1".to_owned()),
+            ..Default::default()
+        };
+        let client = options.client("bucket").unwrap();
+        // the clones of the options share the store and the runtime
+        let other = options.clone().client("bucket").unwrap();
+        assert!(Arc::ptr_eq(&client.store, &other.store));
+        assert!(Arc::ptr_eq(&client.runtime, &other.runtime));
+    }
+}